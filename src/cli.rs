@@ -0,0 +1,442 @@
+use std::path::PathBuf;
+
+use anyhow::{bail, Context as AnyhowContextTrait, Result};
+use clap::{Args, Parser, Subcommand};
+
+use crate::log::LogLevel;
+use crate::opengl::capture::{CaptureFormat, CaptureSettings};
+use crate::opengl::color::ColorMode;
+use crate::opengl::shader::SHADER_SOURCE_DIR;
+use crate::options::{Mode, Options, DEFAULT_HEADLESS_FRAMES, DEFAULT_SNAPSHOT_PATH};
+use crate::simulation::boundary::{Boundary, BoundaryKind};
+use crate::simulation::initial::InitialCondition;
+use crate::simulation::integrator::Integrator;
+use crate::simulation::{BackendKind, ForceModel};
+
+/// Interactive 2D particle simulation on the GPU.
+///
+/// Without a subcommand, the arguments are those of `run`.
+#[derive(Debug, Parser)]
+#[command(name = "ogl", version, args_conflicts_with_subcommands = true)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    #[command(flatten)]
+    pub run: RunArgs,
+    /// How much to print: `error`, `warn`, `info` or `debug`. OpenGL errors are always shown, `debug` also shows its other debug messages.
+    #[arg(long, global = true, value_name = "LEVEL", default_value_t = LogLevel::Info)]
+    pub log_level: LogLevel,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Open a window and run the simulation. The default.
+    Run(RunArgs),
+    /// Render frames offscreen and write them to a directory.
+    Render(RenderArgs),
+    /// Time Barnes-Hut against brute-force gravity on the CPU.
+    Bench(BenchArgs),
+    /// Compare the energy and momentum drift of the integrators on the CPU.
+    Drift(DriftArgs),
+    /// Compile and link the shaders offscreen and report any errors.
+    ValidateShaders(ShaderArgs),
+}
+
+/// The simulation settings. All of them but `--scene`, `--backend`, `--load` and `--shader-dir` are also keys
+/// of the scene file, and the command line wins over the file.
+#[derive(Debug, Clone, Default, Args)]
+pub struct SceneArgs {
+    /// Scene file to take the settings from. Edits to it apply while the program runs.
+    #[arg(long, visible_alias = "config", value_name = "FILE")]
+    pub scene: Option<PathBuf>,
+    /// Simulation backend: `cpu` or `gpu`.
+    #[arg(long)]
+    pub backend: Option<BackendKind>,
+    /// Seed for the particle RNG, drawn from the OS when absent.
+    #[arg(long)]
+    pub seed: Option<u64>,
+    /// Number of particles.
+    #[arg(long, value_name = "COUNT")]
+    pub particles: Option<usize>,
+    /// Particles alive at the start, the rest are free slots for the emitters.
+    #[arg(long, value_name = "COUNT")]
+    pub initial_particles: Option<usize>,
+    /// Initial layout of the particles, like `galaxy:radius=0.8`.
+    #[arg(long, value_name = "CONDITION")]
+    pub init: Option<InitialCondition>,
+    /// Start from this snapshot instead of the initial condition.
+    #[arg(long, value_name = "FILE")]
+    pub load: Option<PathBuf>,
+    /// `mouse`, `nbody` or `barnes-hut`.
+    #[arg(long, value_name = "MODEL")]
+    pub force_model: Option<ForceModel>,
+    /// Gravitational constant.
+    #[arg(long, value_name = "G")]
+    pub gravity: Option<f32>,
+    /// Softening length of the gravity.
+    #[arg(long, value_name = "LENGTH")]
+    pub softening: Option<f32>,
+    /// Barnes-Hut opening angle, smaller is more accurate and slower.
+    #[arg(long)]
+    pub theta: Option<f32>,
+    /// `euler`, `verlet`, `leapfrog` or `rk4`.
+    #[arg(long)]
+    pub integrator: Option<Integrator>,
+    /// `none`, `reflect`, `periodic`, `absorb` or `contain`.
+    #[arg(long)]
+    pub boundary: Option<BoundaryKind>,
+    /// Simulation domain as `min_x,min_y,max_x,max_y`.
+    #[arg(long, value_name = "BOX", value_parser = Boundary::parse_domain, allow_hyphen_values = true)]
+    pub domain: Option<((f32, f32), (f32, f32))>,
+    /// Velocity kept when bouncing off a reflective wall, 0 to 1.
+    #[arg(long)]
+    pub restitution: Option<f32>,
+    /// Spring constant of the soft containment boundary.
+    #[arg(long, value_name = "STRENGTH")]
+    pub contain_strength: Option<f32>,
+    /// Step by this many simulated seconds per frame instead of the wall-clock time.
+    #[arg(long, value_name = "SECONDS")]
+    pub fixed_dt: Option<f32>,
+    /// Simulated seconds per real second, 0.1 to 10.
+    #[arg(long, value_name = "FACTOR")]
+    pub time_scale: Option<f32>,
+    /// Longest wall-clock step per frame.
+    #[arg(long, value_name = "SECONDS")]
+    pub max_dt: Option<f32>,
+    /// Frames advanced by the "step many" key.
+    #[arg(long, value_name = "COUNT")]
+    pub step_frames: Option<u32>,
+    /// Integrate in substeps of this many simulated seconds.
+    #[arg(long, value_name = "SECONDS")]
+    pub substep_dt: Option<f32>,
+    /// Most substeps per frame.
+    #[arg(long, value_name = "COUNT")]
+    pub max_substeps: Option<u32>,
+    /// Draw positions interpolated between the last two substeps, needs `--substep-dt`: `on` or `off`,
+    /// `on` when given without a value.
+    #[arg(long, value_name = "SWITCH", value_parser = parse_switch, num_args = 0..=1, default_missing_value = "on")]
+    pub interpolate: Option<bool>,
+    /// `solid`, `speed`, `acceleration`, `heading` or `index`.
+    #[arg(long, value_name = "MODE")]
+    pub color_mode: Option<ColorMode>,
+    /// Text file of color stops for the color modes.
+    #[arg(long, value_name = "FILE")]
+    pub gradient: Option<PathBuf>,
+    /// Speed or acceleration at the end of the gradient.
+    #[arg(long, value_name = "RANGE")]
+    pub color_range: Option<f32>,
+    /// Side length of a particle's quad in world units.
+    #[arg(long, value_name = "LENGTH")]
+    pub quad_size: Option<f32>,
+    /// CSV file to log energy, momentum and velocity histograms to.
+    #[arg(long, value_name = "FILE")]
+    pub diagnostics: Option<PathBuf>,
+    /// Simulation frames between two rows of the diagnostics log.
+    #[arg(long, value_name = "FRAMES")]
+    pub diagnostics_every: Option<u32>,
+    /// Bins per velocity histogram in the diagnostics log.
+    #[arg(long, value_name = "COUNT")]
+    pub histogram_bins: Option<usize>,
+    /// Load the shaders from this directory instead of the built-in sources.
+    #[arg(long, value_name = "DIR")]
+    pub shader_dir: Option<PathBuf>,
+}
+
+/// What the window or offscreen framebuffer shows at the start.
+#[derive(Debug, Clone, Default, Args)]
+pub struct ViewArgs {
+    /// Window or framebuffer size as `WIDTHxHEIGHT`.
+    #[arg(long, value_name = "SIZE", value_parser = parse_window_size)]
+    pub window_size: Option<(u32, u32)>,
+    /// Fit the view to the particles at the start: `on` or `off`, `on` when given without a value.
+    #[arg(long, value_name = "SWITCH", value_parser = parse_switch, num_args = 0..=1, default_missing_value = "on")]
+    pub fit_view: Option<bool>,
+}
+
+#[derive(Debug, Clone, Default, Args)]
+pub struct CaptureArgs {
+    /// Image format of captured frames: `ppm` or `png`.
+    #[arg(long, value_name = "FORMAT", default_value_t = CaptureFormat::default())]
+    pub capture_format: CaptureFormat,
+    /// Capture every n-th frame.
+    #[arg(long, value_name = "N", default_value_t = 1)]
+    pub capture_stride: u32,
+    /// Stop capturing after this many images.
+    #[arg(long, value_name = "COUNT")]
+    pub capture_max: Option<u32>,
+    /// Keep the rows bottom-up, as OpenGL returns them.
+    #[arg(long)]
+    pub capture_no_flip: bool,
+}
+
+#[derive(Debug, Clone, Default, Args)]
+pub struct RunArgs {
+    #[command(flatten)]
+    pub scene: SceneArgs,
+    #[command(flatten)]
+    pub view: ViewArgs,
+    /// Render offscreen instead of opening a window, like `render`.
+    #[arg(long)]
+    pub headless: bool,
+    /// Frames to render with `--headless`.
+    #[arg(long, value_name = "COUNT", default_value_t = DEFAULT_HEADLESS_FRAMES)]
+    pub frames: u32,
+    /// Open the window fullscreen on the primary monitor, at the monitor's resolution: `on` or `off`, `on`
+    /// when given without a value.
+    #[arg(long, value_name = "SWITCH", value_parser = parse_switch, num_args = 0..=1, default_missing_value = "on")]
+    pub fullscreen: Option<bool>,
+    /// Wait for the display's refresh between frames: `on` or `off`.
+    #[arg(long, value_name = "SWITCH", value_parser = parse_switch)]
+    pub vsync: Option<bool>,
+    /// Keymap file overriding the default key bindings.
+    #[arg(long, value_name = "FILE")]
+    pub keymap: Option<PathBuf>,
+    /// Snapshot file the save and restore keys use.
+    #[arg(long, value_name = "FILE", default_value = DEFAULT_SNAPSHOT_PATH)]
+    pub snapshot: PathBuf,
+    /// Recompile the shaders when they change, from `--shader-dir` or the source tree.
+    #[arg(long)]
+    pub hot_reload_shaders: bool,
+    /// Write the rendered frames to this directory.
+    #[arg(long, value_name = "DIR")]
+    pub capture_dir: Option<PathBuf>,
+    #[command(flatten)]
+    pub capture: CaptureArgs,
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct RenderArgs {
+    #[command(flatten)]
+    pub scene: SceneArgs,
+    #[command(flatten)]
+    pub view: ViewArgs,
+    /// Frames to render.
+    #[arg(long, value_name = "COUNT", default_value_t = DEFAULT_HEADLESS_FRAMES)]
+    pub frames: u32,
+    /// Directory to write the frames to.
+    #[arg(long, short, value_name = "DIR")]
+    pub capture_dir: PathBuf,
+    #[command(flatten)]
+    pub capture: CaptureArgs,
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct BenchArgs {
+    #[command(flatten)]
+    pub scene: SceneArgs,
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct DriftArgs {
+    #[command(flatten)]
+    pub scene: SceneArgs,
+    /// Steps to run each integrator for.
+    #[arg(long, value_name = "COUNT")]
+    pub steps: Option<u32>,
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct ShaderArgs {
+    /// Directory to load the shaders from instead of the built-in sources.
+    #[arg(long, value_name = "DIR")]
+    pub shader_dir: Option<PathBuf>,
+}
+
+impl Cli {
+    /// The options the command line asks for, before the scene file is applied.
+    pub fn into_options(self) -> Options {
+        let options = match self.command.unwrap_or(Command::Run(self.run)) {
+            Command::Run(args) => args.into_options(),
+            Command::Render(args) => args.into_options(),
+            Command::Bench(args) => Options {
+                mode: Mode::Bench,
+                ..args.scene.into_options()
+            },
+            Command::Drift(args) => Options {
+                mode: Mode::Drift,
+                drift_steps: args.steps,
+                ..args.scene.into_options()
+            },
+            Command::ValidateShaders(args) => Options {
+                mode: Mode::ValidateShaders,
+                shader_dir: args.shader_dir,
+                ..Options::default()
+            },
+        };
+
+        Options {
+            log_level: self.log_level,
+            ..options
+        }
+    }
+}
+
+impl SceneArgs {
+    fn into_options(self) -> Options {
+        Options {
+            config: self.scene,
+            backend: self.backend.unwrap_or_default(),
+            seed: self.seed,
+            particle_count: self.particles,
+            initial_particles: self.initial_particles,
+            initial: self.init,
+            load_snapshot: self.load,
+            force_model: self.force_model,
+            gravity: self.gravity,
+            softening: self.softening,
+            theta: self.theta,
+            integrator: self.integrator,
+            boundary: self.boundary,
+            domain: self.domain,
+            restitution: self.restitution,
+            contain_strength: self.contain_strength,
+            fixed_dt: self.fixed_dt,
+            time_scale: self.time_scale,
+            max_dt: self.max_dt,
+            step_frames: self.step_frames,
+            substep_dt: self.substep_dt,
+            max_substeps: self.max_substeps,
+            interpolate: self.interpolate,
+            color_mode: self.color_mode,
+            gradient: self.gradient,
+            color_range: self.color_range,
+            quad_size: self.quad_size,
+            diagnostics: self.diagnostics,
+            diagnostics_every: self.diagnostics_every,
+            histogram_bins: self.histogram_bins,
+            shader_dir: self.shader_dir,
+            ..Options::default()
+        }
+    }
+}
+
+impl ViewArgs {
+    fn apply(self, options: &mut Options) {
+        options.width = self.window_size.map(|(w, _)| w);
+        options.height = self.window_size.map(|(_, h)| h);
+        options.fit_view = self.fit_view;
+    }
+}
+
+impl CaptureArgs {
+    fn settings(self, dir: PathBuf) -> CaptureSettings {
+        CaptureSettings {
+            format: self.capture_format,
+            stride: self.capture_stride,
+            max_frames: self.capture_max,
+            flip: !self.capture_no_flip,
+            ..CaptureSettings::new(dir)
+        }
+    }
+}
+
+impl RunArgs {
+    fn into_options(self) -> Options {
+        let mut options = Options {
+            headless: self.headless,
+            frames: self.frames,
+            fullscreen: self.fullscreen,
+            vsync: self.vsync,
+            keymap: self.keymap,
+            snapshot_path: self.snapshot,
+            capture: self.capture_dir.map(|dir| self.capture.settings(dir)),
+            ..self.scene.into_options()
+        };
+        if self.hot_reload_shaders {
+            options.shader_dir.get_or_insert_with(|| PathBuf::from(SHADER_SOURCE_DIR));
+        }
+        self.view.apply(&mut options);
+        options
+    }
+}
+
+impl RenderArgs {
+    fn into_options(self) -> Options {
+        let mut options = Options {
+            mode: Mode::Render,
+            headless: true,
+            frames: self.frames,
+            capture: Some(self.capture.settings(self.capture_dir)),
+            ..self.scene.into_options()
+        };
+        self.view.apply(&mut options);
+        options
+    }
+}
+
+fn parse_window_size(s: &str) -> Result<(u32, u32)> {
+    let (width, height) = s
+        .split_once('x')
+        .with_context(|| format!("window size `{}` must be WIDTHxHEIGHT", s))?;
+    let width = width.parse().with_context(|| format!("invalid window width `{}`", width))?;
+    let height = height.parse().with_context(|| format!("invalid window height `{}`", height))?;
+    Ok((width, height))
+}
+
+fn parse_switch(s: &str) -> Result<bool> {
+    match s {
+        "on" => Ok(true),
+        "off" => Ok(false),
+        _ => bail!("expected `on` or `off`, found `{}`", s),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::iter;
+
+    use super::*;
+    use clap::CommandFactory;
+
+    fn options(args: &[&str]) -> Options {
+        let cli = Cli::try_parse_from(iter::once(&"ogl").chain(args)).expect("the arguments should parse");
+        cli.into_options()
+    }
+
+    #[test]
+    fn command_is_well_formed() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn no_subcommand_runs() {
+        let options = options(&["--scene", "x.toml"]);
+        assert_eq!(options.mode, Mode::Run, "no subcommand should mean `run`");
+        assert_eq!(options.config, Some(PathBuf::from("x.toml")), "--scene should be kept");
+    }
+
+    #[test]
+    fn render_requires_a_capture_dir() {
+        assert!(Cli::try_parse_from(["ogl", "render"]).is_err(), "render without --capture-dir should fail");
+
+        let options = options(&["render", "--capture-dir", "frames"]);
+        assert_eq!(options.mode, Mode::Render, "render should select Mode::Render");
+        assert!(options.headless, "render should be headless");
+        assert!(options.capture.is_some(), "render should capture");
+    }
+
+    #[test]
+    fn switches_take_on_and_off() {
+        assert_eq!(options(&["--vsync", "off"]).vsync, Some(false), "--vsync off");
+        assert_eq!(options(&["--vsync", "on"]).vsync, Some(true), "--vsync on");
+        assert!(Cli::try_parse_from(["ogl", "--vsync"]).is_err(), "--vsync needs a value");
+        assert!(Cli::try_parse_from(["ogl", "--vsync", "yes"]).is_err(), "--vsync takes only on or off");
+
+        assert_eq!(options(&["--interpolate", "off"]).interpolate, Some(false), "--interpolate off");
+        assert_eq!(options(&["--interpolate"]).interpolate, Some(true), "bare --interpolate");
+        assert_eq!(options(&["--fullscreen"]).fullscreen, Some(true), "bare --fullscreen");
+        assert_eq!(options(&["--fit-view", "off"]).fit_view, Some(false), "--fit-view off");
+        assert_eq!(options(&[]).interpolate, None, "an absent switch should leave the scene file's value");
+    }
+
+    #[test]
+    fn flags_map_to_options() {
+        let options = options(&["--particles", "500", "--softening", "0.25", "--window-size", "800x600", "--log-level", "debug"]);
+        assert_eq!(options.particle_count, Some(500), "--particles");
+        assert_eq!(options.softening, Some(0.25), "--softening");
+        assert_eq!((options.width, options.height), (Some(800), Some(600)), "--window-size");
+        assert_eq!(options.log_level, LogLevel::Debug, "--log-level");
+
+        assert!(Cli::try_parse_from(["ogl", "--window-size", "800"]).is_err(), "--window-size needs WIDTHxHEIGHT");
+    }
+}
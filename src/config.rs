@@ -0,0 +1,289 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context as AnyhowContextTrait, Result};
+use serde::Deserialize;
+
+use crate::opengl::color::ColorMode;
+use crate::options::Options;
+use crate::simulation::boundary::BoundaryKind;
+use crate::simulation::emitter::Emitter;
+use crate::simulation::initial::InitialCondition;
+use crate::simulation::integrator::Integrator;
+use crate::simulation::ForceModel;
+
+/// Settings read from the TOML scene file given with `--scene`. Command-line options take precedence.
+///
+/// Every key is optional, so a file can describe a whole scene or just tweak a few settings. See the
+/// `scenes` directory for examples.
+///
+/// Relative paths in the file, `gradient`, `keymap`, `diagnostics` and the image of an `[initial]` table,
+/// are taken relative to the file's directory rather than the working directory, so a scene runs the same
+/// from anywhere. The same paths given on the command line stay relative to the working directory.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Seed for the particle RNG. A random one is drawn from the OS when absent.
+    pub seed: Option<u64>,
+    /// Advance the simulation by this many seconds every frame instead of the wall-clock delta.
+    pub fixed_dt: Option<f32>,
+    /// Simulated seconds per real second, 0.1 to 10. Editing this while the program runs applies live.
+    pub time_scale: Option<f32>,
+    /// Longest wall-clock step taken in one frame.
+    pub max_dt: Option<f32>,
+    /// Frames advanced by the "step many" key.
+    pub step_frames: Option<u32>,
+    /// Integrate in fixed substeps of this many simulated seconds, several per frame if needed.
+    pub substep_dt: Option<f32>,
+    /// Most substeps run in one frame, leftover time beyond that is dropped.
+    pub max_substeps: Option<u32>,
+    /// Draw positions interpolated between the last two substeps, needs `substep_dt`.
+    pub interpolate: Option<bool>,
+    /// `[window]` table, read once at startup.
+    pub window: Option<WindowConfig>,
+    /// `[camera]` table, where the view starts.
+    pub camera: Option<CameraConfig>,
+    /// Side length of a particle's quad in world units. Editing this while the program runs applies live.
+    pub quad_size: Option<f32>,
+    /// Background color as `[r, g, b]` between 0 and 1. Editing this while the program runs applies live.
+    pub clear_color: Option<[f32; 3]>,
+    /// Number of particles. Editing this while the program runs resizes the particle buffers.
+    pub particle_count: Option<usize>,
+    /// Particles alive at the start, the rest are free slots for the emitters. Defaults to all of them
+    /// without emitters and to none with.
+    pub initial_particles: Option<usize>,
+    /// `[[emitters]]` tables, see [`Emitter`]. Editing these while the program runs applies live.
+    pub emitters: Option<Vec<Emitter>>,
+    /// `[initial]` table with a `kind` and that generator's parameters, see [`InitialCondition`]. Editing
+    /// this while the program runs applies on the next reset.
+    pub initial: Option<InitialCondition>,
+    pub force_model: Option<ForceModel>,
+    /// Gravitational constant.
+    pub gravity: Option<f32>,
+    /// Softening length for the gravity calculations.
+    pub softening: Option<f32>,
+    /// Barnes-Hut opening angle, smaller is more accurate and slower.
+    pub theta: Option<f32>,
+    /// `euler`, `verlet`, `leapfrog` or `rk4`. Editing this while the program runs applies live.
+    pub integrator: Option<Integrator>,
+    /// `none`, `reflect`, `periodic`, `absorb` or `contain`. Editing this while the program runs applies live.
+    pub boundary: Option<BoundaryKind>,
+    /// Simulation domain as `[min_x, min_y, max_x, max_y]`.
+    pub domain: Option<[f32; 4]>,
+    /// Velocity kept when bouncing off a reflective wall, 0 to 1.
+    pub restitution: Option<f32>,
+    /// Spring constant of the soft containment boundary.
+    pub contain_strength: Option<f32>,
+    pub color_mode: Option<ColorMode>,
+    /// Text file of gradient color stops, read once at startup. Relative to the scene file's directory.
+    pub gradient: Option<PathBuf>,
+    /// Speed or acceleration magnitude at the end of the gradient.
+    pub color_range: Option<f32>,
+    /// Keymap file overriding the default key bindings, read once at startup. Relative to the scene file's
+    /// directory.
+    pub keymap: Option<PathBuf>,
+    /// CSV file to log energy, momentum and velocity histograms to. Relative to the scene file's directory.
+    pub diagnostics: Option<PathBuf>,
+    /// Simulation frames between two rows of the diagnostics log.
+    pub diagnostics_every: Option<u32>,
+    /// Bins per velocity histogram in the diagnostics log.
+    pub histogram_bins: Option<usize>,
+}
+
+/// The `[window]` table.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WindowConfig {
+    /// Also the size of the headless framebuffer.
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub title: Option<String>,
+    /// Open on the primary monitor at its resolution, ignoring the size.
+    pub fullscreen: Option<bool>,
+    /// Wait for the display's refresh between frames, on by default.
+    pub vsync: Option<bool>,
+}
+
+/// The `[camera]` table.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CameraConfig {
+    /// World position at the middle of the view.
+    pub center: Option<[f32; 2]>,
+    /// At zoom 1 the view spans `[-1, 1]` vertically.
+    pub zoom: Option<f32>,
+    /// Fit the view to the particles instead, like the "fit view" key.
+    pub fit: Option<bool>,
+}
+
+impl Config {
+    /// Reads the file at `path` and checks the range of each setting in it. Relative paths in it are taken
+    /// relative to its directory, see [`Config`].
+    ///
+    /// Settings that depend on each other, like `interpolate` and `substep_dt`, may be split between the
+    /// file and the command line, so they're only checked once both are merged, see [`Options::validate`].
+    ///
+    /// Errors point at the offending line: TOML syntax errors and unknown keys through the parser's own
+    /// message, out-of-range values through [`find_key_line`].
+    pub fn load(path: &Path) -> Result<Self> {
+        let source = fs::read_to_string(path).with_context(|| format!("Failed to read config {}", path.display()))?;
+        let mut config: Self = toml::from_str(&source).with_context(|| format!("Invalid config {}", path.display()))?;
+        if let Some(dir) = path.parent() {
+            config.resolve_paths(dir);
+        }
+
+        let mut options = Options::default();
+        options.apply_config(config.clone());
+        if let Err(invalid) = options.validate_ranges() {
+            match find_key_line(&source, &invalid.key) {
+                Some(line) => bail!("Invalid config {}: line {}, `{}`: {}", path.display(), line, invalid.key, invalid),
+                None => bail!("Invalid config {}: `{}`: {}", path.display(), invalid.key, invalid),
+            }
+        }
+
+        Ok(config)
+    }
+
+    /// Joins the relative paths in the file onto `dir`, its directory. Absolute paths are left alone.
+    fn resolve_paths(&mut self, dir: &Path) {
+        let paths = [&mut self.gradient, &mut self.keymap, &mut self.diagnostics];
+        for path in paths.into_iter().flatten() {
+            *path = dir.join(&*path);
+        }
+        if let Some(InitialCondition::Image(ref mut params)) = self.initial {
+            params.path = dir.join(&params.path);
+        }
+    }
+}
+
+/// The 1-based line that sets `key` in the TOML `source`, or that opens its table.
+///
+/// `key` is dotted like [`InvalidSetting::key`](crate::options::InvalidSetting::key): `softening`,
+/// `window.width`, or `emitters.2` for the third `[[emitters]]` table. Only keys written out as
+/// `key = value` under their table's header are found, not ones inside inline tables.
+pub fn find_key_line(source: &str, key: &str) -> Option<usize> {
+    let (table, name) = key.rsplit_once('.').unwrap_or(("", key));
+    let index = name.parse::<usize>().ok();
+
+    let mut current = "";
+    let mut seen = 0;
+    for (i, line) in source.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+
+        if let Some(header) = table_header(line) {
+            current = header;
+            if header == key {
+                return Some(i + 1);
+            }
+            if index.is_some() && header == table {
+                if index == Some(seen) {
+                    return Some(i + 1);
+                }
+                seen += 1;
+            }
+            continue;
+        }
+
+        let Some((k, _)) = line.split_once('=') else {
+            continue;
+        };
+        let k = k.trim().trim_matches('"');
+        if (current == table && k == name) || (current.is_empty() && k == key) {
+            return Some(i + 1);
+        }
+    }
+
+    None
+}
+
+/// The name of the table a `[table]` or `[[table]]` header line opens. Lines of multi-line arrays
+/// also start with a bracket, but they don't consist of a bare key.
+fn table_header(line: &str) -> Option<&str> {
+    let name = line.strip_prefix('[')?.strip_suffix(']')?;
+    let name = name.strip_prefix('[').and_then(|n| n.strip_suffix(']')).unwrap_or(name).trim();
+    (!name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.')))
+        .then_some(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::process;
+
+    use super::*;
+
+    const SCENE: &str = r#"# softening = 0.1 only in a comment
+seed = 1
+gravity = 2.0 # softening = 0.2
+domain = [
+    -1, -1,
+    1, 1,
+]
+
+[window]
+width = 800
+"height" = 600
+
+[camera]
+zoom = 2.0
+
+[[emitters]]
+rate = 10.0
+
+[[emitters]]
+# rate = 5.0
+shape = "disk"
+rate = 20.0
+"#;
+
+    #[test]
+    fn finds_top_level_keys() {
+        assert_eq!(find_key_line(SCENE, "seed"), Some(2), "seed");
+        assert_eq!(find_key_line(SCENE, "gravity"), Some(3), "gravity");
+        assert_eq!(find_key_line(SCENE, "domain"), Some(4), "multi-line value");
+    }
+
+    #[test]
+    fn finds_keys_in_tables() {
+        assert_eq!(find_key_line(SCENE, "window.width"), Some(10), "window width");
+        assert_eq!(find_key_line(SCENE, "window.height"), Some(11), "quoted key");
+        assert_eq!(find_key_line(SCENE, "camera.zoom"), Some(14), "camera zoom");
+        assert_eq!(find_key_line(SCENE, "window"), Some(9), "the table itself");
+        assert_eq!(find_key_line(SCENE, "width"), None, "table keys aren't top-level keys");
+        assert_eq!(find_key_line(SCENE, "camera.center"), None, "missing key");
+    }
+
+    #[test]
+    fn finds_array_tables() {
+        assert_eq!(find_key_line(SCENE, "emitters.0"), Some(16), "first emitter");
+        assert_eq!(find_key_line(SCENE, "emitters.1"), Some(19), "second emitter");
+        assert_eq!(find_key_line(SCENE, "emitters.2"), None, "only two emitters");
+    }
+
+    #[test]
+    fn ignores_comments() {
+        assert_eq!(find_key_line(SCENE, "softening"), None, "keys in comments aren't set");
+        assert_eq!(find_key_line("# [window]\nwidth = 1", "window.width"), None, "headers in comments don't open tables");
+    }
+
+    #[test]
+    fn parses_table_headers() {
+        assert_eq!(table_header("[window]"), Some("window"), "table");
+        assert_eq!(table_header("[[emitters]]"), Some("emitters"), "array of tables");
+        assert_eq!(table_header("[ camera ]"), Some("camera"), "spaces inside the brackets");
+        assert_eq!(table_header("[1, 2]"), None, "array value line");
+        assert_eq!(table_header("[]"), None, "empty brackets");
+        assert_eq!(table_header("seed = 1"), None, "key line");
+    }
+
+    #[test]
+    fn load_reports_the_line() {
+        let path = env::temp_dir().join(format!("ogl-config-test-{}.toml", process::id()));
+        fs::write(&path, "seed = 1\n\n[window]\nwidth = 0\n").expect("couldn't write the test scene");
+        let result = Config::load(&path);
+        fs::remove_file(&path).expect("couldn't remove the test scene");
+
+        let message = format!("{:#}", result.expect_err("zero width accepted"));
+        assert!(message.contains("line 4, `window.width`"), "no line in `{}`", message);
+    }
+}
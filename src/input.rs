@@ -0,0 +1,524 @@
+use core::fmt::{self, Display};
+use core::hash::{Hash, Hasher};
+use core::str::FromStr;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{bail, Context as AnyhowContextTrait, Error, Result};
+use glfw::{Key, Modifiers, WindowEvent};
+use serde::Deserialize;
+
+use crate::warn;
+
+/// Something the user can trigger from the keyboard. Names in keymap files are the snake_case variant names.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Quit,
+    /// Stops or resumes the simulation; drawing continues.
+    Pause,
+    /// Pauses and advances by one frame.
+    Step,
+    /// Pauses and advances by `--step-frames` frames.
+    StepMany,
+    /// Divides the time scale by [`TIME_SCALE_STEP`](crate::simulation::time::TIME_SCALE_STEP).
+    SlowDown,
+    /// Multiplies the time scale by [`TIME_SCALE_STEP`](crate::simulation::time::TIME_SCALE_STEP).
+    SpeedUp,
+    /// Sets the time scale back to 1.
+    NormalSpeed,
+    /// Starts over from fresh random particles with the current seed.
+    Reset,
+    SaveSnapshot,
+    LoadSnapshot,
+    /// Writes the current frame to a PNG file.
+    Screenshot,
+    /// Doubles the particle count.
+    MoreParticles,
+    /// Halves the particle count.
+    FewerParticles,
+    CycleColorMode,
+    /// Fits the camera to the particles' bounding box.
+    FitView,
+    /// Prints the energy, momentum and extent of the particles, see [`Diagnostics`](crate::simulation::diagnostics::Diagnostics).
+    PrintDiagnostics,
+    ToolAttract,
+    ToolRepel,
+    ToolVortex,
+    ToolDrag,
+    ToolSpawn,
+}
+
+impl Action {
+    /// Whether holding the key down triggers the action again on key repeat.
+    pub const fn repeats(self) -> bool {
+        matches!(
+            self,
+            Self::Step | Self::StepMany | Self::SlowDown | Self::SpeedUp | Self::MoreParticles | Self::FewerParticles
+        )
+    }
+}
+
+impl Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match *self {
+            Self::Quit => "quit",
+            Self::Pause => "pause",
+            Self::Step => "step",
+            Self::StepMany => "step_many",
+            Self::SlowDown => "slow_down",
+            Self::SpeedUp => "speed_up",
+            Self::NormalSpeed => "normal_speed",
+            Self::Reset => "reset",
+            Self::SaveSnapshot => "save_snapshot",
+            Self::LoadSnapshot => "load_snapshot",
+            Self::Screenshot => "screenshot",
+            Self::MoreParticles => "more_particles",
+            Self::FewerParticles => "fewer_particles",
+            Self::CycleColorMode => "cycle_color_mode",
+            Self::FitView => "fit_view",
+            Self::PrintDiagnostics => "print_diagnostics",
+            Self::ToolAttract => "tool_attract",
+            Self::ToolRepel => "tool_repel",
+            Self::ToolVortex => "tool_vortex",
+            Self::ToolDrag => "tool_drag",
+            Self::ToolSpawn => "tool_spawn",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Names accepted for keys in keymap files, compared case-insensitively. Digits may also be written
+/// without the `Num` prefix.
+const KEY_NAMES: &[(&str, Key)] = &[
+    ("Space", Key::Space),
+    ("Apostrophe", Key::Apostrophe),
+    ("Comma", Key::Comma),
+    ("Minus", Key::Minus),
+    ("Period", Key::Period),
+    ("Slash", Key::Slash),
+    ("Num0", Key::Num0),
+    ("Num1", Key::Num1),
+    ("Num2", Key::Num2),
+    ("Num3", Key::Num3),
+    ("Num4", Key::Num4),
+    ("Num5", Key::Num5),
+    ("Num6", Key::Num6),
+    ("Num7", Key::Num7),
+    ("Num8", Key::Num8),
+    ("Num9", Key::Num9),
+    ("Semicolon", Key::Semicolon),
+    ("Equal", Key::Equal),
+    ("A", Key::A),
+    ("B", Key::B),
+    ("C", Key::C),
+    ("D", Key::D),
+    ("E", Key::E),
+    ("F", Key::F),
+    ("G", Key::G),
+    ("H", Key::H),
+    ("I", Key::I),
+    ("J", Key::J),
+    ("K", Key::K),
+    ("L", Key::L),
+    ("M", Key::M),
+    ("N", Key::N),
+    ("O", Key::O),
+    ("P", Key::P),
+    ("Q", Key::Q),
+    ("R", Key::R),
+    ("S", Key::S),
+    ("T", Key::T),
+    ("U", Key::U),
+    ("V", Key::V),
+    ("W", Key::W),
+    ("X", Key::X),
+    ("Y", Key::Y),
+    ("Z", Key::Z),
+    ("LeftBracket", Key::LeftBracket),
+    ("Backslash", Key::Backslash),
+    ("RightBracket", Key::RightBracket),
+    ("GraveAccent", Key::GraveAccent),
+    ("Escape", Key::Escape),
+    ("Enter", Key::Enter),
+    ("Tab", Key::Tab),
+    ("Backspace", Key::Backspace),
+    ("Insert", Key::Insert),
+    ("Delete", Key::Delete),
+    ("Right", Key::Right),
+    ("Left", Key::Left),
+    ("Down", Key::Down),
+    ("Up", Key::Up),
+    ("PageUp", Key::PageUp),
+    ("PageDown", Key::PageDown),
+    ("Home", Key::Home),
+    ("End", Key::End),
+    ("PrintScreen", Key::PrintScreen),
+    ("Pause", Key::Pause),
+    ("F1", Key::F1),
+    ("F2", Key::F2),
+    ("F3", Key::F3),
+    ("F4", Key::F4),
+    ("F5", Key::F5),
+    ("F6", Key::F6),
+    ("F7", Key::F7),
+    ("F8", Key::F8),
+    ("F9", Key::F9),
+    ("F10", Key::F10),
+    ("F11", Key::F11),
+    ("F12", Key::F12),
+    ("Kp0", Key::Kp0),
+    ("Kp1", Key::Kp1),
+    ("Kp2", Key::Kp2),
+    ("Kp3", Key::Kp3),
+    ("Kp4", Key::Kp4),
+    ("Kp5", Key::Kp5),
+    ("Kp6", Key::Kp6),
+    ("Kp7", Key::Kp7),
+    ("Kp8", Key::Kp8),
+    ("Kp9", Key::Kp9),
+    ("KpDecimal", Key::KpDecimal),
+    ("KpDivide", Key::KpDivide),
+    ("KpMultiply", Key::KpMultiply),
+    ("KpSubtract", Key::KpSubtract),
+    ("KpAdd", Key::KpAdd),
+    ("KpEnter", Key::KpEnter),
+    ("KpEqual", Key::KpEqual),
+];
+
+/// The modifiers that take part in matching. Caps Lock and Num Lock are ignored.
+fn chord_modifiers(mods: Modifiers) -> Modifiers {
+    mods & (Modifiers::Shift | Modifiers::Control | Modifiers::Alt | Modifiers::Super)
+}
+
+/// A key together with the modifiers that must be held, written like `Ctrl+Shift+S` in keymap files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyChord {
+    pub key: Key,
+    pub mods: Modifiers,
+}
+
+// Not derived, `Modifiers` only implements `Hash` in some versions of glfw.
+impl Hash for KeyChord {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key.hash(state);
+        self.mods.bits().hash(state);
+    }
+}
+
+impl KeyChord {
+    pub fn new(key: Key, mods: Modifiers) -> Self {
+        Self {
+            key,
+            mods: chord_modifiers(mods),
+        }
+    }
+
+    pub fn plain(key: Key) -> Self {
+        Self::new(key, Modifiers::empty())
+    }
+}
+
+impl FromStr for KeyChord {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts: Vec<&str> = s.split('+').map(str::trim).collect();
+        let key_name = parts.pop().unwrap_or_default();
+
+        let mut mods = Modifiers::empty();
+        for part in parts {
+            mods |= match part.to_ascii_lowercase().as_str() {
+                "shift" => Modifiers::Shift,
+                "ctrl" | "control" => Modifiers::Control,
+                "alt" => Modifiers::Alt,
+                "super" => Modifiers::Super,
+                _ => bail!("unknown modifier `{}` in `{}`, expected Shift, Ctrl, Alt or Super", part, s),
+            };
+        }
+
+        let key = KEY_NAMES
+            .iter()
+            .find(|&&(name, _)| name.eq_ignore_ascii_case(key_name) || name.strip_prefix("Num") == Some(key_name))
+            .map(|&(_, key)| key);
+
+        match key {
+            Some(key) => Ok(Self::new(key, mods)),
+            None => bail!("unknown key `{}` in `{}`", key_name, s),
+        }
+    }
+}
+
+impl Display for KeyChord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (flag, name) in [
+            (Modifiers::Control, "Ctrl"),
+            (Modifiers::Alt, "Alt"),
+            (Modifiers::Shift, "Shift"),
+            (Modifiers::Super, "Super"),
+        ] {
+            if self.mods.contains(flag) {
+                write!(f, "{}+", name)?;
+            }
+        }
+
+        match KEY_NAMES.iter().find(|&&(_, key)| key == self.key) {
+            Some(&(name, _)) => f.write_str(name),
+            None => write!(f, "{:?}", self.key),
+        }
+    }
+}
+
+/// One key or a list of keys for an action in a keymap file.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+enum ChordList {
+    One(String),
+    Many(Vec<String>),
+}
+
+/// Maps key chords to [`Action`]s.
+///
+/// Keymap files are TOML tables from action names to one key chord or a list of them, e.g.
+/// `pause = "Space"` or `save_snapshot = ["F5", "Ctrl+S"]`. An action listed in the file loses its
+/// default keys; actions that aren't listed keep them.
+#[derive(Debug, Clone)]
+pub struct Keymap {
+    bindings: HashMap<KeyChord, Action>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        let defaults = [
+            (KeyChord::plain(Key::Escape), Action::Quit),
+            (KeyChord::plain(Key::Space), Action::Pause),
+            (KeyChord::plain(Key::Period), Action::Step),
+            (KeyChord::new(Key::Period, Modifiers::Shift), Action::StepMany),
+            (KeyChord::plain(Key::LeftBracket), Action::SlowDown),
+            (KeyChord::plain(Key::RightBracket), Action::SpeedUp),
+            (KeyChord::plain(Key::Backspace), Action::NormalSpeed),
+            (KeyChord::plain(Key::R), Action::Reset),
+            (KeyChord::plain(Key::F5), Action::SaveSnapshot),
+            (KeyChord::plain(Key::F9), Action::LoadSnapshot),
+            (KeyChord::plain(Key::F12), Action::Screenshot),
+            (KeyChord::plain(Key::Equal), Action::MoreParticles),
+            (KeyChord::plain(Key::KpAdd), Action::MoreParticles),
+            (KeyChord::plain(Key::Minus), Action::FewerParticles),
+            (KeyChord::plain(Key::KpSubtract), Action::FewerParticles),
+            (KeyChord::plain(Key::C), Action::CycleColorMode),
+            (KeyChord::plain(Key::F), Action::FitView),
+            (KeyChord::plain(Key::D), Action::PrintDiagnostics),
+            (KeyChord::plain(Key::Num1), Action::ToolAttract),
+            (KeyChord::plain(Key::Num2), Action::ToolRepel),
+            (KeyChord::plain(Key::Num3), Action::ToolVortex),
+            (KeyChord::plain(Key::Num4), Action::ToolDrag),
+            (KeyChord::plain(Key::Num5), Action::ToolSpawn),
+        ];
+
+        Self {
+            bindings: defaults.into_iter().collect(),
+        }
+    }
+}
+
+impl Keymap {
+    /// The default bindings, overridden by the ones in the keymap file at `path`.
+    pub fn load(path: &Path) -> Result<Self> {
+        let source = fs::read_to_string(path).with_context(|| format!("Failed to read keymap {}", path.display()))?;
+        Self::default()
+            .with_overrides(&source)
+            .with_context(|| format!("Invalid keymap {}", path.display()))
+    }
+
+    /// Applies the bindings in the TOML `source` on top of these.
+    ///
+    /// Two actions of `source` can't share a chord. A chord taken from the default of an action `source`
+    /// doesn't list is a warning, that action loses it.
+    pub fn with_overrides(mut self, source: &str) -> Result<Self> {
+        for (chord, from, to) in self.apply_overrides(source)? {
+            warn!("`{}` no longer triggers `{}`, the keymap binds it to `{}`", chord, from, to);
+        }
+        Ok(self)
+    }
+
+    /// Does the work of [`with_overrides`](Self::with_overrides), returning each chord taken from an unlisted
+    /// action with the action it was taken from and the one it's bound to now.
+    fn apply_overrides(&mut self, source: &str) -> Result<Vec<(KeyChord, Action, Action)>> {
+        let table: HashMap<Action, ChordList> = toml::from_str(source)?;
+        let mut table: Vec<(Action, ChordList)> = table.into_iter().collect();
+        table.sort_by_key(|&(action, _)| action as u8);
+
+        let mut overrides: HashMap<KeyChord, Action> = HashMap::new();
+        for (action, chords) in table {
+            let chords = match chords {
+                ChordList::One(chord) => vec![chord],
+                ChordList::Many(chords) => chords,
+            };
+            for chord in chords {
+                let chord: KeyChord = chord.parse()?;
+                match overrides.insert(chord, action) {
+                    Some(other) if other != action => bail!("`{}` is bound to both `{}` and `{}`", chord, other, action),
+                    _ => {}
+                }
+            }
+        }
+
+        self.bindings.retain(|_, bound| !overrides.values().any(|action| action == bound));
+        let mut taken = Vec::new();
+        for (chord, action) in overrides {
+            if let Some(other) = self.action_for(chord) {
+                taken.push((chord, other, action));
+            }
+            self.bind(chord, action);
+        }
+
+        Ok(taken)
+    }
+
+    /// Binds `chord` to `action`, replacing whatever it was bound to.
+    pub fn bind(&mut self, chord: KeyChord, action: Action) {
+        self.bindings.insert(chord, action);
+    }
+
+    pub fn action_for(&self, chord: KeyChord) -> Option<Action> {
+        self.bindings.get(&chord).copied()
+    }
+
+    /// The action a window event triggers, if any. Only key presses, and key repeats for actions that
+    /// [repeat](Action::repeats), trigger anything.
+    pub fn translate(&self, event: &WindowEvent) -> Option<Action> {
+        let WindowEvent::Key(key, _, action, mods) = *event else {
+            return None;
+        };
+
+        let bound = self.action_for(KeyChord::new(key, mods))?;
+        match action {
+            glfw::Action::Press => Some(bound),
+            glfw::Action::Repeat if bound.repeats() => Some(bound),
+            _ => None,
+        }
+    }
+
+    /// Every binding, sorted by action, for printing.
+    pub fn bindings(&self) -> Vec<(Action, KeyChord)> {
+        let mut bindings: Vec<(Action, KeyChord)> = self.bindings.iter().map(|(&chord, &action)| (action, chord)).collect();
+        bindings.sort_by_key(|&(action, chord)| (action as u8, chord.to_string()));
+        bindings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key_event(key: Key, action: glfw::Action, mods: Modifiers) -> WindowEvent {
+        WindowEvent::Key(key, 0, action, mods)
+    }
+
+    fn press(keymap: &Keymap, key: Key, mods: Modifiers) -> Option<Action> {
+        keymap.translate(&key_event(key, glfw::Action::Press, mods))
+    }
+
+    #[test]
+    fn default_bindings() {
+        let keymap = Keymap::default();
+        assert_eq!(press(&keymap, Key::Escape, Modifiers::empty()), Some(Action::Quit), "Escape should quit");
+        assert_eq!(press(&keymap, Key::Space, Modifiers::empty()), Some(Action::Pause), "Space should pause");
+        assert_eq!(press(&keymap, Key::KpAdd, Modifiers::empty()), Some(Action::MoreParticles), "keypad + should add particles");
+        assert_eq!(press(&keymap, Key::Q, Modifiers::empty()), None, "Q is unbound by default");
+    }
+
+    #[test]
+    fn only_press_and_repeat_trigger() {
+        let keymap = Keymap::default();
+        let release = key_event(Key::Space, glfw::Action::Release, Modifiers::empty());
+        assert_eq!(keymap.translate(&release), None, "releases shouldn't trigger");
+
+        let repeat_pause = key_event(Key::Space, glfw::Action::Repeat, Modifiers::empty());
+        assert_eq!(keymap.translate(&repeat_pause), None, "pause doesn't repeat");
+        let repeat_step = key_event(Key::Period, glfw::Action::Repeat, Modifiers::empty());
+        assert_eq!(keymap.translate(&repeat_step), Some(Action::Step), "step repeats");
+
+        assert_eq!(keymap.translate(&WindowEvent::Char('r')), None, "only key events trigger");
+    }
+
+    #[test]
+    fn modifier_chords() {
+        let keymap = Keymap::default();
+        assert_eq!(press(&keymap, Key::Period, Modifiers::Shift), Some(Action::StepMany), "Shift+. should step many");
+        assert_eq!(press(&keymap, Key::Period, Modifiers::Control), None, "Ctrl+. is unbound");
+        assert_eq!(
+            press(&keymap, Key::Space, Modifiers::CapsLock | Modifiers::NumLock),
+            Some(Action::Pause),
+            "lock modifiers should be ignored"
+        );
+    }
+
+    #[test]
+    fn override_rebinds_action() {
+        let keymap = Keymap::default()
+            .with_overrides("pause = \"P\"\nsave_snapshot = [\"F6\", \"Ctrl+Shift+S\"]")
+            .expect("valid keymap");
+
+        assert_eq!(press(&keymap, Key::P, Modifiers::empty()), Some(Action::Pause), "P should pause");
+        assert_eq!(press(&keymap, Key::Space, Modifiers::empty()), None, "Space should lose its default binding");
+        assert_eq!(press(&keymap, Key::F5, Modifiers::empty()), None, "F5 should lose its default binding");
+        assert_eq!(press(&keymap, Key::F6, Modifiers::empty()), Some(Action::SaveSnapshot), "F6 should save");
+        assert_eq!(
+            press(&keymap, Key::S, Modifiers::Control | Modifiers::Shift),
+            Some(Action::SaveSnapshot),
+            "Ctrl+Shift+S should save"
+        );
+        assert_eq!(press(&keymap, Key::S, Modifiers::Control), None, "Ctrl+S needs Shift too");
+        assert_eq!(press(&keymap, Key::Escape, Modifiers::empty()), Some(Action::Quit), "unlisted actions keep their keys");
+    }
+
+    #[test]
+    fn rejects_chords_bound_twice() {
+        let error = Keymap::default()
+            .with_overrides("pause = \"X\"\nreset = [\"F2\", \"X\"]")
+            .expect_err("two actions on one chord accepted");
+        let message = error.to_string();
+        assert!(message.contains("pause") && message.contains("reset"), "the error should name both actions: {}", message);
+
+        let keymap = Keymap::default()
+            .with_overrides("pause = [\"X\", \"X\"]")
+            .expect("one action may list a chord twice");
+        assert_eq!(press(&keymap, Key::X, Modifiers::empty()), Some(Action::Pause), "X should pause");
+    }
+
+    #[test]
+    fn override_takes_chord_from_unlisted_default() {
+        let mut keymap = Keymap::default();
+        let taken = keymap.apply_overrides("pause = \"Escape\"\nreset = \"Space\"").expect("valid keymap");
+        assert_eq!(
+            taken,
+            [(KeyChord::plain(Key::Escape), Action::Quit, Action::Pause)],
+            "only Escape should be reported, Space belonged to pause, which the keymap rebinds"
+        );
+        assert_eq!(press(&keymap, Key::Escape, Modifiers::empty()), Some(Action::Pause), "the override should win");
+        assert!(
+            keymap.bindings().iter().all(|&(action, _)| action != Action::Quit),
+            "quit only had Escape, so it should be left unbound"
+        );
+    }
+
+    #[test]
+    fn rejects_bad_keymap_lines() {
+        assert!(Keymap::default().with_overrides("pause = \"Hyper+P\"").is_err(), "unknown modifier accepted");
+        assert!(Keymap::default().with_overrides("pause = \"Nope\"").is_err(), "unknown key accepted");
+        assert!(Keymap::default().with_overrides("explode = \"X\"").is_err(), "unknown action accepted");
+        assert!(Keymap::default().with_overrides("pause = 3").is_err(), "non-string key accepted");
+    }
+
+    #[test]
+    fn chord_names_round_trip() {
+        for name in ["Ctrl+Shift+S", "F12", "Alt+KpAdd", "Num1"] {
+            let chord: KeyChord = name.parse().expect("valid chord");
+            assert_eq!(chord.to_string(), name, "chord doesn't print back as written");
+        }
+        assert_eq!("1".parse::<KeyChord>().expect("valid chord"), KeyChord::plain(Key::Num1), "digits without Num");
+        assert_eq!("ctrl+s".parse::<KeyChord>().expect("valid chord"), KeyChord::new(Key::S, Modifiers::Control), "case-insensitive");
+    }
+}
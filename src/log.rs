@@ -0,0 +1,58 @@
+use core::fmt::{self, Display};
+use core::str::FromStr;
+use core::sync::atomic::{AtomicU8, Ordering};
+
+use anyhow::{bail, Error};
+
+/// How much the program prints, set once at startup with `--log-level`.
+///
+/// Results that a command exists to print, like the `bench` table, are printed at every level.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(u8)]
+pub enum LogLevel {
+    /// Only errors that end the program.
+    Error = 0,
+    /// Also recoverable errors, like a config edit that doesn't parse.
+    Warn = 1,
+    /// Also the startup summary and status messages.
+    #[default]
+    Info = 2,
+    /// Also the frame rate and every OpenGL debug message, not only the errors.
+    Debug = 3,
+}
+
+static LEVEL: AtomicU8 = AtomicU8::new(LogLevel::Info as u8);
+
+pub fn set_level(level: LogLevel) {
+    LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+/// Whether messages at `level` are printed.
+pub fn enabled(level: LogLevel) -> bool {
+    level as u8 <= LEVEL.load(Ordering::Relaxed)
+}
+
+impl FromStr for LogLevel {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "error" => Ok(Self::Error),
+            "warn" => Ok(Self::Warn),
+            "info" => Ok(Self::Info),
+            "debug" => Ok(Self::Debug),
+            _ => bail!("unknown log level `{}`, expected `error`, `warn`, `info` or `debug`", s),
+        }
+    }
+}
+
+impl Display for LogLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::Error => write!(f, "error"),
+            Self::Warn => write!(f, "warn"),
+            Self::Info => write!(f, "info"),
+            Self::Debug => write!(f, "debug"),
+        }
+    }
+}
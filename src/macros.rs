@@ -10,3 +10,33 @@ macro_rules! include_cstr {
         VALUE
     }};
 }
+
+/// `eprintln!` at [`LogLevel::Warn`](crate::log::LogLevel::Warn) and above.
+#[macro_export]
+macro_rules! warn {
+    ($($arg:tt)*) => {
+        if $crate::log::enabled($crate::log::LogLevel::Warn) {
+            eprintln!($($arg)*);
+        }
+    };
+}
+
+/// `println!` at [`LogLevel::Info`](crate::log::LogLevel::Info) and above.
+#[macro_export]
+macro_rules! info {
+    ($($arg:tt)*) => {
+        if $crate::log::enabled($crate::log::LogLevel::Info) {
+            println!($($arg)*);
+        }
+    };
+}
+
+/// `println!` at [`LogLevel::Debug`](crate::log::LogLevel::Debug).
+#[macro_export]
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        if $crate::log::enabled($crate::log::LogLevel::Debug) {
+            println!($($arg)*);
+        }
+    };
+}
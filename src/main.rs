@@ -1,21 +1,77 @@
 #![allow(unused_imports)]
+extern crate alloc;
 extern crate anyhow;
 extern crate gl;
 extern crate glfw;
 extern crate rayon;
 
 use anyhow::Result;
-use opengl::global_state::GlobalState;
+use opengl::global_state::{validate_shaders, GlobalState};
+use options::{Mode, Options};
 use rayon::{prelude::*, ThreadPoolBuilder};
+use simulation::bench::{self, BENCH_THETAS, DEFAULT_BENCH_PARTICLES};
+use simulation::drift::{self, DEFAULT_DRIFT_DT, DEFAULT_DRIFT_G, DEFAULT_DRIFT_PARTICLES, DEFAULT_DRIFT_SOFTENING, DEFAULT_DRIFT_STEPS};
+use simulation::integrator::Integrator;
+use simulation::SimParams;
+use voxell_rng::getrandom::MagicSeed;
 
+pub mod cli;
+pub mod config;
+pub mod input;
+pub mod log;
 pub mod macros;
 pub mod opengl;
+pub mod options;
+pub mod simulation;
 pub mod vec2;
+pub mod watch;
 
 fn main() -> Result<()> {
-    let mut global_state: GlobalState<128> = GlobalState::new()?;
-    // Safety: we just initialized global_state, triplet can't be None.
-    unsafe { global_state.main_loop() };
+    let options = Options::from_env_args()?;
+    log::set_level(options.log_level);
+
+    if options.mode == Mode::ValidateShaders {
+        validate_shaders(options.shader_dir.as_deref())?;
+        println!("Shaders OK");
+        return Ok(());
+    }
+
+    if options.mode == Mode::Bench {
+        let count = options.particle_count.unwrap_or(DEFAULT_BENCH_PARTICLES);
+        let seed = options
+            .seed
+            .unwrap_or_else(|| MagicSeed::u64().expect("fix your OS, couldn't get OS entropy"));
+        let thetas = options.theta.map_or_else(|| BENCH_THETAS.to_vec(), |theta| vec![theta]);
+        bench::run(count, seed, &options.sim_params(), &thetas);
+        return Ok(());
+    }
+
+    if options.mode == Mode::Drift {
+        let count = options.particle_count.unwrap_or(DEFAULT_DRIFT_PARTICLES);
+        let seed = options
+            .seed
+            .unwrap_or_else(|| MagicSeed::u64().expect("fix your OS, couldn't get OS entropy"));
+        let params = SimParams {
+            g: options.gravity.unwrap_or(DEFAULT_DRIFT_G),
+            softening: options.softening.unwrap_or(DEFAULT_DRIFT_SOFTENING),
+            ..options.sim_params()
+        };
+        let integrators = options
+            .integrator
+            .map_or_else(|| Integrator::ALL.to_vec(), |integrator| vec![integrator]);
+        let dt = options.fixed_dt.unwrap_or(DEFAULT_DRIFT_DT);
+        drift::run(count, seed, &params, &integrators, dt, options.drift_steps.unwrap_or(DEFAULT_DRIFT_STEPS));
+        return Ok(());
+    }
+
+    let mut global_state = GlobalState::new(&options)?;
+
+    if options.headless {
+        global_state.run_headless(options.frames)?;
+    } else {
+        // Safety: we just initialized global_state without --headless, triplet can't be None.
+        unsafe { global_state.main_loop()? };
+    }
 
     Ok(())
 }
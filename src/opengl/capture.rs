@@ -0,0 +1,206 @@
+use core::ffi::c_void;
+use core::fmt::{self, Display};
+use core::str::FromStr;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context as AnyhowContextTrait, Error, Result};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CaptureFormat {
+    Ppm,
+    #[default]
+    Png,
+}
+
+impl CaptureFormat {
+    pub const fn extension(self) -> &'static str {
+        match self {
+            Self::Ppm => "ppm",
+            Self::Png => "png",
+        }
+    }
+}
+
+impl FromStr for CaptureFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ppm" => Ok(Self::Ppm),
+            "png" => Ok(Self::Png),
+            _ => bail!("unknown capture format `{}`, expected `ppm` or `png`", s),
+        }
+    }
+}
+
+impl Display for CaptureFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.extension())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CaptureSettings {
+    pub dir: PathBuf,
+    pub format: CaptureFormat,
+    /// Capture every `stride`-th rendered frame.
+    pub stride: u32,
+    /// Stop capturing after this many images have been written.
+    pub max_frames: Option<u32>,
+    /// Flip rows so the image is top-down; `glReadPixels` returns them bottom-up.
+    pub flip: bool,
+}
+
+impl CaptureSettings {
+    pub fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            format: CaptureFormat::default(),
+            stride: 1,
+            max_frames: None,
+            flip: true,
+        }
+    }
+}
+
+/// Reads back the currently bound framebuffer and writes it out as a numbered image sequence.
+#[derive(Debug)]
+pub struct FrameCapture {
+    settings: CaptureSettings,
+    frame: u64,
+    captured: u32,
+    pixels: Vec<u8>,
+}
+
+impl FrameCapture {
+    pub fn new(settings: CaptureSettings) -> Result<Self> {
+        if settings.stride == 0 {
+            bail!("capture stride must be at least 1");
+        }
+
+        fs::create_dir_all(&settings.dir).with_context(|| format!("Failed to create capture directory {}", settings.dir.display()))?;
+
+        Ok(Self {
+            settings,
+            frame: 0,
+            captured: 0,
+            pixels: Vec::new(),
+        })
+    }
+
+    pub const fn settings(&self) -> &CaptureSettings {
+        &self.settings
+    }
+
+    /// Number of images written so far.
+    pub const fn captured(&self) -> u32 {
+        self.captured
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.settings.max_frames.is_some_and(|max| self.captured >= max)
+    }
+
+    /// Called once per rendered frame, right after drawing. Only every `stride`-th call reads pixels back.
+    pub fn on_frame(&mut self, width: usize, height: usize) -> Result<()> {
+        let frame = self.frame;
+        self.frame += 1;
+
+        if self.is_done() || !frame.is_multiple_of(self.settings.stride as u64) {
+            return Ok(());
+        }
+
+        read_pixels_rgb(width, height, &mut self.pixels);
+        if self.settings.flip {
+            flip_rows(&mut self.pixels, width * 3);
+        }
+
+        let path = self
+            .settings
+            .dir
+            .join(format!("frame_{:06}.{}", self.captured, self.settings.format.extension()));
+
+        match self.settings.format {
+            CaptureFormat::Ppm => write_ppm(&path, width, height, &self.pixels),
+            CaptureFormat::Png => write_png(&path, width, height, &self.pixels),
+        }
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+
+        self.captured += 1;
+
+        Ok(())
+    }
+}
+
+/// Reads the bound read framebuffer as tightly packed RGB8 rows, bottom row first.
+pub fn read_pixels_rgb(width: usize, height: usize, pixels: &mut Vec<u8>) {
+    pixels.resize(width * height * 3, 0);
+
+    unsafe {
+        gl::PixelStorei(gl::PACK_ALIGNMENT, 1);
+        gl::ReadPixels(
+            0,
+            0,
+            width as i32,
+            height as i32,
+            gl::RGB,
+            gl::UNSIGNED_BYTE,
+            pixels.as_mut_ptr().cast::<c_void>(),
+        );
+    }
+}
+
+pub fn flip_rows(pixels: &mut [u8], row_len: usize) {
+    let rows = pixels.len() / row_len;
+
+    for row in 0..rows / 2 {
+        let (top, bottom) = pixels.split_at_mut((rows - row - 1) * row_len);
+        top[row * row_len..(row + 1) * row_len].swap_with_slice(&mut bottom[..row_len]);
+    }
+}
+
+pub fn write_ppm(path: &Path, width: usize, height: usize, rgb: &[u8]) -> Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+
+    write!(out, "P6\n{} {}\n255\n", width, height)?;
+    out.write_all(rgb)?;
+    out.flush()?;
+
+    Ok(())
+}
+
+pub fn write_png(path: &Path, width: usize, height: usize, rgb: &[u8]) -> Result<()> {
+    let out = BufWriter::new(File::create(path)?);
+
+    let mut encoder = png::Encoder::new(out, width as u32, height as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(rgb)?;
+    writer.finish()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flip_rows_reverses_row_order() {
+        let mut odd: Vec<u8> = (0..6).collect();
+        flip_rows(&mut odd, 2);
+        assert_eq!(odd, [4, 5, 2, 3, 0, 1], "the middle row of an odd count stays put");
+
+        let mut even: Vec<u8> = (0..12).collect();
+        flip_rows(&mut even, 3);
+        assert_eq!(even, [9, 10, 11, 6, 7, 8, 3, 4, 5, 0, 1, 2], "rows swap in pairs");
+
+        let mut single = vec![1, 2, 3];
+        flip_rows(&mut single, 3);
+        assert_eq!(single, [1, 2, 3], "a single row is unchanged");
+    }
+}
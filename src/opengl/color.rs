@@ -0,0 +1,322 @@
+use core::error::Error;
+use core::fmt::{self, Display};
+use core::str::FromStr;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{bail, Context as AnyhowContextTrait, Result};
+use serde::Deserialize;
+
+/// Number of texels the gradient is baked into.
+pub const GRADIENT_WIDTH: usize = 256;
+
+/// Speed or acceleration magnitude mapped to the end of the gradient when none is configured.
+pub const DEFAULT_COLOR_RANGE: f32 = 1.0;
+
+/// Texture unit the gradient is bound to, `uGradient` is set to this.
+pub const GRADIENT_TEXTURE_UNIT: u32 = 0;
+
+/// What the particle color is derived from. Discriminants match the `COLOR_*` constants in `vertex.glsl`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorMode {
+    /// Constant orange, no gradient lookup.
+    #[default]
+    Solid = 0,
+    /// `|vel|` divided by the color range.
+    Speed = 1,
+    /// `|acc|` divided by the color range.
+    Acceleration = 2,
+    /// Direction of `vel`, once around the gradient per turn.
+    Heading = 3,
+    /// Position in the particle buffer, first to last.
+    Index = 4,
+}
+
+impl ColorMode {
+    pub const ALL: [Self; 5] = [Self::Solid, Self::Speed, Self::Acceleration, Self::Heading, Self::Index];
+
+    /// The mode after this one, wrapping around. Used by the color mode key.
+    pub const fn next(self) -> Self {
+        Self::ALL[(self as usize + 1) % Self::ALL.len()]
+    }
+}
+
+impl FromStr for ColorMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "solid" => Ok(Self::Solid),
+            "speed" => Ok(Self::Speed),
+            "acceleration" => Ok(Self::Acceleration),
+            "heading" => Ok(Self::Heading),
+            "index" => Ok(Self::Index),
+            _ => bail!("unknown color mode `{}`, expected `solid`, `speed`, `acceleration`, `heading` or `index`", s),
+        }
+    }
+}
+
+impl Display for ColorMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::Solid => write!(f, "solid"),
+            Self::Speed => write!(f, "speed"),
+            Self::Acceleration => write!(f, "acceleration"),
+            Self::Heading => write!(f, "heading"),
+            Self::Index => write!(f, "index"),
+        }
+    }
+}
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum GradientError {
+    /// A line isn't `<position> <r> <g> <b>` or `<position> #rrggbb`.
+    Syntax { line: usize, text: String },
+    /// Positions must be in `[0, 1]` and must not decrease.
+    Position { line: usize, position: f32 },
+    TooFewStops,
+}
+
+impl Error for GradientError {}
+
+impl Display for GradientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::Syntax { line, ref text } => write!(
+                f,
+                "line {}: expected `<position> <r> <g> <b>` or `<position> #rrggbb`, found `{}`",
+                line, text
+            ),
+            Self::Position { line, position } => write!(
+                f,
+                "line {}: stop position {} is outside [0, 1] or before the previous stop",
+                line, position
+            ),
+            Self::TooFewStops => write!(f, "a gradient needs at least one color stop"),
+        }
+    }
+}
+
+/// A piecewise-linear color ramp over `[0, 1]`.
+///
+/// Loaded from a text file with one stop per line, either `<position> <r> <g> <b>` with components in
+/// `[0, 1]` or `<position> #rrggbb`. Blank lines and lines starting with `#` are skipped.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Gradient {
+    stops: Vec<(f32, [f32; 3])>,
+}
+
+impl Default for Gradient {
+    /// Dark blue through magenta and orange to pale yellow.
+    fn default() -> Self {
+        Self {
+            stops: vec![
+                (0.0, [0.05, 0.03, 0.30]),
+                (0.35, [0.60, 0.10, 0.55]),
+                (0.7, [1.0, 0.5, 0.2]),
+                (1.0, [1.0, 0.95, 0.70]),
+            ],
+        }
+    }
+}
+
+impl Gradient {
+    pub fn load(path: &Path) -> Result<Self> {
+        let source = fs::read_to_string(path).with_context(|| format!("Failed to read gradient {}", path.display()))?;
+        Self::parse(&source).with_context(|| format!("Invalid gradient {}", path.display()))
+    }
+
+    pub fn parse(source: &str) -> Result<Self, GradientError> {
+        let mut stops: Vec<(f32, [f32; 3])> = Vec::new();
+
+        for (i, line) in source.lines().enumerate() {
+            let line_no = i + 1;
+            let text = line.trim();
+            if text.is_empty() || text.starts_with('#') {
+                continue;
+            }
+
+            let syntax = || GradientError::Syntax {
+                line: line_no,
+                text: text.to_owned(),
+            };
+
+            let fields: Vec<&str> = text.split_whitespace().collect();
+            let position: f32 = fields[0].parse().map_err(|_| syntax())?;
+            let color = match fields[1..] {
+                [hex] => parse_hex(hex).ok_or_else(syntax)?,
+                [r, g, b] => parse_rgb([r, g, b]).ok_or_else(syntax)?,
+                _ => return Err(syntax()),
+            };
+
+            let previous = stops.last().map_or(0.0, |&(p, _)| p);
+            if !(previous..=1.0).contains(&position) {
+                return Err(GradientError::Position { line: line_no, position });
+            }
+
+            stops.push((position, color));
+        }
+
+        if stops.is_empty() {
+            return Err(GradientError::TooFewStops);
+        }
+
+        Ok(Self { stops })
+    }
+
+    /// Color at `t`, clamped to the first and last stop.
+    pub fn sample(&self, t: f32) -> [f32; 3] {
+        let first = self.stops[0];
+        if t <= first.0 {
+            return first.1;
+        }
+
+        for pair in self.stops.windows(2) {
+            let [(p0, c0), (p1, c1)] = *pair else {
+                unreachable!()
+            };
+            if t <= p1 {
+                let f = if p1 > p0 { (t - p0) / (p1 - p0) } else { 1.0 };
+                return [0, 1, 2].map(|k| (c1[k] - c0[k]).mul_add(f, c0[k]));
+            }
+        }
+
+        self.stops[self.stops.len() - 1].1
+    }
+
+    /// Samples the gradient at `width` evenly spaced texel centers as RGB8.
+    pub fn bake(&self, width: usize) -> Vec<u8> {
+        (0..width)
+            .flat_map(|i| {
+                let t = (i as f32 + 0.5) / width as f32;
+                self.sample(t).map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8)
+            })
+            .collect()
+    }
+}
+
+fn parse_rgb(components: [&str; 3]) -> Option<[f32; 3]> {
+    let [r, g, b] = components.map(|s| s.parse::<f32>().ok());
+    Some([r?, g?, b?])
+}
+
+fn parse_hex(s: &str) -> Option<[f32; 3]> {
+    let hex = s.strip_prefix('#')?;
+    if hex.len() != 6 {
+        return None;
+    }
+
+    let value = u32::from_str_radix(hex, 16).ok()?;
+    Some([16, 8, 0].map(|shift| ((value >> shift) & 0xFF) as f32 / 255.0))
+}
+
+/// A [`Gradient`] baked into a 1D RGB8 texture for the fragment shader (`uGradient`).
+#[derive(Debug)]
+pub struct GradientTexture {
+    handle: u32,
+}
+
+impl GradientTexture {
+    pub fn new(gradient: &Gradient) -> Self {
+        let texels = gradient.bake(GRADIENT_WIDTH);
+        let mut handle = 0;
+
+        unsafe {
+            gl::GenTextures(1, &raw mut handle);
+            gl::BindTexture(gl::TEXTURE_1D, handle);
+            gl::TexParameteri(gl::TEXTURE_1D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
+            gl::TexParameteri(gl::TEXTURE_1D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+            gl::TexParameteri(gl::TEXTURE_1D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+            gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+            gl::TexImage1D(
+                gl::TEXTURE_1D,
+                0,
+                gl::RGB8 as i32,
+                GRADIENT_WIDTH as i32,
+                0,
+                gl::RGB,
+                gl::UNSIGNED_BYTE,
+                texels.as_ptr().cast(),
+            );
+            gl::BindTexture(gl::TEXTURE_1D, 0);
+        }
+
+        Self { handle }
+    }
+
+    /// Binds the texture to [`GRADIENT_TEXTURE_UNIT`].
+    pub fn bind(&self) {
+        unsafe {
+            gl::ActiveTexture(gl::TEXTURE0 + GRADIENT_TEXTURE_UNIT);
+            gl::BindTexture(gl::TEXTURE_1D, self.handle);
+        }
+    }
+}
+
+impl Drop for GradientTexture {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteTextures(1, &raw const self.handle);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_color(found: [f32; 3], expected: [f32; 3], what: &str) {
+        assert!(
+            found.iter().zip(expected).all(|(f, e)| (f - e).abs() < 1e-5),
+            "{}: expected {:?}, found {:?}",
+            what,
+            expected,
+            found
+        );
+    }
+
+    #[test]
+    fn parses_both_stop_formats() {
+        let gradient = Gradient::parse("# black to white via red\n\n0 0 0 0\n  0.5 #ff0000\n1.0 1 1 1\n").expect("valid gradient");
+        assert_eq!(
+            gradient.stops,
+            [(0.0, [0.0, 0.0, 0.0]), (0.5, [1.0, 0.0, 0.0]), (1.0, [1.0, 1.0, 1.0])],
+            "comments and blank lines should be skipped"
+        );
+    }
+
+    #[test]
+    fn reports_the_bad_line() {
+        let syntax = Gradient::parse("0 0 0 0\n# comment\n0.5 #ff00\n").expect_err("short hex accepted");
+        assert!(matches!(syntax, GradientError::Syntax { line: 3, .. }), "wrong error: {:?}", syntax);
+
+        let syntax = Gradient::parse("0 0 0\n").expect_err("two components accepted");
+        assert!(matches!(syntax, GradientError::Syntax { line: 1, .. }), "wrong error: {:?}", syntax);
+
+        let position = Gradient::parse("0.5 #000000\n0.25 #ffffff\n").expect_err("decreasing position accepted");
+        assert!(matches!(position, GradientError::Position { line: 2, .. }), "wrong error: {:?}", position);
+
+        let position = Gradient::parse("1.5 #000000\n").expect_err("position past 1 accepted");
+        assert!(matches!(position, GradientError::Position { line: 1, .. }), "wrong error: {:?}", position);
+
+        assert!(matches!(Gradient::parse("# nothing\n"), Err(GradientError::TooFewStops)), "empty gradient accepted");
+    }
+
+    #[test]
+    fn samples_between_stops() {
+        let gradient = Gradient::parse("0.2 0 0 0\n0.6 1 0.5 0\n1 1 1 1\n").expect("valid gradient");
+        assert_color(gradient.sample(0.0), [0.0, 0.0, 0.0], "before the first stop");
+        assert_color(gradient.sample(0.4), [0.5, 0.25, 0.0], "halfway between the first two stops");
+        assert_color(gradient.sample(0.6), [1.0, 0.5, 0.0], "on a stop");
+        assert_color(gradient.sample(0.8), [1.0, 0.75, 0.5], "halfway between the last two stops");
+        assert_color(gradient.sample(1.0), [1.0, 1.0, 1.0], "at the end");
+    }
+
+    #[test]
+    fn bake_samples_texel_centers() {
+        let gradient = Gradient::parse("0 0 0 0\n1 1 1 1\n").expect("valid gradient");
+        assert_eq!(gradient.bake(2), [64, 64, 64, 191, 191, 191], "texels at 0.25 and 0.75");
+    }
+}
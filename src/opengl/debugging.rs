@@ -75,9 +75,16 @@ extern "system" fn gl_debug_callback(source: u32, gltype: u32, id: u32, severity
     }
 }
 
-pub fn gl_initialize_debugging() {
+/// Prints OpenGL's debug messages. Unless `all`, only errors and high-severity messages are printed.
+pub fn gl_initialize_debugging(all: bool) {
     unsafe {
         gl::Enable(gl::DEBUG_OUTPUT);
         gl::DebugMessageCallback(Some(gl_debug_callback), ptr::null());
+
+        if !all {
+            gl::DebugMessageControl(gl::DONT_CARE, gl::DONT_CARE, gl::DONT_CARE, 0, ptr::null(), gl::FALSE);
+            gl::DebugMessageControl(gl::DONT_CARE, gl::DEBUG_TYPE_ERROR, gl::DONT_CARE, 0, ptr::null(), gl::TRUE);
+            gl::DebugMessageControl(gl::DONT_CARE, gl::DONT_CARE, gl::DEBUG_SEVERITY_HIGH, 0, ptr::null(), gl::TRUE);
+        }
     }
 }
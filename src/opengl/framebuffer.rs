@@ -0,0 +1,108 @@
+use gl::types::GLenum;
+
+use core::error::Error;
+use core::fmt::{self, Display};
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum FramebufferError {
+    Incomplete(GLenum),
+}
+
+impl Error for FramebufferError {}
+
+impl Display for FramebufferError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::Incomplete(status) => write!(f, "Framebuffer is incomplete: {}", get_framebuffer_status_name(status)),
+        }
+    }
+}
+
+pub const fn get_framebuffer_status_name(status: GLenum) -> &'static str {
+    match status {
+        gl::FRAMEBUFFER_UNDEFINED => "undefined",
+        gl::FRAMEBUFFER_INCOMPLETE_ATTACHMENT => "incomplete attachment",
+        gl::FRAMEBUFFER_INCOMPLETE_MISSING_ATTACHMENT => "missing attachment",
+        gl::FRAMEBUFFER_INCOMPLETE_DRAW_BUFFER => "incomplete draw buffer",
+        gl::FRAMEBUFFER_INCOMPLETE_READ_BUFFER => "incomplete read buffer",
+        gl::FRAMEBUFFER_UNSUPPORTED => "unsupported",
+        gl::FRAMEBUFFER_INCOMPLETE_MULTISAMPLE => "incomplete multisample",
+        gl::FRAMEBUFFER_INCOMPLETE_LAYER_TARGETS => "incomplete layer targets",
+        _ => "unknown framebuffer status",
+    }
+}
+
+/// An offscreen RGBA8 render target, used in place of the default framebuffer when there is no window.
+#[derive(Debug)]
+pub struct Framebuffer {
+    fbo: u32,
+    color: u32,
+    width: i32,
+    height: i32,
+}
+
+impl Framebuffer {
+    pub fn new(width: i32, height: i32) -> Result<Self, FramebufferError> {
+        let mut fbo = 0;
+        let mut color = 0;
+
+        unsafe {
+            gl::GenFramebuffers(1, &raw mut fbo);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, fbo);
+
+            gl::GenRenderbuffers(1, &raw mut color);
+            gl::BindRenderbuffer(gl::RENDERBUFFER, color);
+            gl::RenderbufferStorage(gl::RENDERBUFFER, gl::RGBA8, width, height);
+            gl::FramebufferRenderbuffer(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0, gl::RENDERBUFFER, color);
+
+            let status = gl::CheckFramebufferStatus(gl::FRAMEBUFFER);
+
+            gl::BindRenderbuffer(gl::RENDERBUFFER, 0);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+
+            let framebuffer = Self { fbo, color, width, height };
+
+            if status != gl::FRAMEBUFFER_COMPLETE {
+                return Err(FramebufferError::Incomplete(status));
+            }
+
+            Ok(framebuffer)
+        }
+    }
+
+    /// Binds the framebuffer for drawing and reading, and sets the viewport to cover it.
+    pub fn bind(&self) {
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, self.fbo);
+            gl::Viewport(0, 0, self.width, self.height);
+        }
+    }
+
+    pub fn unbind() {
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+        }
+    }
+
+    pub const fn handle(&self) -> u32 {
+        self.fbo
+    }
+
+    pub const fn width(&self) -> i32 {
+        self.width
+    }
+
+    pub const fn height(&self) -> i32 {
+        self.height
+    }
+}
+
+impl Drop for Framebuffer {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteRenderbuffers(1, &raw const self.color);
+            gl::DeleteFramebuffers(1, &raw const self.fbo);
+        }
+    }
+}
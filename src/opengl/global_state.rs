@@ -1,19 +1,54 @@
-use core::{ptr, time::Duration};
-use std::time::Instant;
+use core::{mem, ptr, time::Duration};
+use std::path::{Path, PathBuf};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 
+use crate::config::Config;
+use crate::input::{Action as InputAction, Keymap};
+use crate::log::{self, LogLevel};
 use crate::opengl::uniform::SetAllUniformLocations;
+use crate::options::{Options, DEFAULT_PARTICLE_COUNT};
+use crate::simulation::boundary::BoundaryKind;
+use crate::simulation::emitter::{Emitter, Emitters};
+use crate::simulation::initial::Generator;
+use crate::simulation::diagnostics::{Diagnostics, DiagnosticsLog, DEFAULT_DIAGNOSTICS_EVERY, DEFAULT_HISTOGRAM_BINS};
+use crate::simulation::tools::{ToolKind, Tools, TOOL_SCROLL_STEP};
+use crate::simulation::time::{TimeControl, DEFAULT_MAX_DT, DEFAULT_MAX_SUBSTEPS, DEFAULT_STEP_FRAMES, TIME_SCALE_STEP};
+use crate::simulation::{BackendKind, SimulationBackend};
+use crate::vec2::Vector2;
+use crate::watch::FileWatcher;
+use crate::{debug, info, warn};
 use anyhow::{Context as AnyhowContextTrait, Result};
-use glfw::{fail_on_errors, Context, Glfw, GlfwReceiver, PWindow, WindowEvent};
+use glfw::{fail_on_errors, Action, Context, Glfw, GlfwReceiver, Key, MouseButton, PWindow, SwapInterval, WindowEvent};
+use voxell_rng::getrandom::MagicSeed;
 
 use super::{
+    capture::{flip_rows, read_pixels_rgb, write_png, FrameCapture},
+    color::{ColorMode, Gradient, GradientTexture, DEFAULT_COLOR_RANGE, GRADIENT_TEXTURE_UNIT},
     debugging::gl_initialize_debugging,
+    framebuffer::Framebuffer,
+    headless::{init_headless, HeadlessContext},
+    hot_reload::ShaderHotReload,
     program::Program,
-    render::renderstate::RenderState,
-    shader::{get_all_shaders, Shader},
+    render::{layout::PARTICLE_LAYOUT, outline::DomainOutline, renderstate::RenderState, snapshot::Snapshot},
+    shader::{get_all_shaders, get_all_shaders_from_dir, Shader},
     uniform::UniformLocations,
 };
 
-pub struct GlobalState<const LEN: usize> {
+/// Window size when neither `--window-size` nor the config file set one.
+pub const CANVAS_WIDTH: i32 = 1280;
+pub const CANVAS_HEIGHT: i32 = 720;
+
+pub const DEFAULT_TITLE: &str = "Rust Game";
+
+/// Side length of a particle's quad in world units (`uQuadSize`).
+pub const DEFAULT_QUAD_SIZE: f32 = 0.03;
+
+pub const DEFAULT_CLEAR_COLOR: [f32; 3] = [0.2, 0.3, 0.3];
+
+/// Zoom factor per scroll wheel notch.
+pub const ZOOM_STEP: f32 = 1.1;
+
+pub struct GlobalState {
     pub triplet: Option<GLFWTriplet>,
 
     pub vshader: Shader,
@@ -27,25 +62,180 @@ pub struct GlobalState<const LEN: usize> {
     pub draw_uniforms: UniformLocations,
     pub compute_uniforms: UniformLocations,
 
-    pub render_state: RenderState<LEN>,
+    /// Present with `--hot-reload-shaders`, recompiles the programs when a shader file changes.
+    pub shader_reload: Option<ShaderHotReload>,
+
+    pub render_state: RenderState,
+    pub backend: Box<dyn SimulationBackend>,
+
+    /// What the particle color is derived from (`uColorMode`), cycled with C.
+    pub color_mode: ColorMode,
+    /// Value at the end of the gradient for the speed and acceleration modes (`uColorRange`).
+    pub color_range: f32,
+    pub gradient: GradientTexture,
+    /// Drawn over the particles unless the boundary kind is `none`.
+    pub outline: DomainOutline,
+    /// Side length of a particle's quad in world units (`uQuadSize`).
+    pub quad_size: f32,
+    pub clear_color: [f32; 3],
+
+    /// Mouse tools, picked with 1-5 and applied while the left button is held.
+    pub tools: Tools,
+    /// World-space cursor position on the previous frame, for the cursor velocity the drag tool uses.
+    pub last_cursor: (f32, f32),
+
+    pub keymap: Keymap,
+    /// Pause, stepping, time scale and the dt clamp.
+    pub time: TimeControl,
+    /// Set by the screenshot key, handled once the next frame has been drawn.
+    pub screenshot_requested: bool,
+
+    /// Watches the `--scene` file so that edits to `particle_count` and the force parameters apply live.
+    pub config_watcher: Option<FileWatcher>,
+
+    /// Where the save/restore snapshot keys write to and read from.
+    pub snapshot_path: PathBuf,
+
+    /// Writes every rendered frame to disk when `--capture-dir` is given.
+    pub capture: Option<FrameCapture>,
+
+    /// Appends particle statistics to a CSV file every few simulation frames when `--diagnostics` is given.
+    pub diagnostics_log: Option<DiagnosticsLog>,
+
+    /// Offscreen render target, only present in headless mode.
+    pub framebuffer: Option<Framebuffer>,
+
+    /// Declared last so the EGL context outlives every GL object above.
+    pub headless: Option<HeadlessContext>,
 }
 
-impl<const LEN: usize> GlobalState<LEN> {
-    pub fn new() -> Result<Self> {
-        let triplet = init_glfw()?;
+impl GlobalState {
+    pub fn new(options: &Options) -> Result<Self> {
+        let width = options.width.map_or(CANVAS_WIDTH, |w| w as i32);
+        let height = options.height.map_or(CANVAS_HEIGHT, |h| h as i32);
 
-        let [vshader, fshader, gshader, cshader] = get_all_shaders()?;
+        let (triplet, headless) = if options.headless {
+            (None, Some(init_headless()?))
+        } else {
+            let title = options.title.as_deref().unwrap_or(DEFAULT_TITLE);
+            let vsync = options.vsync.unwrap_or(true);
+            (Some(init_glfw(width as u32, height as u32, title, options.fullscreen.unwrap_or(false), vsync)?), None)
+        };
+        gl_initialize_debugging(log::enabled(LogLevel::Debug));
+
+        let framebuffer = if options.headless {
+            Some(Framebuffer::new(width, height)?)
+        } else {
+            None
+        };
+
+        let [vshader, fshader, gshader, cshader] = match options.shader_dir {
+            Some(ref dir) => get_all_shaders_from_dir(dir)?,
+            None => get_all_shaders()?,
+        };
 
         let draw_program = Program::try_from_shaders(&[&vshader, &fshader, &gshader])?;
         let compute_program = Program::try_from_shaders(&[&cshader])?;
+        PARTICLE_LAYOUT.check_against(&compute_program)?;
 
         let draw_uniforms = UniformLocations::new(&draw_program)?;
         let compute_uniforms = UniformLocations::new(&compute_program)?;
 
-        let render_state = RenderState::new(1280, 720, &draw_program, &compute_program);
+        let seed = options
+            .seed
+            .unwrap_or_else(|| MagicSeed::u64().expect("fix your OS, couldn't get OS entropy"));
+        info!("Seed: {}", seed);
+
+        let particle_count = options.particle_count.unwrap_or(DEFAULT_PARTICLE_COUNT);
+        let mut render_state = RenderState::new(
+            width as usize,
+            height as usize,
+            &draw_program,
+            &compute_program,
+            seed,
+            particle_count,
+        );
+        info!("Particles: {}", render_state.count());
+
+        // The window may not have been created at the requested size, and on high-DPI displays its
+        // framebuffer is larger than its size in screen coordinates.
+        if let Some(ref triplet) = triplet {
+            let (w, h) = triplet.window.get_framebuffer_size();
+            unsafe { gl::Viewport(0, 0, w, h) };
+            render_state.update_canvas_size(w as usize, h as usize);
 
-        Ok(Self {
-            triplet: Some(triplet),
+            let (w, h) = triplet.window.get_size();
+            render_state.coords.set_window_size(w as f32, h as f32);
+        }
+
+        render_state.params = options.sim_params();
+        info!(
+            "Force model: {} (G = {}, softening = {}, theta = {})",
+            render_state.params.force_model, render_state.params.g, render_state.params.softening, render_state.params.theta
+        );
+
+        let backend = options.backend.create();
+        info!("Simulation backend: {}", backend.kind());
+        info!("Integrator: {}", render_state.params.integrator);
+        let boundary = &render_state.params.boundary;
+        if boundary.kind != BoundaryKind::None {
+            info!("Boundary: {} ({:?} to {:?})", boundary.kind, boundary.min, boundary.max);
+        }
+
+        render_state.emitters = Emitters::new(options.emitters.clone().unwrap_or_default());
+        render_state.initial_alive = options
+            .initial_particles
+            .or_else(|| (!render_state.emitters.is_empty()).then_some(0));
+        render_state.generator = Generator::new(options.initial.clone().unwrap_or_default())?;
+        render_state.reset(&draw_program);
+        info!("Initial condition: {}", render_state.generator.condition());
+        if let Some(alive) = render_state.initial_alive {
+            info!("Initially alive: {}", alive);
+        }
+        if !render_state.emitters.is_empty() {
+            info!(
+                "Emitters: {} ({} particles/s)",
+                render_state.emitters.emitters().len(),
+                render_state.emitters.total_rate()
+            );
+        }
+
+        let gradient = match options.gradient {
+            Some(ref path) => Gradient::load(path)?,
+            None => Gradient::default(),
+        };
+        let color_mode = options.color_mode.unwrap_or_default();
+        info!("Color mode: {}", color_mode);
+
+        let capture = options.capture.clone().map(FrameCapture::new).transpose()?;
+        let diagnostics_log = options
+            .diagnostics
+            .as_deref()
+            .map(|path| {
+                DiagnosticsLog::create(
+                    path,
+                    options.diagnostics_every.unwrap_or(DEFAULT_DIAGNOSTICS_EVERY),
+                    options.histogram_bins.unwrap_or(DEFAULT_HISTOGRAM_BINS),
+                )
+            })
+            .transpose()?;
+
+        let mut time = TimeControl::default();
+        time.fixed_dt = options.fixed_dt;
+        time.max_dt = options.max_dt.unwrap_or(DEFAULT_MAX_DT);
+        time.step_frames = options.step_frames.unwrap_or(DEFAULT_STEP_FRAMES);
+        time.set_scale(options.time_scale.unwrap_or(1.0));
+        time.substep_dt = options.substep_dt;
+        time.max_substeps = options.max_substeps.unwrap_or(DEFAULT_MAX_SUBSTEPS);
+        time.interpolate = options.interpolate.unwrap_or(false);
+
+        let keymap = match options.keymap {
+            Some(ref path) => Keymap::load(path)?,
+            None => Keymap::default(),
+        };
+
+        let mut gs = Self {
+            triplet,
             vshader,
             fshader,
             gshader,
@@ -54,34 +244,481 @@ impl<const LEN: usize> GlobalState<LEN> {
             compute_program,
             draw_uniforms,
             compute_uniforms,
+            shader_reload: options.shader_dir.clone().map(ShaderHotReload::new),
             render_state,
-        })
+            backend,
+            color_mode,
+            color_range: options.color_range.unwrap_or(DEFAULT_COLOR_RANGE),
+            gradient: GradientTexture::new(&gradient),
+            outline: DomainOutline::new()?,
+            quad_size: options.quad_size.unwrap_or(DEFAULT_QUAD_SIZE),
+            clear_color: options.clear_color.unwrap_or(DEFAULT_CLEAR_COLOR),
+            tools: Tools::default(),
+            last_cursor: (0.0, 0.0),
+            keymap,
+            time,
+            screenshot_requested: false,
+            config_watcher: options.config.clone().map(FileWatcher::new),
+            snapshot_path: options.snapshot_path.clone(),
+            capture,
+            diagnostics_log,
+            framebuffer,
+            headless,
+        };
+
+        if let Some(ref path) = options.load_snapshot {
+            gs.load_snapshot(path)?;
+        }
+
+        let camera = &mut gs.render_state.camera;
+        if let Some(center) = options.camera_center {
+            camera.center = center.into();
+        }
+        camera.zoom = options.camera_zoom.unwrap_or(camera.zoom);
+        if options.fit_view.unwrap_or(false) {
+            gs.render_state.fit_camera_to_particles();
+        }
+        gs.render_state.update_cursor();
+
+        Ok(gs)
     }
 
-    pub fn all_uniforms(&self) -> [UniformLocations; 2] {
-        [self.draw_uniforms.clone(), self.compute_uniforms.clone()]
+    pub const fn all_uniforms(&self) -> [&UniformLocations; 2] {
+        [&self.draw_uniforms, &self.compute_uniforms]
     }
 
     /// # Safety
     ///
     /// gs.triplet must be Some
-    pub unsafe fn main_loop(&mut self) {
+    pub unsafe fn main_loop(&mut self) -> Result<()> {
         unsafe { main_loop(self) }
     }
-}
 
-pub fn initialize_opengl() {
-    unsafe {
-        gl::Viewport(0, 0, 1280, 720);
+    pub fn save_snapshot(&mut self, path: &Path) -> Result<()> {
+        self.render_state
+            .snapshot()
+            .save(path)
+            .with_context(|| format!("Failed to save snapshot {}", path.display()))?;
+        info!("Saved snapshot {} (t = {:.3}s)", path.display(), self.render_state.sim_time);
+        Ok(())
     }
 
-    gl_initialize_debugging();
+    pub fn load_snapshot(&mut self, path: &Path) -> Result<()> {
+        let snapshot = Snapshot::load(path).with_context(|| format!("Failed to load snapshot {}", path.display()))?;
+        self.render_state.restore(snapshot, &self.draw_program);
+        info!("Restored snapshot {} (t = {:.3}s)", path.display(), self.render_state.sim_time);
+        Ok(())
+    }
+
+    /// Recompiles and relinks both programs from the watched shader directory.
+    ///
+    /// Nothing is replaced unless every shader compiles and both programs link, so a broken edit
+    /// leaves the last working programs in place.
+    pub fn reload_shaders(&mut self) -> Result<()> {
+        let Some(ref reload) = self.shader_reload else {
+            return Ok(());
+        };
+
+        let [vshader, fshader, gshader, cshader] = get_all_shaders_from_dir(reload.dir())?;
+
+        let draw_program = Program::try_from_shaders(&[&vshader, &fshader, &gshader])?;
+        let compute_program = Program::try_from_shaders(&[&cshader])?;
+        PARTICLE_LAYOUT.check_against(&compute_program)?;
+
+        let draw_uniforms = UniformLocations::new(&draw_program)?;
+        let compute_uniforms = UniformLocations::new(&compute_program)?;
+
+        self.vshader = vshader;
+        self.fshader = fshader;
+        self.gshader = gshader;
+        self.cshader = cshader;
+        self.draw_program = draw_program;
+        self.compute_program = compute_program;
+        self.draw_uniforms = draw_uniforms;
+        self.compute_uniforms = compute_uniforms;
+
+        Ok(())
+    }
+
+    pub fn poll_shaders(&mut self) {
+        if !self.shader_reload.as_mut().is_some_and(|reload| reload.poll()) {
+            return;
+        }
+
+        match self.reload_shaders() {
+            Ok(()) => info!("Reloaded shaders"),
+            Err(e) => warn!("Shader reload failed, keeping the previous programs:\n{:#}", e),
+        }
+    }
+
+    /// Reallocates the particle buffers for `count` particles.
+    pub fn resize_particles(&mut self, count: usize) {
+        self.render_state.resize(count, &self.draw_program);
+        info!("Particles: {}", self.render_state.count());
+    }
+
+    /// Re-reads the config file if it changed on disk and applies the settings that can change live.
+    pub fn poll_config(&mut self) {
+        let Some(ref mut watcher) = self.config_watcher else {
+            return;
+        };
+        if !watcher.has_changed() {
+            return;
+        }
+
+        match Config::load(watcher.path()) {
+            Ok(config) => {
+                if let Some(count) = config.particle_count {
+                    self.resize_particles(count);
+                }
+
+                let params = &mut self.render_state.params;
+                params.force_model = config.force_model.unwrap_or(params.force_model);
+                params.g = config.gravity.unwrap_or(params.g);
+                params.softening = config.softening.unwrap_or(params.softening);
+                params.theta = config.theta.unwrap_or(params.theta);
+                params.integrator = config.integrator.unwrap_or(params.integrator);
+                params.boundary.kind = config.boundary.unwrap_or(params.boundary.kind);
+                if let Some([min_x, min_y, max_x, max_y]) = config.domain {
+                    params.boundary.min = (min_x, min_y);
+                    params.boundary.max = (max_x, max_y);
+                }
+                params.boundary.restitution = config.restitution.unwrap_or(params.boundary.restitution);
+                params.boundary.strength = config.contain_strength.unwrap_or(params.boundary.strength);
+                // Any of these change `acc`, so the stored one can't be reused.
+                self.render_state.forces_valid = false;
+
+                if let Some(initial) = config.initial {
+                    match Generator::new(initial) {
+                        Ok(generator) => self.render_state.generator = generator,
+                        Err(e) => warn!("{:#}", e),
+                    }
+                }
+
+                if let Some(emitters) = config.emitters {
+                    match emitters.iter().try_for_each(Emitter::validate) {
+                        Ok(()) => self.render_state.emitters = Emitters::new(emitters),
+                        Err(e) => warn!("{:#}", e),
+                    }
+                }
+
+                self.color_mode = config.color_mode.unwrap_or(self.color_mode);
+                self.color_range = config.color_range.unwrap_or(self.color_range);
+                self.quad_size = config.quad_size.unwrap_or(self.quad_size);
+                self.clear_color = config.clear_color.unwrap_or(self.clear_color);
+
+                if let Some(scale) = config.time_scale {
+                    self.time.set_scale(scale);
+                }
+            }
+            Err(e) => warn!("{:#}", e),
+        }
+    }
+
+    /// Renders `frames` frames into the offscreen framebuffer and returns.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the state wasn't created in headless mode.
+    pub fn run_headless(&mut self, frames: u32) -> Result<()> {
+        let framebuffer = self.framebuffer.take().expect("run_headless requires a headless GlobalState");
+        framebuffer.bind();
+
+        let started = Instant::now();
+        let result = (0..frames).try_for_each(|_| self.render_frame());
+        unsafe { gl::Finish() };
+
+        Framebuffer::unbind();
+        self.framebuffer = Some(framebuffer);
+        result?;
+
+        info!("Rendered {} frames in {:.3}s", frames, started.elapsed().as_secs_f32());
+
+        Ok(())
+    }
+
+    /// Measures the particles, reading them back from the GPU first unless the CPU backend already has them.
+    pub fn diagnostics(&mut self, bins: usize) -> Diagnostics {
+        if self.backend.kind() == BackendKind::Gpu {
+            self.render_state.read_back_buffer_data();
+        }
+
+        let (x, y) = self.render_state.cursor_position;
+        Diagnostics::compute(
+            self.render_state.buffer.data(),
+            &self.render_state.params,
+            &Vector2::new(x, y),
+            self.render_state.sim_time,
+            bins,
+        )
+    }
+
+    /// Writes a row to the `--diagnostics` log if one is due, called once per frame the simulation advanced.
+    fn log_diagnostics(&mut self) {
+        let Some(ref mut log) = self.diagnostics_log else {
+            return;
+        };
+        if !log.tick() {
+            return;
+        }
+
+        let bins = log.bins();
+        let diagnostics = self.diagnostics(bins);
+        if let Some(ref mut log) = self.diagnostics_log {
+            if let Err(e) = log.write(&diagnostics) {
+                warn!("Diagnostics log stopped: {:#}", e);
+                self.diagnostics_log = None;
+            }
+        }
+    }
+
+    fn print_diagnostics(&mut self) {
+        let d = self.diagnostics(0);
+        info!(
+            "t = {:.3}s, {} particles: energy {:.6e} (kinetic {:.6e}, potential {:.6e}), momentum ({:.3e}, {:.3e}), \
+             angular momentum {:.3e}, center of mass ({:.4}, {:.4}), bounds ({:.3}, {:.3}) to ({:.3}, {:.3})",
+            d.time,
+            d.particle_count,
+            d.total_energy(),
+            d.kinetic_energy,
+            d.potential_energy,
+            d.momentum.0,
+            d.momentum.1,
+            d.angular_momentum,
+            d.center_of_mass.0,
+            d.center_of_mass.1,
+            d.bbox_min.x,
+            d.bbox_min.y,
+            d.bbox_max.x,
+            d.bbox_max.y
+        );
+    }
+
+    /// Works out the force tool for this step and runs the spawn tool.
+    fn apply_tools(&mut self, dt: f32) {
+        let cursor = self.render_state.cursor_position;
+        let velocity = if dt > 0.0 {
+            ((cursor.0 - self.last_cursor.0) / dt, (cursor.1 - self.last_cursor.1) / dt)
+        } else {
+            (0.0, 0.0)
+        };
+        self.last_cursor = cursor;
+
+        self.render_state.tool_force = self.tools.force(cursor, velocity);
+
+        let spawn = self.tools.spawn_count(dt);
+        if spawn > 0 {
+            let radius = self.tools.current().radius;
+            let free = match self.backend.kind() {
+                BackendKind::Cpu => self.render_state.free_slots.len(),
+                BackendKind::Gpu => self.render_state.read_free_count(),
+            };
+            self.render_state.spawn(spawn, cursor, radius, free, &self.draw_program);
+        }
+    }
+
+    /// Selects `kind` as the mouse tool.
+    pub fn select_tool(&mut self, kind: ToolKind) {
+        self.tools.selected = kind;
+        self.print_tool();
+    }
+
+    pub fn print_tool(&self) {
+        let settings = self.tools.current();
+        info!("Tool: {} (strength {}, radius {})", self.tools.selected, settings.strength, settings.radius);
+    }
+
+    /// Advances the simulation by one frame and draws the particles into whichever framebuffer is
+    /// currently bound.
+    ///
+    /// How far the simulation advances is up to [`TimeControl::frame_dt`], split into substeps by
+    /// [`TimeControl::substeps`]. `uTime` is the simulated time, so it stands still while paused and
+    /// doesn't depend on how fast frames are drawn.
+    pub fn render_frame(&mut self) -> Result<()> {
+        self.poll_config();
+        self.poll_shaders();
+
+        let wall_dt = self.render_state.last_update.elapsed();
+        self.render_state.last_update = Instant::now();
+
+        let step_dt = self.time.frame_dt(wall_dt.as_secs_f32());
+        let advance = step_dt.is_some();
+        let dt = step_dt.unwrap_or(0.0);
+        let (substeps, substep_dt) = if advance { self.time.substeps(dt) } else { (0, 0.0) };
+        let time = self.render_state.sim_time as f32;
+
+        self.apply_tools(dt);
+
+        let uniforms = self.all_uniforms();
+        uniforms.set("uDt", substep_dt)?;
+        uniforms.set("uAlpha", self.time.alpha())?;
+        uniforms.set("uMousePos", self.render_state.cursor_position)?;
+        uniforms.set("uQuadSize", self.quad_size)?;
+        uniforms.set("uViewProjection", self.render_state.camera.view_projection())?;
+        uniforms.set("uTime", time)?;
+        uniforms.set("uForceModel", self.render_state.params.force_model as i32)?;
+        uniforms.set("uG", self.render_state.params.g)?;
+        uniforms.set("uSoftening", self.render_state.params.softening)?;
+        uniforms.set("uTheta", self.render_state.params.theta)?;
+        let boundary = &self.render_state.params.boundary;
+        uniforms.set("uBoundary", boundary.kind as i32)?;
+        uniforms.set("uDomainMin", boundary.min)?;
+        uniforms.set("uDomainMax", boundary.max)?;
+        uniforms.set("uRestitution", boundary.restitution)?;
+        uniforms.set("uContainStrength", boundary.strength)?;
+        uniforms.set("uColorMode", self.color_mode as i32)?;
+        uniforms.set("uColorRange", self.color_range)?;
+        uniforms.set("uParticleCount", self.render_state.count() as i32)?;
+        uniforms.set("uGradient", GRADIENT_TEXTURE_UNIT as i32)?;
+
+        uniforms.set("uToolActive", self.render_state.tool_force.is_some())?;
+        if let Some(ref tool) = self.render_state.tool_force {
+            uniforms.set("uToolKind", tool.kind as i32)?;
+            uniforms.set("uToolPos", (tool.pos.x, tool.pos.y))?;
+            uniforms.set("uToolVelocity", (tool.velocity.x, tool.velocity.y))?;
+            uniforms.set("uToolStrength", tool.strength)?;
+            uniforms.set("uToolRadius", tool.radius)?;
+        }
+
+        for substep in 0..substeps {
+            if self.time.interpolate && substep + 1 == substeps {
+                self.render_state.save_previous_state();
+            }
+            self.backend
+                .step(&mut self.render_state, &self.compute_program, &self.compute_uniforms, substep_dt)?;
+            self.render_state.sim_time += substep_dt as f64;
+        }
+        if substeps > 0 {
+            self.log_diagnostics();
+        }
+
+        self.draw_program.use_program();
+        self.gradient.bind();
+
+        unsafe {
+            let [r, g, b] = self.clear_color;
+            gl::ClearColor(r, g, b, 1.0);
+            gl::Clear(gl::COLOR_BUFFER_BIT);
+
+            gl::BindVertexArray(self.render_state.vao);
+            gl::DrawArrays(gl::POINTS, 0, self.render_state.count() as i32);
+        }
+
+        if self.render_state.params.boundary.kind != BoundaryKind::None {
+            self.outline
+                .draw(&self.render_state.params.boundary, self.render_state.camera.view_projection())?;
+        }
+
+        if let Some(ref mut capture) = self.capture {
+            if let Err(e) = capture.on_frame(self.render_state.can_w, self.render_state.can_h) {
+                warn!("Frame capture stopped: {:#}", e);
+                self.capture = None;
+            }
+        }
+
+        if mem::take(&mut self.screenshot_requested) {
+            match self.screenshot() {
+                Ok(path) => info!("Saved screenshot {}", path.display()),
+                Err(e) => warn!("{:#}", e),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes the frame that was just drawn to `screenshot_<unix time>.png`, in the capture directory
+    /// if there is one.
+    fn screenshot(&self) -> Result<PathBuf> {
+        let (width, height) = (self.render_state.can_w, self.render_state.can_h);
+        let mut pixels = Vec::new();
+        read_pixels_rgb(width, height, &mut pixels);
+        flip_rows(&mut pixels, width * 3);
+
+        let stamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis();
+        let name = format!("screenshot_{}.png", stamp);
+        let path = match self.capture {
+            Some(ref capture) => capture.settings().dir.join(name),
+            None => PathBuf::from(name),
+        };
+
+        write_png(&path, width, height, &pixels).with_context(|| format!("Failed to write {}", path.display()))?;
+        Ok(path)
+    }
+
+    /// Runs a keyboard action. [`InputAction::Quit`] is left to the caller, which owns the window.
+    pub fn perform(&mut self, action: InputAction) {
+        match action {
+            InputAction::Quit => {}
+            InputAction::Pause => {
+                self.time.toggle_pause();
+                info!("{}", if self.time.paused { "Paused" } else { "Resumed" });
+            }
+            InputAction::Step => self.time.step(1),
+            InputAction::StepMany => self.time.step(self.time.step_frames),
+            InputAction::SlowDown | InputAction::SpeedUp | InputAction::NormalSpeed => {
+                let scale = match action {
+                    InputAction::SlowDown => self.time.scale() / TIME_SCALE_STEP,
+                    InputAction::SpeedUp => self.time.scale() * TIME_SCALE_STEP,
+                    _ => 1.0,
+                };
+                self.time.set_scale(scale);
+                info!("Time scale: {:.2}x", self.time.scale());
+            }
+            InputAction::Reset => {
+                self.render_state.reset(&self.draw_program);
+                info!("Reset with seed {}", self.render_state.seed);
+            }
+            InputAction::SaveSnapshot => {
+                let path = self.snapshot_path.clone();
+                if let Err(e) = self.save_snapshot(&path) {
+                    warn!("{:#}", e);
+                }
+            }
+            InputAction::LoadSnapshot => {
+                let path = self.snapshot_path.clone();
+                if let Err(e) = self.load_snapshot(&path) {
+                    warn!("{:#}", e);
+                }
+            }
+            InputAction::Screenshot => self.screenshot_requested = true,
+            InputAction::MoreParticles => self.resize_particles(self.render_state.count().saturating_mul(2)),
+            InputAction::FewerParticles => self.resize_particles(self.render_state.count() / 2),
+            InputAction::CycleColorMode => {
+                self.color_mode = self.color_mode.next();
+                info!("Color mode: {}", self.color_mode);
+            }
+            InputAction::FitView => self.render_state.fit_camera_to_particles(),
+            InputAction::PrintDiagnostics => self.print_diagnostics(),
+            InputAction::ToolAttract => self.select_tool(ToolKind::Attract),
+            InputAction::ToolRepel => self.select_tool(ToolKind::Repel),
+            InputAction::ToolVortex => self.select_tool(ToolKind::Vortex),
+            InputAction::ToolDrag => self.select_tool(ToolKind::Drag),
+            InputAction::ToolSpawn => self.select_tool(ToolKind::Spawn),
+        }
+    }
+}
+
+/// Compiles and links every shader on a headless context, from `shader_dir` or the built-in sources. The
+/// domain outline's shaders are always built in.
+pub fn validate_shaders(shader_dir: Option<&Path>) -> Result<()> {
+    let _headless = init_headless()?;
+
+    let [vshader, fshader, gshader, cshader] = match shader_dir {
+        Some(dir) => get_all_shaders_from_dir(dir)?,
+        None => get_all_shaders()?,
+    };
+    Program::try_from_shaders(&[&vshader, &fshader, &gshader])?;
+    let compute_program = Program::try_from_shaders(&[&cshader])?;
+    PARTICLE_LAYOUT.check_against(&compute_program)?;
+    DomainOutline::new()?;
+
+    Ok(())
 }
 
 /// # Safety
 ///
 /// gs.triplet must be Some
-unsafe fn main_loop<const LEN: usize>(gs: &mut GlobalState<LEN>) {
+unsafe fn main_loop(gs: &mut GlobalState) -> Result<()> {
     debug_assert!(gs.triplet.is_some(), "UNDEFINED BEHAVIOR: triplet must be Some");
     let GLFWTriplet {
         mut glfw,
@@ -92,65 +729,68 @@ unsafe fn main_loop<const LEN: usize>(gs: &mut GlobalState<LEN>) {
     let mut fps_counter = 0;
     let mut fps_counter_last_printed = Instant::now();
 
-    while !window.should_close() {
+    let result = loop {
+        if window.should_close() {
+            break Ok(());
+        }
+
         glfw.poll_events();
         for (_, event) in glfw::flush_messages(&events) {
-            handle_event(&mut window, event, &mut gs.render_state);
+            handle_event(&mut window, event, gs);
         }
 
         window.swap_buffers();
 
-        let dt = gs.render_state.last_update.elapsed();
-        gs.render_state.last_update = Instant::now();
-
-        gs.all_uniforms().as_slice().set_dt(dt.as_secs_f32());
-        gs.all_uniforms().as_slice().set_mouse_pos(gs.render_state.cursor_position);
-        gs.all_uniforms().as_slice().set_quad_size(0.03);
-        gs.all_uniforms().as_slice().set_time(gs.render_state.start.elapsed().as_secs_f32());
-
-        gs.compute_program.use_program();
-        gs.render_state.dispatch_compute_call();
-        gs.draw_program.use_program();
-
-        // render_state.update_physics(dt.as_secs_f32());
+        if let Err(e) = gs.render_frame() {
+            break Err(e);
+        }
 
         fps_counter += 1;
         if fps_counter_last_printed.elapsed() >= Duration::from_secs(1) {
-            println!("FPS: {}", fps_counter);
+            debug!("FPS: {}", fps_counter);
             fps_counter = 0;
             fps_counter_last_printed = Instant::now();
         }
-
-        unsafe {
-            gl::ClearColor(0.2, 0.3, 0.3, 1.0);
-            gl::Clear(gl::COLOR_BUFFER_BIT);
-
-            gl::BindVertexArray(gs.render_state.vao);
-            gl::DrawArrays(gl::POINTS, 0, gs.render_state.count() as i32);
-        }
-    }
+    };
 
     gs.triplet = Some(GLFWTriplet { glfw, window, events });
+
+    result
 }
 
-fn init_glfw() -> Result<GLFWTriplet> {
+/// Opens the window, fullscreen at the primary monitor's resolution if asked to and there is one.
+fn init_glfw(width: u32, height: u32, title: &str, fullscreen: bool, vsync: bool) -> Result<GLFWTriplet> {
     let mut glfw = glfw::init(fail_on_errors).context("Failed to initialize GLFW")?;
 
     glfw.window_hint(glfw::WindowHint::ContextVersion(4, 3));
     glfw.window_hint(glfw::WindowHint::OpenGlProfile(glfw::OpenGlProfileHint::Core));
 
     let (mut window, events) = glfw
-        .create_window(1280, 720, "Rust Game", glfw::WindowMode::Windowed)
+        .with_primary_monitor(|glfw, monitor| {
+            let monitor = monitor.filter(|_| fullscreen);
+            let (width, height) = monitor
+                .as_ref()
+                .and_then(|m| m.get_video_mode())
+                .map_or((width, height), |mode| (mode.width, mode.height));
+            let mode = monitor.map_or(glfw::WindowMode::Windowed, |m| glfw::WindowMode::FullScreen(m));
+            glfw.create_window(width, height, title, mode)
+        })
         .context("Failed to create GLFW window")?;
 
     window.make_current();
+    glfw.set_swap_interval(if vsync { SwapInterval::Sync(1) } else { SwapInterval::None });
     window.set_key_polling(true);
+    window.set_framebuffer_size_polling(true);
+    window.set_size_polling(true);
+    window.set_cursor_pos_polling(true);
+    window.set_mouse_button_polling(true);
+    window.set_scroll_polling(true);
 
     gl::load_with(|symbol| {
         let addr = glfw.get_proc_address_raw(symbol);
 
         if addr.is_null() {
-            println!("Failed to load symbol: {}", symbol);
+            warn!("Failed to load symbol: {}", symbol);
             ptr::null()
         } else {
             addr as *const _
@@ -166,10 +806,13 @@ pub struct GLFWTriplet {
     pub events: GlfwReceiver<(f64, WindowEvent)>,
 }
 
-fn handle_event<const LEN: usize>(window: &mut glfw::Window, event: glfw::WindowEvent, render_state: &mut RenderState<LEN>) {
+fn handle_event(window: &mut glfw::Window, event: glfw::WindowEvent, gs: &mut GlobalState) {
     match event {
-        #[allow(unused_variables)]
-        WindowEvent::Key(key, scode, action, modif) => {}
+        WindowEvent::Key(..) => match gs.keymap.translate(&event) {
+            Some(InputAction::Quit) => window.set_should_close(true),
+            Some(action) => gs.perform(action),
+            None => {}
+        },
 
         WindowEvent::Close => {
             window.set_should_close(true);
@@ -179,11 +822,38 @@ fn handle_event<const LEN: usize>(window: &mut glfw::Window, event: glfw::Window
             unsafe {
                 gl::Viewport(0, 0, w, h);
             }
-            render_state.update_canvas_size(w as usize, h as usize);
+            gs.render_state.update_canvas_size(w as usize, h as usize);
+        }
+
+        WindowEvent::Size(w, h) => {
+            gs.render_state.coords.set_window_size(w as f32, h as f32);
         }
 
         WindowEvent::CursorPos(x, y) => {
-            render_state.cursor_position = (x as f32, y as f32);
+            gs.render_state.set_cursor_screen(x as f32, y as f32);
+        }
+
+        // Dragging with the right button pans the view.
+        WindowEvent::MouseButton(MouseButton::Button2, Action::Press, _) => gs.render_state.begin_pan(),
+        WindowEvent::MouseButton(MouseButton::Button2, Action::Release, _) => gs.render_state.end_pan(),
+
+        // Shift+scroll changes the tool strength, Ctrl+scroll its radius, plain scrolling zooms.
+        WindowEvent::Scroll(_, y) => {
+            let held = |keys: [Key; 2]| keys.iter().any(|&key| window.get_key(key) == Action::Press);
+
+            if held([Key::LeftShift, Key::RightShift]) {
+                gs.tools.current_mut().strength *= TOOL_SCROLL_STEP.powf(y as f32);
+                gs.print_tool();
+            } else if held([Key::LeftControl, Key::RightControl]) {
+                gs.tools.current_mut().radius *= TOOL_SCROLL_STEP.powf(y as f32);
+                gs.print_tool();
+            } else {
+                gs.render_state.zoom_at_cursor(ZOOM_STEP.powf(y as f32));
+            }
+        }
+
+        WindowEvent::MouseButton(MouseButton::Button1, action, _) => {
+            gs.tools.engaged = action != Action::Release;
         }
 
         _ => {}
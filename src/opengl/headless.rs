@@ -0,0 +1,87 @@
+use core::ptr;
+
+use anyhow::{anyhow, Context as AnyhowContextTrait, Result};
+use khronos_egl as egl;
+
+use crate::warn;
+
+/// `EGL_PLATFORM_SURFACELESS_MESA`, from `EGL_MESA_platform_surfaceless`.
+const PLATFORM_SURFACELESS_MESA: egl::Enum = 0x31DD;
+
+/// A window-less OpenGL 4.3 core context on an EGL surfaceless display.
+///
+/// This works without any display server (e.g. Mesa llvmpipe on CI), so all
+/// drawing has to go into a [`Framebuffer`](super::framebuffer::Framebuffer).
+pub struct HeadlessContext {
+    egl: egl::DynamicInstance<egl::EGL1_5>,
+    display: egl::Display,
+    context: egl::Context,
+}
+
+pub fn init_headless() -> Result<HeadlessContext> {
+    // Safety: libEGL is a system library that implements the EGL API.
+    let egl = unsafe { egl::DynamicInstance::<egl::EGL1_5>::load_required() }.map_err(|e| anyhow!("Failed to load libEGL: {}", e))?;
+
+    // Safety: the surfaceless platform takes no native display.
+    let display = unsafe { egl.get_platform_display(PLATFORM_SURFACELESS_MESA, egl::DEFAULT_DISPLAY, &[egl::ATTRIB_NONE]) }
+        .context("Failed to get a surfaceless EGL display")?;
+    egl.initialize(display).context("Failed to initialize EGL")?;
+
+    let config_attribs = [
+        egl::SURFACE_TYPE,
+        egl::PBUFFER_BIT,
+        egl::RENDERABLE_TYPE,
+        egl::OPENGL_BIT,
+        egl::RED_SIZE,
+        8,
+        egl::GREEN_SIZE,
+        8,
+        egl::BLUE_SIZE,
+        8,
+        egl::ALPHA_SIZE,
+        8,
+        egl::NONE,
+    ];
+    let config = egl
+        .choose_first_config(display, &config_attribs)
+        .context("Failed to choose an EGL config")?
+        .context("No EGL config supports desktop OpenGL")?;
+
+    egl.bind_api(egl::OPENGL_API).context("Failed to bind the OpenGL API")?;
+
+    let context_attribs = [
+        egl::CONTEXT_MAJOR_VERSION,
+        4,
+        egl::CONTEXT_MINOR_VERSION,
+        3,
+        egl::CONTEXT_OPENGL_PROFILE_MASK,
+        egl::CONTEXT_OPENGL_CORE_PROFILE_BIT,
+        egl::NONE,
+    ];
+    let context = egl
+        .create_context(display, config, None, &context_attribs)
+        .context("Failed to create an OpenGL 4.3 core EGL context")?;
+
+    egl.make_current(display, None, None, Some(context))
+        .context("Failed to make the EGL context current")?;
+
+    gl::load_with(|symbol| {
+        egl.get_proc_address(symbol).map_or_else(
+            || {
+                warn!("Failed to load symbol: {}", symbol);
+                ptr::null()
+            },
+            |addr| addr as *const _,
+        )
+    });
+
+    Ok(HeadlessContext { egl, display, context })
+}
+
+impl Drop for HeadlessContext {
+    fn drop(&mut self) {
+        let _ = self.egl.make_current(self.display, None, None, None);
+        let _ = self.egl.destroy_context(self.display, self.context);
+        let _ = self.egl.terminate(self.display);
+    }
+}
@@ -0,0 +1,28 @@
+use std::path::{Path, PathBuf};
+
+use crate::opengl::shader::SHADER_FILES;
+use crate::watch::FileWatcher;
+
+/// Watches the shader sources in a directory so they can be recompiled while the program runs.
+#[derive(Debug)]
+pub struct ShaderHotReload {
+    dir: PathBuf,
+    watchers: Vec<FileWatcher>,
+}
+
+impl ShaderHotReload {
+    pub fn new(dir: PathBuf) -> Self {
+        let watchers = SHADER_FILES.iter().map(|&(file, _)| FileWatcher::new(dir.join(file))).collect();
+        Self { dir, watchers }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Returns `true` if any shader source changed since the last call.
+    pub fn poll(&mut self) -> bool {
+        // No short-circuiting, every watcher has to take note of its file's new modification time.
+        self.watchers.iter_mut().fold(false, |changed, watcher| watcher.has_changed() | changed)
+    }
+}
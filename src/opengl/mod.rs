@@ -1,5 +1,10 @@
+pub mod capture;
+pub mod color;
 pub mod debugging;
+pub mod framebuffer;
 pub mod global_state;
+pub mod headless;
+pub mod hot_reload;
 pub mod program;
 pub mod render;
 pub mod shader;
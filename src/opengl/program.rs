@@ -28,7 +28,8 @@ impl Display for ProgramError {
     }
 }
 
-#[derive(Debug, Clone)]
+/// Not `Clone`: the program object is deleted on drop, so a copy of the handle would dangle.
+#[derive(Debug)]
 pub struct Program {
     handle: u32,
 }
@@ -0,0 +1,162 @@
+/// Closest and farthest the camera can zoom, keeps the view matrix invertible.
+pub const MIN_ZOOM: f32 = 1e-6;
+pub const MAX_ZOOM: f32 = 1e6;
+
+/// Fraction of the view "fit all" leaves around the particles' bounding box.
+const FIT_MARGIN: f32 = 0.1;
+
+/// A 2D orthographic camera. At zoom 1 the view spans `[-1, 1]` vertically around `center`, and
+/// `aspect` times that horizontally, so world units are square on screen.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Camera {
+    /// World position at the middle of the view.
+    pub center: (f32, f32),
+    pub zoom: f32,
+    /// Framebuffer width divided by height.
+    pub aspect: f32,
+}
+
+impl Camera {
+    pub const fn new(aspect: f32) -> Self {
+        Self {
+            center: (0.0, 0.0),
+            zoom: 1.0,
+            aspect,
+        }
+    }
+
+    /// World units from the center to the edges of the view, horizontally and vertically.
+    pub fn half_extent(&self) -> (f32, f32) {
+        (self.aspect / self.zoom, 1.0 / self.zoom)
+    }
+
+    pub fn world_to_ndc(&self, x: f32, y: f32) -> (f32, f32) {
+        let (hx, hy) = self.half_extent();
+        ((x - self.center.0) / hx, (y - self.center.1) / hy)
+    }
+
+    pub fn ndc_to_world(&self, x: f32, y: f32) -> (f32, f32) {
+        let (hx, hy) = self.half_extent();
+        (x.mul_add(hx, self.center.0), y.mul_add(hy, self.center.1))
+    }
+
+    /// World to clip space, column-major for `uViewProjection`.
+    pub fn view_projection(&self) -> [[f32; 4]; 4] {
+        let (hx, hy) = self.half_extent();
+        let (sx, sy) = (1.0 / hx, 1.0 / hy);
+
+        [
+            [sx, 0.0, 0.0, 0.0],
+            [0.0, sy, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [-self.center.0 * sx, -self.center.1 * sy, 0.0, 1.0],
+        ]
+    }
+
+    /// Moves the view so that the world point `from` ends up where `to` is now.
+    pub fn pan(&mut self, from: (f32, f32), to: (f32, f32)) {
+        self.center.0 += from.0 - to.0;
+        self.center.1 += from.1 - to.1;
+    }
+
+    /// Multiplies the zoom by `factor`, keeping the world point `anchor` at the same place on screen.
+    pub fn zoom_at(&mut self, anchor: (f32, f32), factor: f32) {
+        let old_zoom = self.zoom;
+        self.zoom = (self.zoom * factor).clamp(MIN_ZOOM, MAX_ZOOM);
+
+        let ratio = old_zoom / self.zoom;
+        self.center.0 = (self.center.0 - anchor.0).mul_add(ratio, anchor.0);
+        self.center.1 = (self.center.1 - anchor.1).mul_add(ratio, anchor.1);
+    }
+
+    /// Centers the view on the box from `min` to `max` and zooms so that all of it is visible.
+    pub fn fit(&mut self, min: (f32, f32), max: (f32, f32)) {
+        self.center = ((min.0 + max.0) * 0.5, (min.1 + max.1) * 0.5);
+
+        let width = (max.0 - min.0).max(f32::EPSILON);
+        let height = (max.1 - min.1).max(f32::EPSILON);
+        let zoom = (2.0 * self.aspect / width).min(2.0 / height) * (1.0 - FIT_MARGIN);
+        self.zoom = zoom.clamp(MIN_ZOOM, MAX_ZOOM);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(found: (f32, f32), expected: (f32, f32), what: &str) {
+        assert!(
+            (found.0 - expected.0).abs() < 1e-4 && (found.1 - expected.1).abs() < 1e-4,
+            "{}: expected {:?}, found {:?}",
+            what,
+            expected,
+            found
+        );
+    }
+
+    #[test]
+    fn pan_moves_from_onto_to() {
+        let mut camera = Camera::new(2.0);
+        let grabbed = camera.world_to_ndc(0.5, 0.25);
+        camera.pan((0.5, 0.25), (1.5, -0.75));
+        assert_eq!(camera.center, (-1.0, 1.0), "the center moves against the drag");
+        assert_close(camera.world_to_ndc(-0.5, 1.25), grabbed, "the grabbed point follows the cursor");
+    }
+
+    #[test]
+    fn zoom_keeps_the_anchor_in_place() {
+        let mut camera = Camera {
+            center: (1.0, 2.0),
+            zoom: 1.5,
+            aspect: 16.0 / 9.0,
+        };
+        let anchor = (1.7, 1.4);
+        let before = camera.world_to_ndc(anchor.0, anchor.1);
+        for factor in [2.0, 0.25, 1.1] {
+            camera.zoom_at(anchor, factor);
+            assert_close(camera.world_to_ndc(anchor.0, anchor.1), before, "the anchor moved on screen");
+        }
+        let expected = 1.5 * 2.0 * 0.25 * 1.1;
+        assert!((camera.zoom / expected - 1.0).abs() < 1e-5, "zoom should multiply, got {}", camera.zoom);
+    }
+
+    #[test]
+    fn zoom_is_clamped() {
+        let mut camera = Camera::new(1.0);
+        camera.zoom_at((0.3, 0.3), 1e9);
+        assert_eq!(camera.zoom, MAX_ZOOM, "zoomed in past the maximum");
+        camera.zoom_at((0.3, 0.3), 1e-20);
+        assert_eq!(camera.zoom, MIN_ZOOM, "zoomed out past the minimum");
+    }
+
+    #[test]
+    fn fit_shows_the_whole_box() {
+        for aspect in [0.5, 1.0, 16.0 / 9.0] {
+            for (min, max) in [((-3.0, -1.0), (5.0, 2.0)), ((0.0, -10.0), (0.5, 10.0)), ((2.0, 2.0), (2.0, 2.0))] {
+                let mut camera = Camera::new(aspect);
+                camera.fit(min, max);
+                assert_eq!(camera.center, ((min.0 + max.0) * 0.5, (min.1 + max.1) * 0.5), "the box should be centered");
+                for corner in [min, max, (min.0, max.1), (max.0, min.1)] {
+                    let (x, y) = camera.world_to_ndc(corner.0, corner.1);
+                    assert!(x.abs() <= 1.0 && y.abs() <= 1.0, "{:?} falls outside the view at aspect {}", corner, aspect);
+                }
+                assert!(camera.zoom.is_finite() && camera.zoom > 0.0, "a degenerate box gave zoom {}", camera.zoom);
+            }
+        }
+    }
+
+    #[test]
+    fn view_projection_matches_world_to_ndc() {
+        let camera = Camera {
+            center: (-4.0, 0.5),
+            zoom: 3.0,
+            aspect: 1.25,
+        };
+        let m = camera.view_projection();
+        for (x, y) in [(0.0, 0.0), (-4.0, 0.5), (2.5, -7.0)] {
+            let clip = (m[0][0].mul_add(x, m[3][0]), m[1][1].mul_add(y, m[3][1]));
+            assert_close(clip, camera.world_to_ndc(x, y), "the matrix disagrees with world_to_ndc");
+        }
+        assert_eq!((m[0][1], m[1][0], m[2][2], m[3][3]), (0.0, 0.0, 1.0, 1.0), "not a plain scale and translation");
+    }
+}
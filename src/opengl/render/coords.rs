@@ -0,0 +1,156 @@
+use crate::opengl::render::camera::Camera;
+
+/// Conversions between the coordinate spaces of the window and the simulation.
+///
+/// - screen: cursor positions as reported by GLFW, origin top-left, Y down, in screen coordinates.
+/// - pixels: framebuffer pixels, origin top-left, Y down. Differs from screen on high-DPI displays.
+/// - NDC: `[-1, 1]` on both axes, Y up.
+/// - world: where particle positions live, mapped to NDC by the [`Camera`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CoordinateSpace {
+    /// Window size in screen coordinates.
+    window_size: (f32, f32),
+    /// Framebuffer size in pixels.
+    framebuffer_size: (f32, f32),
+}
+
+impl CoordinateSpace {
+    /// A space where screen coordinates and pixels are the same, e.g. an offscreen framebuffer.
+    pub const fn new(width: f32, height: f32) -> Self {
+        Self {
+            window_size: (width, height),
+            framebuffer_size: (width, height),
+        }
+    }
+
+    pub const fn window_size(&self) -> (f32, f32) {
+        self.window_size
+    }
+
+    pub const fn framebuffer_size(&self) -> (f32, f32) {
+        self.framebuffer_size
+    }
+
+    /// Zero sizes (a minimized window) are ignored so the conversions never divide by zero.
+    pub const fn set_window_size(&mut self, width: f32, height: f32) {
+        if width > 0.0 && height > 0.0 {
+            self.window_size = (width, height);
+        }
+    }
+
+    /// Zero sizes (a minimized window) are ignored so the conversions never divide by zero.
+    pub const fn set_framebuffer_size(&mut self, width: f32, height: f32) {
+        if width > 0.0 && height > 0.0 {
+            self.framebuffer_size = (width, height);
+        }
+    }
+
+    /// Framebuffer pixels per screen coordinate on each axis, the DPI scale.
+    pub fn pixel_ratio(&self) -> (f32, f32) {
+        (
+            self.framebuffer_size.0 / self.window_size.0,
+            self.framebuffer_size.1 / self.window_size.1,
+        )
+    }
+
+    pub fn screen_to_pixels(&self, x: f32, y: f32) -> (f32, f32) {
+        let (sx, sy) = self.pixel_ratio();
+        (x * sx, y * sy)
+    }
+
+    pub fn pixels_to_screen(&self, x: f32, y: f32) -> (f32, f32) {
+        let (sx, sy) = self.pixel_ratio();
+        (x / sx, y / sy)
+    }
+
+    pub fn pixels_to_ndc(&self, x: f32, y: f32) -> (f32, f32) {
+        let (w, h) = self.framebuffer_size;
+        ((x / w).mul_add(2.0, -1.0), (y / h).mul_add(-2.0, 1.0))
+    }
+
+    pub fn ndc_to_pixels(&self, x: f32, y: f32) -> (f32, f32) {
+        let (w, h) = self.framebuffer_size;
+        ((x + 1.0) * 0.5 * w, (1.0 - y) * 0.5 * h)
+    }
+
+    pub fn screen_to_ndc(&self, x: f32, y: f32) -> (f32, f32) {
+        let (px, py) = self.screen_to_pixels(x, y);
+        self.pixels_to_ndc(px, py)
+    }
+
+    pub fn ndc_to_screen(&self, x: f32, y: f32) -> (f32, f32) {
+        let (px, py) = self.ndc_to_pixels(x, y);
+        self.pixels_to_screen(px, py)
+    }
+
+    /// Where the cursor at `(x, y)` points to in the simulation.
+    pub fn screen_to_world(&self, camera: &Camera, x: f32, y: f32) -> (f32, f32) {
+        let (nx, ny) = self.screen_to_ndc(x, y);
+        camera.ndc_to_world(nx, ny)
+    }
+
+    pub fn world_to_screen(&self, camera: &Camera, x: f32, y: f32) -> (f32, f32) {
+        let (nx, ny) = camera.world_to_ndc(x, y);
+        self.ndc_to_screen(nx, ny)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(found: (f32, f32), expected: (f32, f32), what: &str) {
+        assert!(
+            (found.0 - expected.0).abs() < 1e-4 && (found.1 - expected.1).abs() < 1e-4,
+            "{}: expected {:?}, found {:?}",
+            what,
+            expected,
+            found
+        );
+    }
+
+    /// An 800x600 window on a display with twice as many pixels per screen coordinate.
+    fn high_dpi() -> CoordinateSpace {
+        let mut coords = CoordinateSpace::new(800.0, 600.0);
+        coords.set_framebuffer_size(1600.0, 1200.0);
+        coords
+    }
+
+    #[test]
+    fn screen_corners_map_to_ndc_with_y_up() {
+        let coords = high_dpi();
+        assert_eq!(coords.pixel_ratio(), (2.0, 2.0), "the framebuffer is twice the window");
+        assert_close(coords.screen_to_pixels(400.0, 150.0), (800.0, 300.0), "screen to pixels");
+        assert_close(coords.screen_to_ndc(0.0, 0.0), (-1.0, 1.0), "top-left corner");
+        assert_close(coords.screen_to_ndc(800.0, 600.0), (1.0, -1.0), "bottom-right corner");
+        assert_close(coords.screen_to_ndc(400.0, 300.0), (0.0, 0.0), "center");
+        assert_close(coords.pixels_to_ndc(1600.0, 0.0), (1.0, 1.0), "top-right pixel");
+    }
+
+    #[test]
+    fn conversions_round_trip() {
+        let camera = Camera {
+            center: (3.0, -2.0),
+            zoom: 0.5,
+            aspect: 4.0 / 3.0,
+        };
+        for coords in [CoordinateSpace::new(640.0, 480.0), high_dpi()] {
+            for point in [(0.0, 0.0), (123.5, 456.25), (800.0, 600.0)] {
+                let (px, py) = coords.screen_to_pixels(point.0, point.1);
+                assert_close(coords.pixels_to_screen(px, py), point, "screen -> pixels -> screen");
+                let (nx, ny) = coords.screen_to_ndc(point.0, point.1);
+                assert_close(coords.ndc_to_screen(nx, ny), point, "screen -> NDC -> screen");
+                let (wx, wy) = coords.screen_to_world(&camera, point.0, point.1);
+                assert_close(coords.world_to_screen(&camera, wx, wy), point, "screen -> world -> screen");
+            }
+        }
+    }
+
+    #[test]
+    fn zero_sizes_are_ignored() {
+        let mut coords = high_dpi();
+        coords.set_window_size(0.0, 0.0);
+        coords.set_framebuffer_size(0.0, 600.0);
+        assert_eq!(coords, high_dpi(), "a minimized window shouldn't change the sizes");
+    }
+}
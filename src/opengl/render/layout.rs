@@ -0,0 +1,193 @@
+use alloc::ffi::CString;
+use core::error::Error;
+use core::ffi::c_void;
+use core::fmt::{self, Display};
+use core::mem;
+use core::ptr;
+
+use gl::types::{GLenum, GLint};
+
+use crate::opengl::program::Program;
+use crate::opengl::render::particle::Particle;
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum VertexLayoutError {
+    /// The compute shader's `particles[]` elements aren't `expected` bytes apart.
+    Stride { expected: usize, found: usize },
+    /// A field sits at a different offset in the compute shader than in the Rust struct.
+    Offset { field: &'static str, expected: usize, found: usize },
+}
+
+impl Error for VertexLayoutError {}
+
+impl Display for VertexLayoutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::Stride { expected, found } => write!(
+                f,
+                "Particle is {} bytes in Rust but the std430 array stride in compute.glsl is {}",
+                expected, found
+            ),
+            Self::Offset { field, expected, found } => write!(
+                f,
+                "Particle::{} is at offset {} in Rust but at offset {} in compute.glsl",
+                field, expected, found
+            ),
+        }
+    }
+}
+
+/// One field of an interleaved vertex buffer, bound to a vertex shader input.
+#[derive(Debug, Clone, Copy)]
+pub struct VertexAttribute {
+    /// `layout(location = ...)` of the input in `vertex.glsl`.
+    pub location: u32,
+    /// Field name, the same in Rust and in the `Particle` struct of `compute.glsl`.
+    pub name: &'static str,
+    /// Number of components.
+    pub components: i32,
+    /// Byte offset inside the struct.
+    pub offset: usize,
+    /// Read as unsigned integers into a `uint` input instead of as floats.
+    pub integer: bool,
+}
+
+/// How one element of an interleaved vertex buffer is laid out.
+#[derive(Debug, Clone, Copy)]
+pub struct VertexLayout {
+    pub stride: usize,
+    pub attributes: &'static [VertexAttribute],
+}
+
+/// [`Particle`] as seen by the draw pass: `aPos`, `aVel` and `aAcc` at locations 0, 1 and 2, and `aAlive`
+/// at location 4.
+pub const PARTICLE_LAYOUT: VertexLayout = VertexLayout {
+    stride: mem::size_of::<Particle>(),
+    attributes: &[
+        VertexAttribute {
+            location: 0,
+            name: "pos",
+            components: 2,
+            offset: mem::offset_of!(Particle, pos),
+            integer: false,
+        },
+        VertexAttribute {
+            location: 1,
+            name: "vel",
+            components: 2,
+            offset: mem::offset_of!(Particle, vel),
+            integer: false,
+        },
+        VertexAttribute {
+            location: 2,
+            name: "acc",
+            components: 2,
+            offset: mem::offset_of!(Particle, acc),
+            integer: false,
+        },
+        VertexAttribute {
+            location: 4,
+            name: "alive",
+            components: 1,
+            offset: mem::offset_of!(Particle, alive),
+            integer: true,
+        },
+    ],
+};
+
+/// `pos` read from the previous-state buffer as `aPrevPos`, for interpolating between substeps.
+pub const PREV_POS_ATTRIBUTE: VertexAttribute = VertexAttribute {
+    location: 3,
+    name: "pos",
+    components: 2,
+    offset: mem::offset_of!(Particle, pos),
+    integer: false,
+};
+
+impl VertexLayout {
+    /// Points the attributes of the currently bound VAO at the buffer bound to `GL_ARRAY_BUFFER`.
+    ///
+    /// # Safety
+    ///
+    /// A VAO and an array buffer must be bound.
+    pub unsafe fn apply(&self) {
+        for attr in self.attributes {
+            unsafe { self.apply_attribute(attr) };
+        }
+    }
+
+    /// Points a single attribute at the buffer bound to `GL_ARRAY_BUFFER`, which has this layout.
+    ///
+    /// # Safety
+    ///
+    /// A VAO and an array buffer must be bound.
+    pub unsafe fn apply_attribute(&self, attr: &VertexAttribute) {
+        let offset = ptr::without_provenance::<c_void>(attr.offset);
+        unsafe {
+            if attr.integer {
+                gl::VertexAttribIPointer(attr.location, attr.components, gl::UNSIGNED_INT, self.stride as i32, offset);
+            } else {
+                gl::VertexAttribPointer(attr.location, attr.components, gl::FLOAT, gl::FALSE, self.stride as i32, offset);
+            }
+            gl::EnableVertexAttribArray(attr.location);
+        }
+    }
+
+    /// Checks the layout against the `particles[]` SSBO of `compute_program`, as the driver laid it out.
+    ///
+    /// Fields the driver reports as inactive can't be checked and are skipped.
+    pub fn check_against(&self, compute_program: &Program) -> Result<(), VertexLayoutError> {
+        let mut stride = None;
+
+        for attr in self.attributes {
+            let Some([offset, array_stride]) = buffer_variable(compute_program, &format!("particles[0].{}", attr.name)) else {
+                continue;
+            };
+
+            if offset != attr.offset {
+                return Err(VertexLayoutError::Offset {
+                    field: attr.name,
+                    expected: attr.offset,
+                    found: offset,
+                });
+            }
+            stride = Some(array_stride);
+        }
+
+        match stride {
+            Some(found) if found != self.stride => Err(VertexLayoutError::Stride {
+                expected: self.stride,
+                found,
+            }),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Offset and top-level array stride of a buffer variable, `None` if it isn't active.
+fn buffer_variable(program: &Program, name: &str) -> Option<[usize; 2]> {
+    let name = CString::new(name).ok()?;
+    let props: [GLenum; 2] = [gl::OFFSET, gl::TOP_LEVEL_ARRAY_STRIDE];
+    let mut values: [GLint; 2] = [0; 2];
+
+    unsafe {
+        let index = gl::GetProgramResourceIndex(program.handle(), gl::BUFFER_VARIABLE, name.as_ptr());
+        if index == gl::INVALID_INDEX {
+            return None;
+        }
+
+        gl::GetProgramResourceiv(
+            program.handle(),
+            gl::BUFFER_VARIABLE,
+            index,
+            props.len() as i32,
+            props.as_ptr(),
+            values.len() as i32,
+            ptr::null_mut(),
+            values.as_mut_ptr(),
+        );
+    }
+
+    Some(values.map(|v| v as usize))
+}
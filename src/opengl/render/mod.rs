@@ -1,2 +1,7 @@
+pub mod camera;
+pub mod coords;
+pub mod layout;
+pub mod outline;
 pub mod particle;
-pub mod renderstate;
\ No newline at end of file
+pub mod renderstate;
+pub mod snapshot;
\ No newline at end of file
@@ -0,0 +1,89 @@
+use core::mem;
+use core::ptr;
+
+use anyhow::Result;
+
+use crate::opengl::program::Program;
+use crate::opengl::shader::{Shader, OUTLINE_F_SOURCE, OUTLINE_V_SOURCE};
+use crate::opengl::uniform::UniformLocations;
+use crate::simulation::boundary::Boundary;
+
+/// Color of the domain outline.
+pub const OUTLINE_COLOR: [f32; 4] = [0.85, 0.85, 0.85, 1.0];
+
+/// Draws the simulation domain as a rectangle of lines in world space.
+pub struct DomainOutline {
+    program: Program,
+    uniforms: UniformLocations,
+    vao: u32,
+    vbo: u32,
+    /// Corners currently in `vbo`, so they're only uploaded when the domain changes.
+    corners: [[f32; 2]; 4],
+}
+
+impl DomainOutline {
+    pub fn new() -> Result<Self> {
+        let vshader = Shader::try_from_source(OUTLINE_V_SOURCE, gl::VERTEX_SHADER)?;
+        let fshader = Shader::try_from_source(OUTLINE_F_SOURCE, gl::FRAGMENT_SHADER)?;
+        let program = Program::try_from_shaders(&[&vshader, &fshader])?;
+        let uniforms = UniformLocations::new(&program)?;
+
+        let mut vao = 0;
+        let mut vbo = 0;
+        unsafe {
+            gl::GenVertexArrays(1, &raw mut vao);
+            gl::BindVertexArray(vao);
+
+            gl::GenBuffers(1, &raw mut vbo);
+            gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
+            gl::BufferData(gl::ARRAY_BUFFER, mem::size_of::<[[f32; 2]; 4]>() as isize, ptr::null(), gl::DYNAMIC_DRAW);
+            gl::VertexAttribPointer(0, 2, gl::FLOAT, gl::FALSE, mem::size_of::<[f32; 2]>() as i32, ptr::null());
+            gl::EnableVertexAttribArray(0);
+
+            gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+            gl::BindVertexArray(0);
+        }
+
+        Ok(Self {
+            program,
+            uniforms,
+            vao,
+            vbo,
+            corners: [[f32::NAN; 2]; 4],
+        })
+    }
+
+    /// Draws the edges of `boundary`'s domain into the bound framebuffer.
+    pub fn draw(&mut self, boundary: &Boundary, view_projection: [[f32; 4]; 4]) -> Result<()> {
+        let (min, max) = (boundary.min, boundary.max);
+        let corners = [[min.0, min.1], [max.0, min.1], [max.0, max.1], [min.0, max.1]];
+
+        unsafe {
+            if corners != self.corners {
+                gl::BindBuffer(gl::ARRAY_BUFFER, self.vbo);
+                gl::BufferSubData(gl::ARRAY_BUFFER, 0, mem::size_of_val(&corners) as isize, corners.as_ptr().cast());
+                gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+                self.corners = corners;
+            }
+
+            self.program.use_program();
+            self.uniforms.set("uViewProjection", view_projection)?;
+            self.uniforms.set("uColor", OUTLINE_COLOR)?;
+
+            gl::BindVertexArray(self.vao);
+            gl::DrawArrays(gl::LINE_LOOP, 0, 4);
+            gl::BindVertexArray(0);
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for DomainOutline {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteVertexArrays(1, &raw const self.vao);
+            gl::DeleteBuffers(1, &raw const self.vbo);
+        }
+    }
+}
@@ -1,45 +1,105 @@
-use boxarray::BoxedArray;
 use voxell_rng::rng::XorShift128;
 
 use crate::vec2::Vector2;
 
-pub struct RenderData<const LEN: usize> {
-    data: BoxedArray<LEN, Particle>,
+pub struct RenderData {
+    data: Vec<Particle>,
 }
 
-#[derive(Debug, Clone, Default)]
-#[repr(C)]
+/// One element of the particle SSBO, the `Particle` struct of `compute.glsl`.
+///
+/// Aligned to 8 bytes because std430 rounds the struct up to a multiple of its `vec2` alignment.
+#[derive(Debug, Clone)]
+#[repr(C, align(8))]
 pub struct Particle {
-    pos: Vector2,
-    vel: Vector2,
-    acc: Vector2,
+    pub pos: Vector2,
+    pub vel: Vector2,
+    pub acc: Vector2,
+    /// Simulated seconds since the particle was spawned.
+    pub age: f32,
+    /// Seconds the particle lives for, 0 for forever.
+    pub lifetime: f32,
+    /// 0 for a free slot that isn't simulated or drawn, waiting for an emitter to reuse it.
+    pub alive: u32,
 }
 
-impl<const LEN: usize> RenderData<LEN> {
-    
-    pub fn new(rng: &mut XorShift128) -> Self {
-        let mut data: Vec<Particle> = Vec::with_capacity(LEN);
-
-        for _ in 0..LEN {
-            data.push(Particle {
-                pos: Vector2::new(rng.next_f32(), rng.next_f32()),
-                vel: Vector2::new(rng.next_f32(), rng.next_f32()),
-                acc: Vector2::new(rng.next_f32(), rng.next_f32()),
-            });
+impl Particle {
+    pub fn random(rng: &mut XorShift128) -> Self {
+        Self {
+            pos: Vector2::new(rng.next_f32(), rng.next_f32()),
+            vel: Vector2::new(rng.next_f32(), rng.next_f32()),
+            acc: Vector2::new(rng.next_f32(), rng.next_f32()),
+            ..Self::default()
+        }
+    }
+
+    pub const fn is_alive(&self) -> bool {
+        self.alive != 0
+    }
+}
+
+/// A live, immortal particle at rest at the origin.
+impl Default for Particle {
+    fn default() -> Self {
+        Self {
+            pos: Vector2::default(),
+            vel: Vector2::default(),
+            acc: Vector2::default(),
+            age: 0.0,
+            lifetime: 0.0,
+            alive: 1,
+        }
+    }
+}
+
+impl RenderData {
+    pub fn new(rng: &mut XorShift128, count: usize) -> Self {
+        let mut data: Vec<Particle> = Vec::with_capacity(count);
+
+        for _ in 0..count {
+            data.push(Particle::random(rng));
         }
 
-        let bs = data.into_boxed_slice();
-        let data = BoxedArray::try_from_boxed_slice(bs).unwrap();
         Self { data }
     }
 
-    
-    pub const fn data(&self) -> &BoxedArray<LEN, Particle> {
+    pub const fn from_particles(particles: Vec<Particle>) -> Self {
+        Self { data: particles }
+    }
+
+    pub const fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn push(&mut self, particle: Particle) {
+        self.data.push(particle);
+    }
+
+    /// Drops particles from the end until there are at most `count`.
+    pub fn truncate(&mut self, count: usize) {
+        self.data.truncate(count);
+    }
+
+    pub fn extend(&mut self, particles: Vec<Particle>) {
+        self.data.extend(particles);
+    }
+
+    pub fn data(&self) -> &[Particle] {
         &self.data
     }
 
-    
-    pub fn data_mut(&mut self) -> &mut BoxedArray<LEN, Particle> {
+    pub fn data_mut(&mut self) -> &mut [Particle] {
         &mut self.data
     }
+
+    /// Marks every particle from index `alive` on as a free slot.
+    pub fn kill_from(&mut self, alive: usize) {
+        for p in self.data.iter_mut().skip(alive) {
+            p.alive = 0;
+        }
+    }
 }
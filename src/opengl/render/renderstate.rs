@@ -3,80 +3,494 @@ use core::mem;
 use core::ptr;
 use std::time::Instant;
 
-use voxell_rng::{getrandom::MagicSeed, rng::XorShift128};
+use voxell_rng::rng::XorShift128;
 
 use crate::opengl::program::Program;
-use crate::opengl::render::particle::RenderData;
+use crate::opengl::render::camera::Camera;
+use crate::opengl::render::coords::CoordinateSpace;
+use crate::opengl::render::layout::{PARTICLE_LAYOUT, PREV_POS_ATTRIBUTE};
+use crate::opengl::render::particle::{Particle, RenderData};
+use crate::opengl::render::snapshot::Snapshot;
+use crate::simulation::emitter::{free_slots, prepend_batch, Emitter, EmitterShape, Emitters, SpawnBatch};
+use crate::simulation::initial::Generator;
+use crate::simulation::tools::ToolForce;
+use crate::simulation::SimParams;
 use crate::vec2::Vector2;
 
-pub struct RenderState<const OBJECTS_AMT: usize> {
-    pub buffer: RenderData<OBJECTS_AMT>,
+/// Upper bound for live resizing, keeps a stray key repeat from exhausting memory.
+pub const MAX_PARTICLES: usize = 1 << 24;
+
+/// Minimum number of work groups every implementation supports along one dispatch axis.
+const MAX_WORK_GROUPS_X: usize = u16::MAX as usize;
+
+/// Must match `local_size_x` in `compute.glsl`.
+const WORK_GROUP_SIZE: usize = 64;
+
+pub struct RenderState {
+    pub buffer: RenderData,
     pub rng: XorShift128,
+    /// The seed `rng` was created from, recorded in snapshots.
+    pub seed: u64,
+    /// Simulated seconds since the particles were created.
+    pub sim_time: f64,
+    pub params: SimParams,
+    /// `acc` in the particle buffer is up to date with the positions and `params`, so integrators that
+    /// reuse it can skip their extra forces pass. Cleared whenever the particles are replaced.
+    pub forces_valid: bool,
+    /// Simulation steps taken so far, seeds the respawn positions of absorbing boundaries.
+    pub step_count: u32,
+    pub emitters: Emitters,
+    /// Lays out the particles on reset and the ones added by a resize.
+    pub generator: Generator,
+    /// Particles that start out alive after a reset, the rest are free slots for the emitters. All of
+    /// them when `None`.
+    pub initial_alive: Option<usize>,
+    /// Indices of the dead particles, the CPU backend's copy of the `FreeList` SSBO.
+    pub free_slots: Vec<u32>,
+    /// Spawns queued by the spawn tool for the next step, as a disk emitter and a count.
+    pub tool_spawns: Option<(Emitter, u32)>,
 
     pub last_update: Instant,
-    pub start: Instant,
 
+    /// Cursor position in world space, see [`CoordinateSpace::screen_to_world`].
     pub cursor_position: (f32, f32),
+    /// Cursor position in screen coordinates, as last reported by the window.
+    pub cursor_screen: (f32, f32),
+    /// World point grabbed when a pan drag started, kept under the cursor while dragging.
+    pub pan_anchor: Option<(f32, f32)>,
+    /// The force tool held down this frame, if any.
+    pub tool_force: Option<ToolForce>,
     pub unit_vec: Vector2,
 
     pub can_w: usize,
     pub can_h: usize,
+    pub coords: CoordinateSpace,
+    pub camera: Camera,
     pub vao: u32,
     pub vbo: u32,
+    /// Copy of the particles from before the last substep, drawn as `aPrevPos` for interpolation.
+    pub prev_vbo: u32,
+    /// `FreeList` SSBO of `compute.glsl` (binding 3): a count followed by the indices of the dead particles.
+    pub free_list: u32,
 }
 
-impl<const LEN: usize> RenderState<LEN> {
-    pub fn new(can_w: usize, can_h: usize, draw_program: &Program, _compute_program: &Program) -> Self {
-        let seed = [
-            MagicSeed::u64().expect("fix your OS, couldn't get OS entropy"),
-            MagicSeed::u64().expect("fix your OS, couldn't get OS entropy"),
-        ];
-        let mut rng = XorShift128::wrap(seed);
+impl RenderState {
+    pub fn new(can_w: usize, can_h: usize, draw_program: &Program, _compute_program: &Program, seed: u64, count: usize) -> Self {
+        let mut rng = seeded_rng(seed);
 
-        let data = RenderData::new(&mut rng);
+        let data = RenderData::new(&mut rng, count);
 
         let mut vao = 0;
         let mut vbo = 0;
+        let mut prev_vbo = 0;
 
-        initialize_buffers(draw_program, &data, &mut vao, &mut vbo);
+        let mut free_list = 0;
+
+        initialize_buffers(draw_program, &data, &mut vao, &mut vbo, &mut prev_vbo);
+        unsafe {
+            gl::GenBuffers(1, &raw mut free_list);
+        }
 
-        let start = Instant::now();
         let unit_vec = Vector2::new(0.1f32 / can_w as f32, 0.1f32 / can_w as f32);
-        Self {
+        let mut state = Self {
             buffer: data,
             vao,
             vbo,
+            prev_vbo,
+            free_list,
             rng,
-            start,
+            seed,
+            sim_time: 0.0,
+            params: SimParams::default(),
+            forces_valid: false,
+            step_count: 0,
+            emitters: Emitters::default(),
+            generator: Generator::default(),
+            initial_alive: None,
+            free_slots: Vec::new(),
+            tool_spawns: None,
             can_w,
             unit_vec,
             can_h,
+            coords: CoordinateSpace::new(can_w as f32, can_h as f32),
+            camera: Camera::new(can_w as f32 / can_h as f32),
             cursor_position: (0.0, 0.0),
+            cursor_screen: (0.0, 0.0),
+            pan_anchor: None,
+            tool_force: None,
             last_update: Instant::now(),
-        }
+        };
+        state.upload_free_list();
+        state
     }
 
     pub const fn count(&self) -> usize {
-        LEN
+        self.buffer.len()
     }
 
-    pub const fn update_canvas_size(&mut self, w: usize, h: usize) {
+    pub fn update_canvas_size(&mut self, w: usize, h: usize) {
         self.can_w = w;
         self.can_h = h;
         self.unit_vec = Vector2::new(0.1f32 / w as f32, 0.1f32 / h as f32);
+        self.coords.set_framebuffer_size(w as f32, h as f32);
+
+        let (fb_w, fb_h) = self.coords.framebuffer_size();
+        self.camera.aspect = fb_w / fb_h;
+        self.update_cursor();
+    }
+
+    /// Records a cursor move in screen coordinates, dragging the view along if a pan is in progress.
+    pub fn set_cursor_screen(&mut self, x: f32, y: f32) {
+        self.cursor_screen = (x, y);
+
+        if let Some(anchor) = self.pan_anchor {
+            let under_cursor = self.coords.screen_to_world(&self.camera, x, y);
+            self.camera.pan(anchor, under_cursor);
+        }
+
+        self.update_cursor();
+    }
+
+    pub const fn begin_pan(&mut self) {
+        self.pan_anchor = Some(self.cursor_position);
+    }
+
+    pub const fn end_pan(&mut self) {
+        self.pan_anchor = None;
+    }
+
+    /// Zooms by `factor` around the world point under the cursor.
+    pub fn zoom_at_cursor(&mut self, factor: f32) {
+        self.camera.zoom_at(self.cursor_position, factor);
+        self.update_cursor();
+    }
+
+    /// Reads the particles back and fits the camera to the bounding box of the live ones. Non-finite
+    /// positions are skipped.
+    pub fn fit_camera_to_particles(&mut self) {
+        self.read_back_buffer_data();
+
+        let mut min = (f32::INFINITY, f32::INFINITY);
+        let mut max = (f32::NEG_INFINITY, f32::NEG_INFINITY);
+        for p in self.buffer.data().iter().filter(|p| p.is_alive()) {
+            if !(p.pos.x.is_finite() && p.pos.y.is_finite()) {
+                continue;
+            }
+            min = (min.0.min(p.pos.x), min.1.min(p.pos.y));
+            max = (max.0.max(p.pos.x), max.1.max(p.pos.y));
+        }
+
+        if min.0 <= max.0 {
+            self.camera.fit(min, max);
+            self.update_cursor();
+        }
+    }
+
+    /// Recomputes the world-space cursor after the camera or the window changed.
+    pub fn update_cursor(&mut self) {
+        let (x, y) = self.cursor_screen;
+        self.cursor_position = self.coords.screen_to_world(&self.camera, x, y);
     }
 
     pub fn dispatch_compute_call(&self) {
+        self.dispatch_compute(self.count());
+    }
+
+    /// Runs the bound compute program with at least `invocations` invocations, for passes that aren't one
+    /// per particle.
+    pub fn dispatch_compute(&self, invocations: usize) {
         unsafe {
             gl::BindBufferBase(gl::SHADER_STORAGE_BUFFER, 0, self.vbo);
-            let num_groups = (LEN as f32 / 64.0).ceil() as u32;
-            gl::DispatchCompute(num_groups, 1, 1);
+            gl::BindBufferBase(gl::SHADER_STORAGE_BUFFER, 3, self.free_list);
+            gl::BindBufferBase(gl::SHADER_STORAGE_BUFFER, 5, self.prev_vbo);
+            let num_groups = invocations.div_ceil(WORK_GROUP_SIZE).max(1);
+            let groups_x = num_groups.min(MAX_WORK_GROUPS_X);
+            let groups_y = num_groups.div_ceil(groups_x);
+            gl::DispatchCompute(groups_x as u32, groups_y as u32, 1);
             gl::MemoryBarrier(gl::ALL_BARRIER_BITS);
         }
     }
+
+    /// Uploads the CPU-side particles into the SSBO/VBO.
+    pub fn update_buffer_data(&self) {
+        unsafe {
+            gl::BindBuffer(gl::ARRAY_BUFFER, self.vbo);
+            gl::BufferSubData(
+                gl::ARRAY_BUFFER,
+                0,
+                mem::size_of_val(self.buffer.data()) as isize,
+                self.buffer.data().as_ptr().cast::<c_void>(),
+            );
+            gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+        }
+    }
+
+    /// Copies the particles out of the SSBO into `buffer`, so the CPU side sees what the GPU computed.
+    pub fn read_back_buffer_data(&mut self) {
+        unsafe {
+            gl::MemoryBarrier(gl::BUFFER_UPDATE_BARRIER_BIT);
+            gl::BindBuffer(gl::ARRAY_BUFFER, self.vbo);
+            gl::GetBufferSubData(
+                gl::ARRAY_BUFFER,
+                0,
+                mem::size_of_val(self.buffer.data()) as isize,
+                self.buffer.data_mut().as_mut_ptr().cast::<c_void>(),
+            );
+            gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+        }
+    }
+
+    /// Reads the current GPU state back and packs it into a snapshot.
+    pub fn snapshot(&mut self) -> Snapshot {
+        self.read_back_buffer_data();
+        Snapshot::new(self.buffer.data().to_vec(), self.seed, self.sim_time, self.step_count)
+    }
+
+    /// Replaces the particles with the ones in `snapshot` and re-creates the GPU buffers from them.
+    pub fn restore(&mut self, snapshot: Snapshot, draw_program: &Program) {
+        self.buffer = RenderData::from_particles(snapshot.particles);
+        self.seed = snapshot.header.seed;
+        self.rng = seeded_rng(self.seed);
+        self.sim_time = snapshot.header.sim_time;
+        self.step_count = snapshot.header.step_count;
+
+        self.reallocate_buffers(draw_program);
+    }
+
+    /// Changes the particle count, keeping the current (GPU-side) state of the particles that remain
+    /// and laying out new ones with `generator`. The SSBO/VBO is reallocated to the new size.
+    pub fn resize(&mut self, count: usize, draw_program: &Program) {
+        let count = count.clamp(1, MAX_PARTICLES);
+        if count == self.count() {
+            return;
+        }
+
+        self.read_back_buffer_data();
+        if count < self.count() {
+            self.buffer.truncate(count);
+        } else {
+            let missing = count - self.count();
+            let added = self.generator.generate(&mut self.rng, missing, &self.params);
+            self.buffer.extend(added);
+        }
+        self.reallocate_buffers(draw_program);
+    }
+
+    /// Starts over with `count()` fresh particles laid out by `generator` from `seed`, of which the first
+    /// [`initial_alive`](Self::initial_alive) are alive.
+    pub fn reset(&mut self, draw_program: &Program) {
+        let count = self.count();
+        self.rng = seeded_rng(self.seed);
+        self.buffer = RenderData::from_particles(self.generator.generate(&mut self.rng, count, &self.params));
+        self.buffer.kill_from(self.initial_alive.unwrap_or(count));
+        self.sim_time = 0.0;
+        self.reallocate_buffers(draw_program);
+    }
+
+    /// Queues `count` particles at rest, spread uniformly over the disk of `radius` around `center`, for
+    /// the next step to spawn into free slots ahead of the emitters. `free` is the length of the backend's
+    /// free list, the buffer only grows when the queued spawns don't fit in it.
+    pub fn spawn(&mut self, count: u32, center: (f32, f32), radius: f32, free: usize, draw_program: &Program) {
+        let queued = self.tool_spawns.as_ref().map_or(0, |&(_, queued)| queued);
+        let emitter = Emitter {
+            shape: EmitterShape::Disk,
+            position: [center.0, center.1],
+            radius,
+            ..Emitter::default()
+        };
+        let count = queued.saturating_add(count);
+        self.tool_spawns = Some((emitter, count));
+
+        if count as usize > free {
+            self.grow(count as usize - free, free, draw_program);
+        }
+    }
+
+    /// This step's spawns: the spawn tool's, then the ones the emitters have due after `dt` seconds.
+    pub fn plan_spawns(&mut self, dt: f32) -> Vec<SpawnBatch> {
+        let batches = self.emitters.plan(dt);
+        match self.tool_spawns.take() {
+            Some((emitter, count)) => prepend_batch(batches, &emitter, count),
+            None => batches,
+        }
+    }
+
+    /// Adds at least `extra` dead particles at the end and appends them to the free lists, up to
+    /// [`MAX_PARTICLES`]. The buffer grows by half at a time so a held spawn tool rarely reallocates, and the
+    /// particles already there are copied on the GPU instead of being read back. `free` is the length of
+    /// the `FreeList` SSBO's list.
+    fn grow(&mut self, extra: usize, free: usize, draw_program: &Program) {
+        let old = self.count();
+        let new = (old + extra).max(old + old / 2).min(MAX_PARTICLES);
+        if new <= old {
+            return;
+        }
+
+        let dead = Particle {
+            alive: 0,
+            ..Particle::default()
+        };
+        self.buffer.extend(vec![dead; new - old]);
+        let added: Vec<u32> = (old as u32..new as u32).collect();
+        self.free_slots.extend_from_slice(&added);
+
+        let size = mem::size_of::<Particle>();
+        let new_range = &self.buffer.data()[old..];
+        let free_count = (free + added.len()) as u32;
+        unsafe {
+            gl::MemoryBarrier(gl::BUFFER_UPDATE_BARRIER_BIT);
+            for vbo in [&mut self.vbo, &mut self.prev_vbo] {
+                grow_buffer(vbo, old * size, new * size);
+                gl::BindBuffer(gl::ARRAY_BUFFER, *vbo);
+                gl::BufferSubData(gl::ARRAY_BUFFER, (old * size) as isize, mem::size_of_val(new_range) as isize, new_range.as_ptr().cast::<c_void>());
+            }
+
+            let index = mem::size_of::<u32>();
+            grow_buffer(&mut self.free_list, (old + 1) * index, (new + 1) * index);
+            gl::BindBuffer(gl::SHADER_STORAGE_BUFFER, self.free_list);
+            gl::BufferSubData(gl::SHADER_STORAGE_BUFFER, 0, index as isize, (&raw const free_count).cast::<c_void>());
+            gl::BufferSubData(
+                gl::SHADER_STORAGE_BUFFER,
+                ((free + 1) * index) as isize,
+                mem::size_of_val(added.as_slice()) as isize,
+                added.as_ptr().cast::<c_void>(),
+            );
+            gl::BindBuffer(gl::SHADER_STORAGE_BUFFER, 0);
+
+            gl::UseProgram(draw_program.handle());
+            gl::BindVertexArray(self.vao);
+            gl::BindBuffer(gl::ARRAY_BUFFER, self.vbo);
+            PARTICLE_LAYOUT.apply();
+            gl::BindBuffer(gl::ARRAY_BUFFER, self.prev_vbo);
+            PARTICLE_LAYOUT.apply_attribute(&PREV_POS_ATTRIBUTE);
+            gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+            gl::BindVertexArray(0);
+        }
+    }
+
+    /// Copies the current particles into `prev_vbo`, call right before the last substep of a frame.
+    pub fn save_previous_state(&self) {
+        unsafe {
+            gl::MemoryBarrier(gl::BUFFER_UPDATE_BARRIER_BIT);
+            gl::BindBuffer(gl::COPY_READ_BUFFER, self.vbo);
+            gl::BindBuffer(gl::COPY_WRITE_BUFFER, self.prev_vbo);
+            gl::CopyBufferSubData(
+                gl::COPY_READ_BUFFER,
+                gl::COPY_WRITE_BUFFER,
+                0,
+                0,
+                mem::size_of_val(self.buffer.data()) as isize,
+            );
+            gl::BindBuffer(gl::COPY_READ_BUFFER, 0);
+            gl::BindBuffer(gl::COPY_WRITE_BUFFER, 0);
+        }
+    }
+
+    /// Reads the length of the `FreeList` SSBO's list back from the GPU. Waits for the GPU to catch up, so
+    /// it's only done once per frame, for the spawn tool.
+    pub fn read_free_count(&self) -> usize {
+        let mut count = 0i32;
+        unsafe {
+            gl::MemoryBarrier(gl::BUFFER_UPDATE_BARRIER_BIT);
+            gl::BindBuffer(gl::SHADER_STORAGE_BUFFER, self.free_list);
+            gl::GetBufferSubData(gl::SHADER_STORAGE_BUFFER, 0, mem::size_of::<i32>() as isize, (&raw mut count).cast::<c_void>());
+            gl::BindBuffer(gl::SHADER_STORAGE_BUFFER, 0);
+        }
+        usize::try_from(count).unwrap_or(0)
+    }
+
+    /// Copies the given particles into `prev_vbo` as they are now, so they're drawn where they jumped to
+    /// instead of sliding there from where they were before the last substep.
+    pub fn reset_previous_positions(&self, indices: &[u32]) {
+        if indices.is_empty() {
+            return;
+        }
+
+        let particles = self.buffer.data();
+        let size = mem::size_of::<Particle>();
+        unsafe {
+            gl::BindBuffer(gl::ARRAY_BUFFER, self.prev_vbo);
+            for &idx in indices {
+                let idx = idx as usize;
+                gl::BufferSubData(gl::ARRAY_BUFFER, (idx * size) as isize, size as isize, (&raw const particles[idx]).cast::<c_void>());
+            }
+            gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+        }
+    }
+
+    fn reallocate_buffers(&mut self, draw_program: &Program) {
+        self.forces_valid = false;
+        unsafe {
+            gl::DeleteVertexArrays(1, &raw const self.vao);
+            gl::DeleteBuffers(1, &raw const self.vbo);
+            gl::DeleteBuffers(1, &raw const self.prev_vbo);
+        }
+        initialize_buffers(draw_program, &self.buffer, &mut self.vao, &mut self.vbo, &mut self.prev_vbo);
+        self.upload_free_list();
+    }
+
+    /// Rebuilds `free_slots` from the particles' `alive` flags and uploads it to the `FreeList` SSBO, sized
+    /// so that every particle could die.
+    fn upload_free_list(&mut self) {
+        self.free_slots = free_slots(self.buffer.data());
+
+        let mut contents = Vec::with_capacity(self.count() + 1);
+        contents.push(self.free_slots.len() as u32);
+        contents.extend_from_slice(&self.free_slots);
+        contents.resize(self.count() + 1, 0);
+
+        unsafe {
+            gl::BindBuffer(gl::SHADER_STORAGE_BUFFER, self.free_list);
+            gl::BufferData(
+                gl::SHADER_STORAGE_BUFFER,
+                mem::size_of_val(contents.as_slice()) as isize,
+                contents.as_ptr().cast::<c_void>(),
+                gl::DYNAMIC_COPY,
+            );
+            gl::BindBuffer(gl::SHADER_STORAGE_BUFFER, 0);
+        }
+    }
+}
+
+/// Replaces `buffer` with a new one of `size` bytes that starts with the first `kept` bytes of the old one,
+/// copied on the GPU. The rest is left undefined.
+///
+/// # Safety
+///
+/// `buffer` must be a buffer of at least `kept` bytes.
+unsafe fn grow_buffer(buffer: &mut u32, kept: usize, size: usize) {
+    let mut grown = 0;
+    unsafe {
+        gl::GenBuffers(1, &raw mut grown);
+        gl::BindBuffer(gl::COPY_WRITE_BUFFER, grown);
+        gl::BufferData(gl::COPY_WRITE_BUFFER, size as isize, ptr::null(), gl::DYNAMIC_COPY);
+        gl::BindBuffer(gl::COPY_READ_BUFFER, *buffer);
+        gl::CopyBufferSubData(gl::COPY_READ_BUFFER, gl::COPY_WRITE_BUFFER, 0, 0, kept as isize);
+        gl::BindBuffer(gl::COPY_READ_BUFFER, 0);
+        gl::BindBuffer(gl::COPY_WRITE_BUFFER, 0);
+        gl::DeleteBuffers(1, buffer);
+    }
+    *buffer = grown;
 }
 
-pub fn initialize_buffers<const LEN: usize>(draw_program: &Program, data: &RenderData<LEN>, vao: &mut u32, vbo: &mut u32) {
+/// Expands a single `u64` seed into an `XorShift128` state with splitmix64, so that nearby
+/// seeds still give unrelated streams and the state is never all zeroes.
+pub fn seeded_rng(seed: u64) -> XorShift128 {
+    let mut state = seed;
+    let mut splitmix = || {
+        state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    };
+
+    XorShift128::wrap([splitmix(), splitmix()])
+}
+
+/// Creates the particle VAO, the particle buffer `vbo` and the previous-state buffer `prev_vbo`, both
+/// filled with `data`.
+pub fn initialize_buffers(draw_program: &Program, data: &RenderData, vao: &mut u32, vbo: &mut u32, prev_vbo: &mut u32) {
     unsafe {
         gl::UseProgram(draw_program.handle());
 
@@ -86,12 +500,23 @@ pub fn initialize_buffers<const LEN: usize>(draw_program: &Program, data: &Rende
         gl::GenBuffers(1, vbo);
         gl::BindBuffer(gl::ARRAY_BUFFER, *vbo);
 
-        gl::VertexAttribPointer(0, 2, gl::FLOAT, gl::FALSE, 0, ptr::null());
-        gl::EnableVertexAttribArray(0);
+        PARTICLE_LAYOUT.apply();
+
+        gl::BufferData(
+            gl::ARRAY_BUFFER,
+            mem::size_of_val(data.data()) as isize,
+            data.data().as_ptr().cast::<c_void>(),
+            gl::STATIC_DRAW,
+        );
+
+        gl::GenBuffers(1, prev_vbo);
+        gl::BindBuffer(gl::ARRAY_BUFFER, *prev_vbo);
+
+        PARTICLE_LAYOUT.apply_attribute(&PREV_POS_ATTRIBUTE);
 
         gl::BufferData(
             gl::ARRAY_BUFFER,
-            mem::size_of_val(data.data().as_slice()) as isize,
+            mem::size_of_val(data.data()) as isize,
             data.data().as_ptr().cast::<c_void>(),
             gl::STATIC_DRAW,
         );
@@ -101,40 +526,13 @@ pub fn initialize_buffers<const LEN: usize>(draw_program: &Program, data: &Rende
     }
 }
 
-//
-// pub fn update_buffer_data(&self) {
-//     unsafe {
-//         gl::BindBuffer(gl::ARRAY_BUFFER, self.vbo);
-//         gl::BufferSubData(
-//             gl::ARRAY_BUFFER,
-//             0,
-//             mem::size_of_val(self.buf.data.as_slice()) as isize,
-//             self.buf.data.as_ptr().cast::<c_void>(),
-//         );
-//     }
-// }
-//
-// pub fn update_physics(&mut self, dt: f32) {
-//     self.buf.data.par_iter_mut().for_each(|obj| {
-//         obj.acc.set_vec(&obj.pos);
-//         obj.acc.one_over_d_sq(
-//             &Vector2 {
-//                 x: self.cursor_position.0,
-//                 y: self.cursor_position.1,
-//             },
-//             &self.unit_vec,
-//         );
-//         obj.vel.add(obj.acc.x * dt, obj.acc.y * dt);
-//         obj.pos.add(obj.vel.x * dt, obj.vel.y * dt);
-//     });
-//     self.update_buffer_data();
-// }
-
-impl<const LEN: usize> Drop for RenderState<LEN> {
+impl Drop for RenderState {
     fn drop(&mut self) {
         unsafe {
             gl::DeleteVertexArrays(1, &self.vao);
             gl::DeleteBuffers(1, &self.vbo);
+            gl::DeleteBuffers(1, &self.prev_vbo);
+            gl::DeleteBuffers(1, &self.free_list);
         }
     }
 }
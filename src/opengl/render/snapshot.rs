@@ -0,0 +1,351 @@
+use core::error::Error;
+use core::fmt::{self, Display};
+use core::mem;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use crate::opengl::render::particle::Particle;
+use crate::opengl::render::renderstate::MAX_PARTICLES;
+use crate::vec2::Vector2;
+
+pub const SNAPSHOT_MAGIC: [u8; 8] = *b"HNETSNAP";
+
+/// Bumped whenever the on-disk header or particle layout changes.
+pub const SNAPSHOT_VERSION: u32 = 3;
+
+/// Bytes before the first particle: magic, version, particle size, count, seed, time and step count.
+pub const SNAPSHOT_HEADER_SIZE: u64 = 44;
+
+/// Header size of version 1 and 2 snapshots, from before the step count was saved. Those load with a step
+/// count of 0.
+const V2_HEADER_SIZE: u64 = 40;
+
+/// Bytes per particle in version 1 snapshots, from before particles had lifetimes. Those still load,
+/// as live particles that never die.
+const V1_PARTICLE_SIZE: u32 = 24;
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum SnapshotError {
+    Io(io::Error),
+    BadMagic,
+    UnsupportedVersion(u32),
+    ParticleSizeMismatch { expected: u32, found: u32 },
+    /// The header claims more than [`MAX_PARTICLES`].
+    TooManyParticles(u64),
+    /// The header claims no particles at all.
+    NoParticles,
+    /// The file is shorter than its header says, in bytes.
+    Truncated { expected: u64, found: u64 },
+}
+
+impl Error for SnapshotError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            Self::Io(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::Io(_) => write!(f, "Snapshot I/O error"),
+            Self::BadMagic => write!(f, "Not a snapshot file (bad magic)"),
+            Self::UnsupportedVersion(v) => write!(f, "Unsupported snapshot version {} (expected {})", v, SNAPSHOT_VERSION),
+            Self::ParticleSizeMismatch { expected, found } => {
+                write!(f, "Snapshot particle size is {} bytes, expected {}", found, expected)
+            }
+            Self::TooManyParticles(count) => write!(f, "Snapshot has {} particles, at most {} are supported", count, MAX_PARTICLES),
+            Self::NoParticles => write!(f, "Snapshot has no particles"),
+            Self::Truncated { expected, found } => write!(f, "Snapshot is truncated, expected {} bytes, found {}", expected, found),
+        }
+    }
+}
+
+impl From<io::Error> for SnapshotError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+/// Fixed-size header at the start of every snapshot file. All fields are little-endian.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SnapshotHeader {
+    pub version: u32,
+    pub particle_size: u32,
+    pub count: u64,
+    pub seed: u64,
+    pub sim_time: f64,
+    /// Steps taken so far, which seed the respawns of absorbing boundaries and the emitters' spawns.
+    pub step_count: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    pub header: SnapshotHeader,
+    pub particles: Vec<Particle>,
+}
+
+impl Snapshot {
+    pub const fn new(particles: Vec<Particle>, seed: u64, sim_time: f64, step_count: u32) -> Self {
+        Self {
+            header: SnapshotHeader {
+                version: SNAPSHOT_VERSION,
+                particle_size: mem::size_of::<Particle>() as u32,
+                count: particles.len() as u64,
+                seed,
+                sim_time,
+                step_count,
+            },
+            particles,
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), SnapshotError> {
+        let mut out = BufWriter::new(File::create(path)?);
+        self.write_to(&mut out)?;
+        out.flush()?;
+        Ok(())
+    }
+
+    /// Reads a snapshot file, checking its length against the header before reading any particles.
+    pub fn load(path: &Path) -> Result<Self, SnapshotError> {
+        let file = File::open(path)?;
+        let found = file.metadata()?.len();
+        let mut r = BufReader::new(file);
+
+        let header = read_header(&mut r)?;
+        let header_size = if header.version < 3 { V2_HEADER_SIZE } else { SNAPSHOT_HEADER_SIZE };
+        let expected = header.count * u64::from(header.particle_size) + header_size;
+        if found < expected {
+            return Err(SnapshotError::Truncated { expected, found });
+        }
+
+        let particles = read_particles(&mut r, &header)?;
+        Ok(Self { header, particles })
+    }
+
+    pub fn write_to<W: Write>(&self, w: &mut W) -> Result<(), SnapshotError> {
+        w.write_all(&SNAPSHOT_MAGIC)?;
+        w.write_all(&self.header.version.to_le_bytes())?;
+        w.write_all(&self.header.particle_size.to_le_bytes())?;
+        w.write_all(&self.header.count.to_le_bytes())?;
+        w.write_all(&self.header.seed.to_le_bytes())?;
+        w.write_all(&self.header.sim_time.to_le_bytes())?;
+        w.write_all(&self.header.step_count.to_le_bytes())?;
+
+        for p in &self.particles {
+            for v in [&p.pos, &p.vel, &p.acc] {
+                w.write_all(&v.x.to_le_bytes())?;
+                w.write_all(&v.y.to_le_bytes())?;
+            }
+            w.write_all(&p.age.to_le_bytes())?;
+            w.write_all(&p.lifetime.to_le_bytes())?;
+            w.write_all(&p.alive.to_le_bytes())?;
+            // Padding, so records are `particle_size` bytes like in memory.
+            w.write_all(&[0; 4])?;
+        }
+
+        Ok(())
+    }
+
+    pub fn read_from<R: Read>(r: &mut R) -> Result<Self, SnapshotError> {
+        let header = read_header(r)?;
+        let particles = read_particles(r, &header)?;
+        Ok(Self { header, particles })
+    }
+}
+
+fn read_header<R: Read>(r: &mut R) -> Result<SnapshotHeader, SnapshotError> {
+    let mut magic = [0; 8];
+    r.read_exact(&mut magic)?;
+    if magic != SNAPSHOT_MAGIC {
+        return Err(SnapshotError::BadMagic);
+    }
+
+    let version = u32::from_le_bytes(read_array(r)?);
+    let expected_size = match version {
+        1 => V1_PARTICLE_SIZE,
+        2 | SNAPSHOT_VERSION => mem::size_of::<Particle>() as u32,
+        _ => return Err(SnapshotError::UnsupportedVersion(version)),
+    };
+
+    let particle_size = u32::from_le_bytes(read_array(r)?);
+    if particle_size != expected_size {
+        return Err(SnapshotError::ParticleSizeMismatch {
+            expected: expected_size,
+            found: particle_size,
+        });
+    }
+
+    let count = u64::from_le_bytes(read_array(r)?);
+    if count > MAX_PARTICLES as u64 {
+        return Err(SnapshotError::TooManyParticles(count));
+    }
+    if count == 0 {
+        return Err(SnapshotError::NoParticles);
+    }
+
+    Ok(SnapshotHeader {
+        version,
+        particle_size,
+        count,
+        seed: u64::from_le_bytes(read_array(r)?),
+        sim_time: f64::from_le_bytes(read_array(r)?),
+        step_count: if version < 3 { 0 } else { u32::from_le_bytes(read_array(r)?) },
+    })
+}
+
+fn read_particles<R: Read>(r: &mut R, header: &SnapshotHeader) -> Result<Vec<Particle>, SnapshotError> {
+    let mut particles = Vec::with_capacity(header.count as usize);
+    for _ in 0..header.count {
+        let mut p = Particle {
+            pos: read_vec2(r)?,
+            vel: read_vec2(r)?,
+            acc: read_vec2(r)?,
+            ..Particle::default()
+        };
+        if header.version > 1 {
+            p.age = f32::from_le_bytes(read_array(r)?);
+            p.lifetime = f32::from_le_bytes(read_array(r)?);
+            p.alive = u32::from_le_bytes(read_array(r)?);
+            read_array::<_, 4>(r)?;
+        }
+        particles.push(p);
+    }
+    Ok(particles)
+}
+
+fn read_array<R: Read, const N: usize>(r: &mut R) -> io::Result<[u8; N]> {
+    let mut buf = [0; N];
+    r.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+fn read_vec2<R: Read>(r: &mut R) -> io::Result<Vector2> {
+    let x = f32::from_le_bytes(read_array(r)?);
+    let y = f32::from_le_bytes(read_array(r)?);
+    Ok(Vector2::new(x, y))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::io::Cursor;
+    use std::process;
+
+    use super::*;
+
+    fn particles() -> Vec<Particle> {
+        (0..5)
+            .map(|i| {
+                let f = i as f32;
+                Particle {
+                    pos: Vector2::new(f, -f),
+                    vel: Vector2::new(0.5 * f, 2.0),
+                    acc: Vector2::new(-1.0, f * f),
+                    age: 0.25 * f,
+                    lifetime: 3.0,
+                    alive: i % 2,
+                }
+            })
+            .collect()
+    }
+
+    fn assert_same(a: &[Particle], b: &[Particle]) {
+        assert_eq!(a.len(), b.len(), "particle counts differ");
+        for (p, q) in a.iter().zip(b) {
+            assert_eq!((p.pos.x, p.pos.y, p.vel.x, p.vel.y), (q.pos.x, q.pos.y, q.vel.x, q.vel.y), "motion differs");
+            assert_eq!((p.acc.x, p.acc.y), (q.acc.x, q.acc.y), "acceleration differs");
+            assert_eq!((p.age, p.lifetime, p.alive), (q.age, q.lifetime, q.alive), "lifetime differs");
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let snapshot = Snapshot::new(particles(), 42, 1.5, 1234);
+        let path = env::temp_dir().join(format!("ogl-snapshot-test-{}.hns", process::id()));
+        snapshot.save(&path).expect("save failed");
+        let loaded = Snapshot::load(&path);
+        fs::remove_file(&path).expect("couldn't remove the test snapshot");
+        let loaded = loaded.expect("load failed");
+
+        assert_eq!(loaded.header, snapshot.header, "header differs");
+        assert_same(&loaded.particles, &snapshot.particles);
+    }
+
+    #[test]
+    fn header_size() {
+        let mut bytes = Vec::new();
+        Snapshot::new(Vec::new(), 0, 0.0, 0).write_to(&mut bytes).expect("write failed");
+        assert_eq!(bytes.len() as u64, SNAPSHOT_HEADER_SIZE, "header size constant is stale");
+    }
+
+    #[test]
+    fn loads_v1() {
+        let mut bytes = SNAPSHOT_MAGIC.to_vec();
+        bytes.extend(1u32.to_le_bytes());
+        bytes.extend(V1_PARTICLE_SIZE.to_le_bytes());
+        bytes.extend(2u64.to_le_bytes());
+        bytes.extend(7u64.to_le_bytes());
+        bytes.extend(0.5f64.to_le_bytes());
+        for v in [1.0f32, 2.0, 3.0, 4.0, 5.0, 6.0, -1.0, -2.0, -3.0, -4.0, -5.0, -6.0] {
+            bytes.extend(v.to_le_bytes());
+        }
+
+        let snapshot = Snapshot::read_from(&mut Cursor::new(bytes)).expect("v1 load failed");
+        assert_eq!((snapshot.header.version, snapshot.header.seed), (1, 7), "wrong header");
+        assert_eq!(snapshot.particles.len(), 2, "wrong particle count");
+        let p = &snapshot.particles[1];
+        assert_eq!((p.pos.x, p.vel.y, p.acc.y), (-1.0, -4.0, -6.0), "wrong motion");
+        assert!(p.is_alive() && p.lifetime == 0.0 && p.age == 0.0, "v1 particles should live forever");
+    }
+
+    #[test]
+    fn rejects_huge_count() {
+        let mut bytes = Vec::new();
+        Snapshot::new(Vec::new(), 0, 0.0, 0).write_to(&mut bytes).expect("write failed");
+        bytes[16..24].copy_from_slice(&u64::MAX.to_le_bytes());
+
+        let result = Snapshot::read_from(&mut Cursor::new(bytes));
+        assert!(matches!(result, Err(SnapshotError::TooManyParticles(u64::MAX))), "huge count accepted");
+    }
+
+    #[test]
+    fn rejects_empty_snapshot() {
+        let mut bytes = Vec::new();
+        Snapshot::new(Vec::new(), 0, 0.0, 0).write_to(&mut bytes).expect("write failed");
+
+        let result = Snapshot::read_from(&mut Cursor::new(bytes));
+        assert!(matches!(result, Err(SnapshotError::NoParticles)), "empty snapshot accepted");
+    }
+
+    #[test]
+    fn loads_v2_without_step_count() {
+        let mut bytes = Vec::new();
+        Snapshot::new(particles(), 9, 2.0, 77).write_to(&mut bytes).expect("write failed");
+        bytes[8..12].copy_from_slice(&2u32.to_le_bytes());
+        bytes.drain(V2_HEADER_SIZE as usize..SNAPSHOT_HEADER_SIZE as usize);
+
+        let snapshot = Snapshot::read_from(&mut Cursor::new(bytes)).expect("v2 load failed");
+        assert_eq!((snapshot.header.version, snapshot.header.step_count), (2, 0), "v2 snapshots start from step 0");
+        assert_same(&snapshot.particles, &particles());
+    }
+
+    #[test]
+    fn rejects_truncated_file() {
+        let mut bytes = Vec::new();
+        Snapshot::new(particles(), 0, 0.0, 0).write_to(&mut bytes).expect("write failed");
+        bytes.truncate(bytes.len() - 10);
+        let path = env::temp_dir().join(format!("ogl-truncated-test-{}.hns", process::id()));
+        fs::write(&path, &bytes).expect("couldn't write the test snapshot");
+        let result = Snapshot::load(&path);
+        fs::remove_file(&path).expect("couldn't remove the test snapshot");
+
+        assert!(matches!(result, Err(SnapshotError::Truncated { .. })), "truncated file accepted");
+    }
+}
@@ -1,17 +1,36 @@
-use anyhow::Result;
+use anyhow::{Context as AnyhowContextTrait, Result};
 use gl::types::{GLchar, GLenum, GLint};
 
 use crate::include_cstr;
+use alloc::ffi::CString;
 use core::error::Error;
 use core::ffi::CStr;
 use core::fmt::{self, Display};
 use core::ptr;
+use std::fs;
+use std::path::Path;
 
 pub const V_SOURCE: &CStr = include_cstr!("./shader_source/vertex.glsl");
 pub const F_SOURCE: &CStr = include_cstr!("./shader_source/frag.glsl");
 pub const G_SOURCE: &CStr = include_cstr!("./shader_source/geometry.glsl");
 pub const C_SOURCE: &CStr = include_cstr!("./shader_source/compute.glsl");
 
+/// Line shaders for the domain outline. They're small and fixed, so hot reloading skips them.
+pub const OUTLINE_V_SOURCE: &CStr = include_cstr!("./shader_source/outline_vertex.glsl");
+pub const OUTLINE_F_SOURCE: &CStr = include_cstr!("./shader_source/outline_frag.glsl");
+
+/// Where the shaders baked in above live in the source tree the binary was built from, so
+/// `--hot-reload-shaders` works from any directory.
+pub const SHADER_SOURCE_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/opengl/shader_source");
+
+/// File names of the vertex, fragment, geometry and compute shaders, in the order [`get_all_shaders`] returns them.
+pub const SHADER_FILES: [(&str, GLenum); 4] = [
+    ("vertex.glsl", gl::VERTEX_SHADER),
+    ("frag.glsl", gl::FRAGMENT_SHADER),
+    ("geometry.glsl", gl::GEOMETRY_SHADER),
+    ("compute.glsl", gl::COMPUTE_SHADER),
+];
+
 #[derive(Debug)]
 #[non_exhaustive]
 pub enum ShaderCompileError {
@@ -51,20 +70,24 @@ pub struct Shader {
 impl Shader {
     pub fn try_from_source(source: &CStr, shader_type: GLenum) -> Result<Self, ShaderCompileError> {
         unsafe {
-            let shader = gl::CreateShader(shader_type);
-            gl::ShaderSource(shader, 1, &source.as_ptr(), ptr::null());
-            gl::CompileShader(shader);
+            // Wrapped right away so the shader object is deleted on the error paths as well.
+            let shader = Self {
+                handle: gl::CreateShader(shader_type),
+                ty: shader_type,
+            };
+            gl::ShaderSource(shader.handle, 1, &source.as_ptr(), ptr::null());
+            gl::CompileShader(shader.handle);
 
             let mut success = gl::FALSE as GLint;
-            gl::GetShaderiv(shader, gl::COMPILE_STATUS, &mut success);
+            gl::GetShaderiv(shader.handle, gl::COMPILE_STATUS, &mut success);
 
             if success != gl::TRUE as GLint {
                 let mut len = 0 as GLint;
-                gl::GetShaderiv(shader, gl::INFO_LOG_LENGTH, &mut len);
+                gl::GetShaderiv(shader.handle, gl::INFO_LOG_LENGTH, &mut len);
 
                 if len != 0 {
                     let mut log = vec![0; len as usize];
-                    gl::GetShaderInfoLog(shader, log.capacity() as i32, ptr::null_mut(), log.as_mut_ptr().cast::<GLchar>());
+                    gl::GetShaderInfoLog(shader.handle, log.capacity() as i32, ptr::null_mut(), log.as_mut_ptr().cast::<GLchar>());
                     let s = match String::from_utf8(log) {
                         Ok(s) => s,
                         Err(_) => return Err(ShaderCompileError::ErrorHandlerError(shader_type)),
@@ -75,10 +98,7 @@ impl Shader {
                 }
             }
 
-            Ok(Self {
-                handle: shader,
-                ty: shader_type,
-            })
+            Ok(shader)
         }
     }
 
@@ -100,6 +120,18 @@ pub fn get_all_shaders() -> Result<[Shader; 4]> {
     Ok([vertex_shader, frag_shader, geometry_shader, compute_shader])
 }
 
+/// Like [`get_all_shaders`], but reads the sources from [`SHADER_FILES`] in `dir` at runtime.
+pub fn get_all_shaders_from_dir(dir: &Path) -> Result<[Shader; 4]> {
+    let [vertex_shader, frag_shader, geometry_shader, compute_shader] = SHADER_FILES.map(|(file, ty)| {
+        let path = dir.join(file);
+        let source = fs::read_to_string(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+        let source = CString::new(source).with_context(|| format!("interior NUL byte(s) in {}", path.display()))?;
+        Ok::<_, anyhow::Error>(Shader::try_from_source(&source, ty)?)
+    });
+
+    Ok([vertex_shader?, frag_shader?, geometry_shader?, compute_shader?])
+}
+
 impl Drop for Shader {
     fn drop(&mut self) {
         unsafe {
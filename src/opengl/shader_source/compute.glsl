@@ -2,40 +2,453 @@
 
 layout(local_size_x = 64) in;
 
+// Must match `Particle` in `render/particle.rs`.
 struct Particle {
     vec2 pos;
     vec2 vel;
     vec2 acc;
+    float age;
+    float lifetime;
+    uint alive;
 };
 
 layout(std430, binding = 0) buffer ParticleBuffer {
     Particle particles[];
 };
 
+// Must match `QuadNode` in `simulation/barnes_hut.rs`.
+struct QuadNode {
+    vec2 centerOfMass;
+    float mass;
+    float size;
+    int firstChild;
+    int next;
+};
+
+// Flattened quadtree, built on the CPU and uploaded by `GpuBackend` when `uForceModel == FORCE_BARNES_HUT`.
+layout(std430, binding = 1) readonly buffer TreeBuffer {
+    QuadNode nodes[];
+};
+
+// Must match `Rk4State` in `simulation/integrator.rs`.
+struct RkState {
+    vec2 pos0;
+    vec2 vel0;
+    vec2 sumPos;
+    vec2 sumVel;
+};
+
+// Scratch space for the RK4 stages, one element per particle, bound by `GpuBackend` when it runs them.
+layout(std430, binding = 2) buffer RkBuffer {
+    RkState rk[];
+};
+
+// Indices of the dead particles, rebuilt by `RenderState` whenever the particles are replaced. The
+// lifetime pass pushes onto it and the spawn pass takes from the top.
+layout(std430, binding = 3) buffer FreeList {
+    int freeCount;
+    uint freeSlots[];
+};
+
+// Must match `SpawnBatch` in `simulation/emitter.rs`.
+struct SpawnBatch {
+    vec2 position;
+    vec2 lineEnd;
+    float radius;
+    int shape;
+    float speed;
+    float speedJitter;
+    float direction;
+    float spread;
+    float lifetime;
+    float lifetimeJitter;
+    uint radial;
+    uint spawnEnd;
+};
+
+// This step's spawns, uploaded by `GpuBackend` before the spawn pass.
+layout(std430, binding = 4) readonly buffer SpawnBuffer {
+    SpawnBatch batches[];
+};
+
+// `prev_vbo`, the positions the draw pass interpolates from. Particles that jump (wrap, respawn or spawn)
+// get their new position written here too, so they aren't drawn sliding across the domain.
+layout(std430, binding = 5) writeonly buffer PrevBuffer {
+    Particle prevParticles[];
+};
+
 uniform vec2 uMousePos;
 uniform float uQuadSize;
 uniform float uTime;
 uniform float uDt;
 
-const float G = 6.67430e-11;
-const float softening = 0.001;
+// Must match `ForceModel` in `simulation/mod.rs`.
+const int FORCE_MOUSE = 0;
+const int FORCE_NBODY = 1;
+const int FORCE_BARNES_HUT = 2;
+
+// Must match `ComputePass` in `simulation/mod.rs`.
+const int PASS_FORCES = 0;
+const int PASS_INTEGRATE = 1;
+const int PASS_KICK = 2;
+const int PASS_DRIFT = 3;
+const int PASS_VERLET_DRIFT = 4;
+const int PASS_RK4_1 = 5;
+const int PASS_RK4_4 = 8;
+const int PASS_BOUNDARY = 9;
+const int PASS_LIFETIME = 10;
+const int PASS_SPAWN = 11;
+const int PASS_SPAWN_COMMIT = 12;
+
+// Must match `EmitterShape` in `simulation/emitter.rs`.
+const int SHAPE_POINT = 0;
+const int SHAPE_LINE = 1;
+const int SHAPE_DISK = 2;
+const int SHAPE_RING = 3;
+
+// Must match `SPAWN_SEED` in `simulation/emitter.rs`.
+const uint SPAWN_SEED = 0x9E3779B9u;
+
+// Must match `BoundaryKind` in `simulation/boundary.rs`.
+const int BOUNDARY_NONE = 0;
+const int BOUNDARY_REFLECT = 1;
+const int BOUNDARY_PERIODIC = 2;
+const int BOUNDARY_ABSORB = 3;
+const int BOUNDARY_CONTAIN = 4;
+
+// Must match `ToolKind` in `simulation/tools.rs`.
+const int TOOL_ATTRACT = 0;
+const int TOOL_REPEL = 1;
+const int TOOL_VORTEX = 2;
+const int TOOL_DRAG = 3;
+
+uniform int uForceModel;
+uniform int uPass;
+uniform float uG;
+uniform float uSoftening;
+uniform float uTheta;
+
+uniform int uBoundary;
+uniform vec2 uDomainMin;
+uniform vec2 uDomainMax;
+uniform float uRestitution;
+uniform float uContainStrength;
+// Steps taken so far, seeds the respawn positions of `BOUNDARY_ABSORB` and the emitters.
+uniform uint uStep;
+// Spawns in `batches` this step.
+uniform uint uSpawnCount;
+
+// The mouse tool held down this step, see `ToolForce`.
+uniform bool uToolActive;
+uniform int uToolKind;
+uniform vec2 uToolPos;
+uniform vec2 uToolVelocity;
+uniform float uToolStrength;
+uniform float uToolRadius;
+
+shared vec2 tilePos[gl_WorkGroupSize.x];
+// 1 for live particles, 0 for free slots and past the end.
+shared float tileMass[gl_WorkGroupSize.x];
+
+vec2 mouseAcceleration(vec2 pos) {
+    vec2 dir = uMousePos - pos;
+    float dist = length(dir) + uSoftening;
+
+    float forceMagnitude = uG / (dist * dist);
+    vec2 forceDirection = normalize(dir);
+    return forceMagnitude * forceDirection;
+}
+
+// Pairwise gravity between unit masses with Plummer softening. Positions are staged through shared
+// memory one work-group-sized tile at a time, so every invocation in the group must call this.
+vec2 nbodyAcceleration(vec2 pos, uint n) {
+    vec2 acc = vec2(0.0);
+    float soft2 = uSoftening * uSoftening;
+
+    for(uint tile = 0; tile < n; tile += gl_WorkGroupSize.x) {
+        uint j = tile + gl_LocalInvocationID.x;
+        tilePos[gl_LocalInvocationID.x] = j < n ? particles[j].pos : vec2(0.0);
+        tileMass[gl_LocalInvocationID.x] = j < n && particles[j].alive != 0u ? 1.0 : 0.0;
+        barrier();
+
+        uint count = min(gl_WorkGroupSize.x, n - tile);
+        for(uint k = 0; k < count; k++) {
+            vec2 d = tilePos[k] - pos;
+            float r2 = dot(d, d) + soft2;
+            // The particle itself has d == 0. With softening it would contribute nothing, without it the
+            // term is 0 / 0, so it's skipped, along with any other particle at exactly the same spot.
+            if(r2 > 0.0) {
+                acc += uG * tileMass[k] * d * inversesqrt(r2 * r2 * r2);
+            }
+        }
+        barrier();
+    }
+
+    return acc;
+}
+
+// Stackless walk of `nodes`: a node is opened by going to its first child, and skipped by following
+// `next`. Nodes that are small enough or far enough away count as a single mass at their center.
+vec2 barnesHutAcceleration(vec2 pos) {
+    vec2 acc = vec2(0.0);
+    float soft2 = uSoftening * uSoftening;
+    float theta2 = uTheta * uTheta;
+
+    int node = nodes.length() > 0 ? 0 : -1;
+    while(node >= 0) {
+        QuadNode nd = nodes[node];
+        vec2 d = nd.centerOfMass - pos;
+        float dist2 = dot(d, d);
+
+        if(nd.firstChild < 0 || nd.size * nd.size < theta2 * dist2) {
+            float r2 = dist2 + soft2;
+            // A leaf holding only `pos` itself, 0 / 0 without softening.
+            if(r2 > 0.0) {
+                acc += uG * nd.mass * d * inversesqrt(r2 * r2 * r2);
+            }
+            node = nd.next;
+        } else {
+            node = nd.firstChild;
+        }
+    }
+
+    return acc;
+}
+
+// Fades out linearly from `uToolStrength` at the cursor to nothing at `uToolRadius`.
+vec2 toolAcceleration(Particle p) {
+    vec2 d = uToolPos - p.pos;
+    float dist = length(d);
+    if(dist >= uToolRadius) {
+        return vec2(0.0);
+    }
+
+    float falloff = uToolStrength * (1.0 - dist / uToolRadius);
+    vec2 dir = dist > 0.0 ? d / dist : vec2(0.0);
+
+    switch(uToolKind) {
+    case TOOL_ATTRACT:
+        return dir * falloff;
+    case TOOL_REPEL:
+        return -dir * falloff;
+    case TOOL_VORTEX:
+        return vec2(-dir.y, dir.x) * falloff;
+    case TOOL_DRAG:
+        return (uToolVelocity - p.vel) * falloff;
+    default:
+        return vec2(0.0);
+    }
+}
+
+// Spring back towards the domain for particles outside it. Same as `contain_acceleration` in
+// `simulation/boundary.rs`.
+vec2 containAcceleration(vec2 pos) {
+    vec2 below = max(uDomainMin - pos, 0.0);
+    vec2 above = max(pos - uDomainMax, 0.0);
+    return uContainStrength * (below - above);
+}
+
+// Same as `pcg_hash` in `simulation/boundary.rs`.
+uint pcgHash(uint v) {
+    uint state = v * 747796405u + 2891336453u;
+    uint word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+
+// Top 24 bits of a hash mapped to [0, 1), exact in single precision.
+float unitFloat(uint h) {
+    return float(h >> 8u) * (1.0 / 16777216.0);
+}
+
+// Mirrors a coordinate that overshot a wall back inside, scaled by the restitution like the velocity.
+void reflectAxis(inout float pos, inout float vel, float lo, float hi) {
+    if(pos < lo) {
+        pos = fma(lo - pos, uRestitution, lo);
+        vel = abs(vel) * uRestitution;
+    } else if(pos > hi) {
+        pos = fma(pos - hi, -uRestitution, hi);
+        vel = -abs(vel) * uRestitution;
+    } else {
+        return;
+    }
+    pos = clamp(pos, lo, hi);
+}
+
+float wrapAxis(float pos, float lo, float hi) {
+    float size = hi - lo;
+    float t = pos - lo;
+    return lo + fma(-size, floor(t / size), t);
+}
+
+// The boundary pass, same as `apply_boundary` in `simulation/boundary.rs`.
+void applyBoundary(inout Particle p, uint idx) {
+    if(uBoundary == BOUNDARY_REFLECT) {
+        reflectAxis(p.pos.x, p.vel.x, uDomainMin.x, uDomainMax.x);
+        reflectAxis(p.pos.y, p.vel.y, uDomainMin.y, uDomainMax.y);
+    } else if(uBoundary == BOUNDARY_PERIODIC) {
+        // Checked first, wrapping a coordinate that's already inside can still round it by an ulp.
+        bool outside = any(lessThan(p.pos, uDomainMin)) || any(greaterThanEqual(p.pos, uDomainMax));
+        p.pos.x = wrapAxis(p.pos.x, uDomainMin.x, uDomainMax.x);
+        p.pos.y = wrapAxis(p.pos.y, uDomainMin.y, uDomainMax.y);
+        if(outside) {
+            prevParticles[idx].pos = p.pos;
+        }
+    } else if(uBoundary == BOUNDARY_ABSORB) {
+        bool inside = all(greaterThanEqual(p.pos, uDomainMin)) && all(lessThanEqual(p.pos, uDomainMax));
+        if(!inside) {
+            uint h1 = pcgHash(idx ^ pcgHash(uStep));
+            uint h2 = pcgHash(h1);
+            vec2 size = uDomainMax - uDomainMin;
+            p.pos = vec2(fma(size.x, unitFloat(h1), uDomainMin.x), fma(size.y, unitFloat(h2), uDomainMin.y));
+            p.vel = vec2(0.0);
+            p.acc = vec2(0.0);
+            prevParticles[idx].pos = p.pos;
+        }
+    }
+}
+
+// RK4 stage `stage` (0 to 3), after a forces pass at the current stage state. Same as `rk4_stage` in
+// `simulation/cpu.rs`.
+void rkStage(inout Particle p, int stage, uint idx) {
+    const float weights[4] = float[4](1.0, 2.0, 2.0, 1.0);
+    const float offsets[3] = float[3](0.5, 0.5, 1.0);
+
+    RkState s = rk[idx];
+    if(stage == 0) {
+        s = RkState(p.pos, p.vel, vec2(0.0), vec2(0.0));
+    }
+    s.sumPos += p.vel * weights[stage];
+    s.sumVel += p.acc * weights[stage];
+
+    if(stage < 3) {
+        float h = offsets[stage] * uDt;
+        p.pos = s.pos0 + p.vel * h;
+        p.vel = s.vel0 + p.acc * h;
+    } else {
+        float h = uDt / 6.0;
+        p.pos = s.pos0 + s.sumPos * h;
+        p.vel = s.vel0 + s.sumVel * h;
+    }
+
+    rk[idx] = s;
+}
+
+// Spawn `i` of this step, the same as `spawn_particle` in `simulation/emitter.rs`.
+Particle spawnParticle(uint i) {
+    uint b = 0u;
+    while(b + 1u < uint(batches.length()) && i >= batches[b].spawnEnd) {
+        b++;
+    }
+    SpawnBatch e = batches[b];
+
+    uint h1 = pcgHash(i ^ pcgHash(uStep ^ SPAWN_SEED));
+    uint h2 = pcgHash(h1);
+    uint h3 = pcgHash(h2);
+    uint h4 = pcgHash(h3);
+    uint h5 = pcgHash(h4);
+
+    vec2 pos = e.position;
+    float normal = 0.0;
+    if(e.shape == SHAPE_LINE) {
+        vec2 d = e.lineEnd - e.position;
+        float t = unitFloat(h1);
+        pos = vec2(fma(d.x, t, pos.x), fma(d.y, t, pos.y));
+        normal = atan(d.x, -d.y);
+    } else if(e.shape == SHAPE_DISK || e.shape == SHAPE_RING) {
+        float r = e.shape == SHAPE_DISK ? e.radius * sqrt(unitFloat(h1)) : e.radius;
+        normal = 6.28318530718 * unitFloat(h2);
+        pos = vec2(fma(r, cos(normal), pos.x), fma(r, sin(normal), pos.y));
+    }
+
+    float base = e.radial != 0u ? e.direction + normal : e.direction;
+    float angle = fma(e.spread, unitFloat(h3) - 0.5, base);
+    float speed = e.speed * fma(e.speedJitter, fma(unitFloat(h4), 2.0, -1.0), 1.0);
+    float lifetime = e.lifetime * fma(e.lifetimeJitter, fma(unitFloat(h5), 2.0, -1.0), 1.0);
+
+    return Particle(pos, speed * vec2(cos(angle), sin(angle)), vec2(0.0), 0.0, lifetime, 1u);
+}
 
 void main() {
-    uint idx = gl_GlobalInvocationID.x;
-    if(idx >= gl_NumWorkGroups.x * gl_WorkGroupSize.x) {
+    // Large particle counts are dispatched as a 2D grid of work groups, see `dispatch_compute_call`.
+    uint idx = gl_GlobalInvocationID.y * gl_NumWorkGroups.x * gl_WorkGroupSize.x + gl_GlobalInvocationID.x;
+    uint n = uint(particles.length());
+    bool inRange = idx < n;
+
+    if(uPass == PASS_SPAWN) {
+        // Spawn `idx` takes the `idx`-th slot from the top, the commit pass pops them all afterwards.
+        if(idx < uSpawnCount && int(idx) < freeCount) {
+            uint slot = freeSlots[freeCount - 1 - int(idx)];
+            particles[slot] = spawnParticle(idx);
+            prevParticles[slot].pos = particles[slot].pos;
+        }
+        return;
+    }
+    if(uPass == PASS_SPAWN_COMMIT) {
+        if(idx == 0u) {
+            freeCount = max(freeCount - int(uSpawnCount), 0);
+        }
         return;
     }
 
-    Particle p = particles[idx];
-    vec2 dir = uMousePos - p.pos;
-    float dist = length(dir) + softening;
+    if(uPass == PASS_FORCES) {
+        // Only `acc` is written in this pass, so every invocation sees the same positions.
+        vec2 acc = vec2(0.0);
+        if(uForceModel == FORCE_NBODY) {
+            acc = nbodyAcceleration(inRange ? particles[idx].pos : vec2(0.0), n);
+        }
+        if(!inRange || particles[idx].alive == 0u) {
+            return;
+        }
 
-    float forceMagnitude = (G) / (dist * dist);
-    vec2 forceDirection = normalize(dir); 
-    p.acc = forceMagnitude * forceDirection;
+        Particle p = particles[idx];
+        if(uForceModel == FORCE_BARNES_HUT) {
+            acc = barnesHutAcceleration(p.pos);
+        } else if(uForceModel == FORCE_MOUSE) {
+            acc = mouseAcceleration(p.pos);
+        }
 
-    p.vel += p.acc * uDt;
-    p.pos += p.vel * uDt;
+        if(uBoundary == BOUNDARY_CONTAIN) {
+            acc += containAcceleration(p.pos);
+        }
+        if(uToolActive) {
+            acc += toolAcceleration(p);
+        }
+        particles[idx].acc = acc;
+        return;
+    }
+
+    if(!inRange) {
+        return;
+    }
+
+    Particle p = particles[idx];
+    if(p.alive == 0u) {
+        return;
+    }
+
+    if(uPass == PASS_INTEGRATE) {
+        // Semi-implicit Euler.
+        p.vel += p.acc * uDt;
+        p.pos += p.vel * uDt;
+    } else if(uPass == PASS_KICK) {
+        p.vel += p.acc * (0.5 * uDt);
+    } else if(uPass == PASS_DRIFT) {
+        p.pos += p.vel * uDt;
+    } else if(uPass == PASS_VERLET_DRIFT) {
+        float halfDt = 0.5 * uDt;
+        p.pos += (p.vel + p.acc * halfDt) * uDt;
+        p.vel += p.acc * halfDt;
+    } else if(uPass >= PASS_RK4_1 && uPass <= PASS_RK4_4) {
+        rkStage(p, uPass - PASS_RK4_1, idx);
+    } else if(uPass == PASS_BOUNDARY) {
+        applyBoundary(p, idx);
+    } else if(uPass == PASS_LIFETIME) {
+        p.age += uDt;
+        if(p.lifetime > 0.0 && p.age >= p.lifetime) {
+            p.alive = 0u;
+            freeSlots[atomicAdd(freeCount, 1)] = idx;
+        }
+    }
 
     particles[idx] = p;
 }
@@ -1,4 +1,5 @@
 #version 430 core
+in float gColorValue;
 out vec4 FragColor;
 
 uniform vec2 uMousePos;
@@ -6,6 +7,16 @@ uniform float uQuadSize;
 uniform float uTime;
 uniform float uDt;
 
+// Must match `ColorMode::Solid` in `color.rs`.
+const int COLOR_SOLID = 0;
+
+uniform int uColorMode;
+uniform sampler1D uGradient;
+
 void main() {
-    FragColor = vec4(1.0, 0.5, 0.2, 1.0);
+    if(uColorMode == COLOR_SOLID) {
+        FragColor = vec4(1.0, 0.5, 0.2, 1.0);
+    } else {
+        FragColor = vec4(texture(uGradient, clamp(gColorValue, 0.0, 1.0)).rgb, 1.0);
+    }
 }
\ No newline at end of file
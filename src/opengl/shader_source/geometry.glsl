@@ -6,8 +6,22 @@ uniform vec2 uMousePos;
 uniform float uQuadSize;
 uniform float uTime;
 uniform float uDt;
+// World to clip space, see `Camera::view_projection`.
+uniform mat4 uViewProjection;
+
+in vec2 vVel[];
+in vec2 vAcc[];
+in float vColorValue[];
+flat in uint vAlive[];
+
+out float gColorValue;
 
 void main() {
+    if(vAlive[0] == 0u) {
+        return;
+    }
+
+    // World space, so quads are square and scale with the zoom.
     vec2 center = gl_in[0].gl_Position.xy;
     float size = uQuadSize;
 
@@ -16,13 +30,17 @@ void main() {
     vec2 bottom_left = center + vec2(-size, -size);
     vec2 bottom_right = center + vec2(size, -size);
 
-    gl_Position = vec4(top_left, 0.0, 1.0);
+    gl_Position = uViewProjection * vec4(top_left, 0.0, 1.0);
+    gColorValue = vColorValue[0];
     EmitVertex();
-    gl_Position = vec4(bottom_left, 0.0, 1.0);
+    gl_Position = uViewProjection * vec4(bottom_left, 0.0, 1.0);
+    gColorValue = vColorValue[0];
     EmitVertex();
-    gl_Position = vec4(top_right, 0.0, 1.0);
+    gl_Position = uViewProjection * vec4(top_right, 0.0, 1.0);
+    gColorValue = vColorValue[0];
     EmitVertex();
-    gl_Position = vec4(bottom_right, 0.0, 1.0);
+    gl_Position = uViewProjection * vec4(bottom_right, 0.0, 1.0);
+    gColorValue = vColorValue[0];
     EmitVertex();
 
     EndPrimitive();
@@ -1,11 +1,57 @@
 #version 430 core
+// Must match `PARTICLE_LAYOUT` in `render/layout.rs`.
 layout(location = 0) in vec2 aPos;
+layout(location = 1) in vec2 aVel;
+layout(location = 2) in vec2 aAcc;
+// Position before the last substep, see `PREV_POS_ATTRIBUTE`.
+layout(location = 3) in vec2 aPrevPos;
+layout(location = 4) in uint aAlive;
 
 uniform vec2 uMousePos;
 uniform float uQuadSize;
 uniform float uTime;
 uniform float uDt;
+// 1 draws the current positions, less moves them back towards `aPrevPos`.
+uniform float uAlpha;
 
-void main() {
-    gl_Position = vec4(aPos, 0.0, 1.0);
+// Must match `ColorMode` in `color.rs`.
+const int COLOR_SOLID = 0;
+const int COLOR_SPEED = 1;
+const int COLOR_ACCELERATION = 2;
+const int COLOR_HEADING = 3;
+const int COLOR_INDEX = 4;
+
+uniform int uColorMode;
+uniform float uColorRange;
+uniform int uParticleCount;
+
+out vec2 vVel;
+out vec2 vAcc;
+// 0 for free slots, which the geometry shader drops.
+flat out uint vAlive;
+// Gradient coordinate, only meaningful when `uColorMode != COLOR_SOLID`.
+out float vColorValue;
+
+float colorValue() {
+    switch(uColorMode) {
+    case COLOR_SPEED:
+        return length(aVel) / uColorRange;
+    case COLOR_ACCELERATION:
+        return length(aAcc) / uColorRange;
+    case COLOR_HEADING:
+        return atan(aVel.y, aVel.x) / (2.0 * 3.14159265) + 0.5;
+    case COLOR_INDEX:
+        return float(gl_VertexID) / float(max(uParticleCount - 1, 1));
+    default:
+        return 0.0;
+    }
 }
+
+void main() {
+    // Stays in world space, the geometry shader builds the quad around it and applies `uViewProjection`.
+    gl_Position = vec4(mix(aPrevPos, aPos, uAlpha), 0.0, 1.0);
+    vVel = aVel;
+    vAcc = aAcc;
+    vAlive = aAlive;
+    vColorValue = colorValue();
+}
\ No newline at end of file
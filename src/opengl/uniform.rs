@@ -1,102 +1,293 @@
 use anyhow::Result;
+use gl::types::{GLchar, GLenum, GLint, GLsizei};
+use std::collections::HashMap;
 
 use crate::opengl::program::Program;
+use crate::vec2::Vector2;
 
-#[derive(Debug, Clone)]
-pub struct UniformLocations {
-    program: Program,
-    time: i32,
-    dt: i32,
-    quad_size: i32,
-    mouse_pos: i32,
+use core::error::Error;
+use core::fmt::{self, Display};
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum UniformError {
+    TypeMismatch { name: String, expected: GLenum, found: &'static str },
 }
 
-impl UniformLocations {
-    pub fn new(program: &Program) -> Result<Self> {
-        let time = program.get_uniform_location(c"uTime");
-        let quad_size = program.get_uniform_location(c"uQuadSize");
-        let dt = program.get_uniform_location(c"uDt");
-        let mouse_pos = program.get_uniform_location(c"uMousePos");
+impl Error for UniformError {}
+
+impl Display for UniformError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::TypeMismatch { ref name, expected, found } => write!(
+                f,
+                "Uniform `{}` is declared as {} but was set with {}",
+                name,
+                get_uniform_type_name(expected),
+                found
+            ),
+        }
+    }
+}
+
+pub const fn get_uniform_type_name(ty: GLenum) -> &'static str {
+    match ty {
+        gl::FLOAT => "float",
+        gl::FLOAT_VEC2 => "vec2",
+        gl::FLOAT_VEC3 => "vec3",
+        gl::FLOAT_VEC4 => "vec4",
+        gl::INT => "int",
+        gl::UNSIGNED_INT => "uint",
+        gl::BOOL => "bool",
+        gl::FLOAT_MAT3 => "mat3",
+        gl::FLOAT_MAT4 => "mat4",
+        gl::SAMPLER_1D => "sampler1D",
+        gl::SAMPLER_2D => "sampler2D",
+        _ => "unsupported uniform type",
+    }
+}
+
+/// A Rust value that can be uploaded to a uniform of a matching GLSL type.
+pub trait UniformValue {
+    /// Name of the GLSL type this value is uploaded as, for error messages.
+    const GLSL_NAME: &'static str;
+
+    /// Whether a uniform declared as `ty` can be set from this value.
+    fn accepts(ty: GLenum) -> bool;
+
+    /// # Safety
+    ///
+    /// `location` must be an active uniform of `program` whose type is accepted by [`Self::accepts`].
+    unsafe fn upload(&self, program: u32, location: GLint);
+}
+
+impl UniformValue for f32 {
+    const GLSL_NAME: &'static str = "float";
+
+    fn accepts(ty: GLenum) -> bool {
+        ty == gl::FLOAT
+    }
+
+    unsafe fn upload(&self, program: u32, location: GLint) {
+        unsafe { gl::ProgramUniform1f(program, location, *self) };
+    }
+}
+
+impl UniformValue for (f32, f32) {
+    const GLSL_NAME: &'static str = "vec2";
 
-        Ok(Self {
-            program: program.clone(),
-            time,
-            quad_size,
-            dt,
-            mouse_pos,
-        })
+    fn accepts(ty: GLenum) -> bool {
+        ty == gl::FLOAT_VEC2
     }
 
-    pub const fn get_time_handle(&self) -> i32 {
-        self.time
+    unsafe fn upload(&self, program: u32, location: GLint) {
+        unsafe { gl::ProgramUniform2f(program, location, self.0, self.1) };
     }
+}
 
-    pub const fn get_dt_handle(&self) -> i32 {
-        self.dt
+impl UniformValue for Vector2 {
+    const GLSL_NAME: &'static str = "vec2";
+
+    fn accepts(ty: GLenum) -> bool {
+        ty == gl::FLOAT_VEC2
     }
 
-    pub const fn get_quad_size_handle(&self) -> i32 {
-        self.quad_size
+    unsafe fn upload(&self, program: u32, location: GLint) {
+        unsafe { gl::ProgramUniform2f(program, location, self.x, self.y) };
     }
+}
+
+impl UniformValue for [f32; 3] {
+    const GLSL_NAME: &'static str = "vec3";
 
-    pub const fn get_mouse_pos_handle(&self) -> i32 {
-        self.mouse_pos
+    fn accepts(ty: GLenum) -> bool {
+        ty == gl::FLOAT_VEC3
     }
 
-    pub fn set_time(&self, val: f32) {
-        unsafe { gl::UseProgram(self.program.handle()) };
-        unsafe { gl::Uniform1f(self.time, val) };
+    unsafe fn upload(&self, program: u32, location: GLint) {
+        unsafe { gl::ProgramUniform3f(program, location, self[0], self[1], self[2]) };
     }
+}
+
+impl UniformValue for [f32; 4] {
+    const GLSL_NAME: &'static str = "vec4";
 
-    pub fn set_dt(&self, val: f32) {
-        unsafe { gl::UseProgram(self.program.handle()) };
-        unsafe { gl::Uniform1f(self.dt, val) };
+    fn accepts(ty: GLenum) -> bool {
+        ty == gl::FLOAT_VEC4
     }
 
-    pub fn set_quad_size(&self, val: f32) {
-        unsafe { gl::UseProgram(self.program.handle()) };
-        unsafe { gl::Uniform1f(self.quad_size, val) };
+    unsafe fn upload(&self, program: u32, location: GLint) {
+        unsafe { gl::ProgramUniform4f(program, location, self[0], self[1], self[2], self[3]) };
     }
+}
 
-    pub fn set_mouse_pos(&self, val: (f32, f32)) {
-        unsafe { gl::UseProgram(self.program.handle()) };
-        unsafe { gl::Uniform2f(self.mouse_pos, val.0, val.1) };
+/// Also sets sampler uniforms, whose value is a texture unit.
+impl UniformValue for i32 {
+    const GLSL_NAME: &'static str = "int";
+
+    fn accepts(ty: GLenum) -> bool {
+        matches!(ty, gl::INT | gl::SAMPLER_1D | gl::SAMPLER_2D)
+    }
+
+    unsafe fn upload(&self, program: u32, location: GLint) {
+        unsafe { gl::ProgramUniform1i(program, location, *self) };
     }
 }
 
-pub trait SetAllUniformLocations {
-    fn set_time(&self, val: f32);
-    fn set_dt(&self, val: f32);
-    fn set_quad_size(&self, val: f32);
-    fn set_mouse_pos(&self, val: (f32, f32));
+impl UniformValue for u32 {
+    const GLSL_NAME: &'static str = "uint";
+
+    fn accepts(ty: GLenum) -> bool {
+        ty == gl::UNSIGNED_INT
+    }
+
+    unsafe fn upload(&self, program: u32, location: GLint) {
+        unsafe { gl::ProgramUniform1ui(program, location, *self) };
+    }
 }
 
-impl SetAllUniformLocations for &[UniformLocations] {
-    fn set_time(&self, val: f32) {
-        for unif in self.iter() {
-            unsafe { gl::UseProgram(unif.program.handle()) };
-            unsafe { gl::Uniform1f(unif.time, val) };
-        }
+impl UniformValue for bool {
+    const GLSL_NAME: &'static str = "bool";
+
+    fn accepts(ty: GLenum) -> bool {
+        ty == gl::BOOL
     }
 
-    fn set_dt(&self, val: f32) {
-        for unif in self.iter() {
-            unsafe { gl::UseProgram(unif.program.handle()) };
-            unsafe { gl::Uniform1f(unif.time, val) };
+    unsafe fn upload(&self, program: u32, location: GLint) {
+        unsafe { gl::ProgramUniform1i(program, location, *self as GLint) };
+    }
+}
+
+/// Column-major, as GLSL expects.
+impl UniformValue for [[f32; 3]; 3] {
+    const GLSL_NAME: &'static str = "mat3";
+
+    fn accepts(ty: GLenum) -> bool {
+        ty == gl::FLOAT_MAT3
+    }
+
+    unsafe fn upload(&self, program: u32, location: GLint) {
+        unsafe { gl::ProgramUniformMatrix3fv(program, location, 1, gl::FALSE, self.as_ptr().cast::<f32>()) };
+    }
+}
+
+/// Column-major, as GLSL expects.
+impl UniformValue for [[f32; 4]; 4] {
+    const GLSL_NAME: &'static str = "mat4";
+
+    fn accepts(ty: GLenum) -> bool {
+        ty == gl::FLOAT_MAT4
+    }
+
+    unsafe fn upload(&self, program: u32, location: GLint) {
+        unsafe { gl::ProgramUniformMatrix4fv(program, location, 1, gl::FALSE, self.as_ptr().cast::<f32>()) };
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UniformInfo {
+    pub location: GLint,
+    pub ty: GLenum,
+    /// Array length, 1 for non-array uniforms.
+    pub size: GLint,
+}
+
+/// The active uniforms of a program, found through `glGetActiveUniform`.
+#[derive(Debug, Clone)]
+pub struct UniformLocations {
+    program: u32,
+    uniforms: HashMap<String, UniformInfo>,
+}
+
+impl UniformLocations {
+    pub fn new(program: &Program) -> Result<Self> {
+        let handle = program.handle();
+        let mut uniforms = HashMap::new();
+
+        unsafe {
+            let mut count = 0;
+            gl::GetProgramiv(handle, gl::ACTIVE_UNIFORMS, &raw mut count);
+
+            let mut max_len = 0;
+            gl::GetProgramiv(handle, gl::ACTIVE_UNIFORM_MAX_LENGTH, &raw mut max_len);
+
+            let mut name_buf = vec![0u8; max_len.max(1) as usize];
+
+            for index in 0..count as u32 {
+                let mut len: GLsizei = 0;
+                let mut size: GLint = 0;
+                let mut ty: GLenum = 0;
+                gl::GetActiveUniform(
+                    handle,
+                    index,
+                    name_buf.len() as GLsizei,
+                    &raw mut len,
+                    &raw mut size,
+                    &raw mut ty,
+                    name_buf.as_mut_ptr().cast::<GLchar>(),
+                );
+
+                // `name_buf` is NUL-terminated by GL.
+                let location = gl::GetUniformLocation(handle, name_buf.as_ptr().cast::<GLchar>());
+                // Members of uniform blocks have no location of their own.
+                if location < 0 {
+                    continue;
+                }
+
+                let name = String::from_utf8_lossy(&name_buf[..len as usize]);
+                // Arrays are reported as `name[0]`, but are set through their plain name.
+                let name = name.strip_suffix("[0]").unwrap_or(&name).to_owned();
+
+                uniforms.insert(name, UniformInfo { location, ty, size });
+            }
         }
+
+        Ok(Self { program: handle, uniforms })
+    }
+
+    pub fn get(&self, name: &str) -> Option<&UniformInfo> {
+        self.uniforms.get(name)
     }
 
-    fn set_quad_size(&self, val: f32) {
-        for unif in self.iter() {
-            unsafe { gl::UseProgram(unif.program.handle()) };
-            unsafe { gl::Uniform1f(unif.time, val) };
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &UniformInfo)> {
+        self.uniforms.iter().map(|(name, info)| (name.as_str(), info))
+    }
+
+    /// Sets the uniform `name` to `value`.
+    ///
+    /// Uniforms the program doesn't have (or that the GLSL compiler optimized out) are skipped
+    /// silently; a uniform of a different type is an error.
+    pub fn set<T: UniformValue>(&self, name: &str, value: T) -> Result<(), UniformError> {
+        let Some(info) = self.uniforms.get(name) else {
+            return Ok(());
+        };
+
+        if !T::accepts(info.ty) {
+            return Err(UniformError::TypeMismatch {
+                name: name.to_owned(),
+                expected: info.ty,
+                found: T::GLSL_NAME,
+            });
         }
+
+        // Safety: the uniform exists in this program and has a type T accepts.
+        unsafe { value.upload(self.program, info.location) };
+
+        Ok(())
     }
+}
 
-    fn set_mouse_pos(&self, val: (f32, f32)) {
-        for unif in self.iter() {
-            unsafe { gl::UseProgram(unif.program.handle()) };
-            unsafe { gl::Uniform2f(unif.mouse_pos, val.0, val.1) };
+pub trait SetAllUniformLocations {
+    /// Sets `name` on every program that has it, see [`UniformLocations::set`].
+    fn set<T: UniformValue + Copy>(&self, name: &str, value: T) -> Result<(), UniformError>;
+}
+
+impl SetAllUniformLocations for [&UniformLocations] {
+    fn set<T: UniformValue + Copy>(&self, name: &str, value: T) -> Result<(), UniformError> {
+        for unif in self {
+            unif.set(name, value)?;
         }
+
+        Ok(())
     }
 }
@@ -0,0 +1,557 @@
+use core::error::Error;
+use core::fmt::{self, Display};
+use std::path::PathBuf;
+
+use anyhow::Result;
+use clap::Parser;
+
+use crate::cli::Cli;
+use crate::config::Config;
+use crate::log::LogLevel;
+use crate::opengl::capture::CaptureSettings;
+use crate::opengl::color::ColorMode;
+use crate::opengl::render::camera::{MAX_ZOOM, MIN_ZOOM};
+use crate::opengl::render::renderstate::MAX_PARTICLES;
+use crate::simulation::boundary::{Boundary, BoundaryKind};
+use crate::simulation::emitter::Emitter;
+use crate::simulation::initial::InitialCondition;
+use crate::simulation::integrator::Integrator;
+use crate::simulation::time::{MAX_TIME_SCALE, MIN_TIME_SCALE};
+use crate::simulation::{BackendKind, ForceModel, SimParams};
+
+/// How many frames `--headless` renders when `--frames` isn't given.
+pub const DEFAULT_HEADLESS_FRAMES: u32 = 300;
+
+/// Number of particles when neither `--particles` nor the config file set one.
+pub const DEFAULT_PARTICLE_COUNT: usize = 128;
+
+/// Where F5/F9 save and restore snapshots when `--snapshot` isn't given.
+pub const DEFAULT_SNAPSHOT_PATH: &str = "snapshot.hns";
+
+/// A setting that failed validation, named by its key in the config file.
+///
+/// Nested keys are dotted, `window.width`, and the tables of an array are numbered from 0, `emitters.2`.
+#[derive(Debug)]
+pub struct InvalidSetting {
+    pub key: String,
+    pub message: String,
+}
+
+impl InvalidSetting {
+    fn new<M: Into<String>>(key: &str, message: M) -> Self {
+        Self {
+            key: key.to_owned(),
+            message: message.into(),
+        }
+    }
+}
+
+impl Error for InvalidSetting {}
+
+impl Display for InvalidSetting {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+/// What the program does, picked with a subcommand.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Mode {
+    /// Run the simulation in a window, or offscreen with `--headless`.
+    #[default]
+    Run,
+    /// Render frames offscreen into a capture directory.
+    Render,
+    /// Time Barnes-Hut against brute-force gravity on the CPU and exit, see [`crate::simulation::bench`].
+    Bench,
+    /// Compare the energy and momentum drift of every integrator on the CPU and exit, see
+    /// [`crate::simulation::drift`].
+    Drift,
+    /// Compile and link the shaders and exit.
+    ValidateShaders,
+}
+
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub mode: Mode,
+    /// Messages below this level aren't printed.
+    pub log_level: LogLevel,
+    pub backend: BackendKind,
+    /// Render into an offscreen framebuffer on a surfaceless EGL context instead of opening a window.
+    pub headless: bool,
+    /// Number of frames to render before exiting in headless mode.
+    pub frames: u32,
+    /// Write rendered frames to this directory.
+    pub capture: Option<CaptureSettings>,
+    /// Snapshot file used by the save/restore keys.
+    pub snapshot_path: PathBuf,
+    /// Start from this snapshot instead of random particles.
+    pub load_snapshot: Option<PathBuf>,
+    /// Seed for the particle RNG, drawn from OS entropy when `None`.
+    pub seed: Option<u64>,
+    /// Step the simulation by this many seconds per frame, ignoring wall-clock time.
+    pub fixed_dt: Option<f32>,
+    /// Simulated seconds per real second, 0.1 to 10.
+    pub time_scale: Option<f32>,
+    /// Longest wall-clock step per frame.
+    pub max_dt: Option<f32>,
+    /// Frames advanced by the "step many" key.
+    pub step_frames: Option<u32>,
+    /// Integrate in substeps of this many simulated seconds instead of one step per frame.
+    pub substep_dt: Option<f32>,
+    /// Most substeps per frame.
+    pub max_substeps: Option<u32>,
+    /// Draw positions interpolated between the last two substeps, needs `substep_dt`. Off when `None`.
+    pub interpolate: Option<bool>,
+    /// Number of particles, [`DEFAULT_PARTICLE_COUNT`] when `None`.
+    pub particle_count: Option<usize>,
+    /// Particles alive at the start, the rest are free slots for the emitters. All of them when `None`
+    /// and there are no emitters, none when there are.
+    pub initial_particles: Option<usize>,
+    /// Particle sources, only read from the config file.
+    pub emitters: Option<Vec<Emitter>>,
+    /// Layout of the particles at the start and after a reset, [`InitialCondition::Noise`] when `None`.
+    pub initial: Option<InitialCondition>,
+    /// Config file to take defaults from and to watch for live changes.
+    pub config: Option<PathBuf>,
+    /// Window or headless framebuffer width, [`CANVAS_WIDTH`](crate::opengl::global_state::CANVAS_WIDTH) when `None`.
+    pub width: Option<u32>,
+    /// Window or headless framebuffer height, [`CANVAS_HEIGHT`](crate::opengl::global_state::CANVAS_HEIGHT) when `None`.
+    pub height: Option<u32>,
+    /// Window title, only read from the config file.
+    pub title: Option<String>,
+    /// Open the window fullscreen on the primary monitor, off when `None`.
+    pub fullscreen: Option<bool>,
+    /// Wait for the display's refresh between frames, on when `None`.
+    pub vsync: Option<bool>,
+    /// Side length of a particle's quad in world units.
+    pub quad_size: Option<f32>,
+    /// Background color, only read from the config file.
+    pub clear_color: Option<[f32; 3]>,
+    /// World position at the middle of the view at the start, only read from the config file.
+    pub camera_center: Option<[f32; 2]>,
+    /// Zoom at the start, only read from the config file.
+    pub camera_zoom: Option<f32>,
+    /// Fit the view to the particles at the start, overriding the camera center and zoom. Off when `None`.
+    pub fit_view: Option<bool>,
+    /// Load shaders from this directory instead of the baked-in sources, and recompile them when they change.
+    pub shader_dir: Option<PathBuf>,
+    pub force_model: Option<ForceModel>,
+    pub gravity: Option<f32>,
+    pub softening: Option<f32>,
+    /// Barnes-Hut opening angle.
+    pub theta: Option<f32>,
+    pub integrator: Option<Integrator>,
+    pub boundary: Option<BoundaryKind>,
+    /// Simulation domain as its lower-left and upper-right corners.
+    pub domain: Option<((f32, f32), (f32, f32))>,
+    /// Velocity kept when bouncing off a reflective wall, 0 to 1.
+    pub restitution: Option<f32>,
+    /// Spring constant of the soft containment boundary.
+    pub contain_strength: Option<f32>,
+    pub color_mode: Option<ColorMode>,
+    /// Text file of color stops for the gradient the color modes map through.
+    pub gradient: Option<PathBuf>,
+    /// Speed or acceleration magnitude that maps to the end of the gradient.
+    pub color_range: Option<f32>,
+    /// Keymap file overriding the default key bindings.
+    pub keymap: Option<PathBuf>,
+    /// CSV file that particle statistics are appended to, see [`crate::simulation::diagnostics`].
+    pub diagnostics: Option<PathBuf>,
+    /// Simulation frames between two rows of the diagnostics log.
+    pub diagnostics_every: Option<u32>,
+    /// Bins per velocity histogram in the diagnostics log.
+    pub histogram_bins: Option<usize>,
+    /// Steps `drift` runs each integrator for.
+    pub drift_steps: Option<u32>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            mode: Mode::default(),
+            log_level: LogLevel::default(),
+            backend: BackendKind::default(),
+            headless: false,
+            frames: DEFAULT_HEADLESS_FRAMES,
+            capture: None,
+            snapshot_path: PathBuf::from(DEFAULT_SNAPSHOT_PATH),
+            load_snapshot: None,
+            seed: None,
+            fixed_dt: None,
+            time_scale: None,
+            max_dt: None,
+            step_frames: None,
+            substep_dt: None,
+            max_substeps: None,
+            interpolate: None,
+            particle_count: None,
+            initial_particles: None,
+            emitters: None,
+            initial: None,
+            config: None,
+            width: None,
+            height: None,
+            title: None,
+            fullscreen: None,
+            vsync: None,
+            quad_size: None,
+            clear_color: None,
+            camera_center: None,
+            camera_zoom: None,
+            fit_view: None,
+            shader_dir: None,
+            force_model: None,
+            gravity: None,
+            softening: None,
+            theta: None,
+            integrator: None,
+            boundary: None,
+            domain: None,
+            restitution: None,
+            contain_strength: None,
+            color_mode: None,
+            gradient: None,
+            color_range: None,
+            keymap: None,
+            diagnostics: None,
+            diagnostics_every: None,
+            histogram_bins: None,
+            drift_steps: None,
+        }
+    }
+}
+
+impl Options {
+    /// Parses the command line, see [`Cli`], and fills in what it leaves out from the scene file.
+    pub fn from_env_args() -> Result<Self> {
+        let mut options = Cli::parse().into_options();
+
+        if let Some(ref path) = options.config {
+            let config = Config::load(path)?;
+            options.apply_config(config);
+        }
+
+        options.validate()?;
+
+        Ok(options)
+    }
+
+    /// Checks every setting that was given, on its own and together with the others.
+    pub fn validate(&self) -> Result<(), InvalidSetting> {
+        self.validate_ranges()?;
+        self.validate_combination()
+    }
+
+    /// Checks the range of every setting that was given on its own. Unlike [`Self::validate`], this also
+    /// holds for a scene file before the command line is merged into it.
+    pub fn validate_ranges(&self) -> Result<(), InvalidSetting> {
+        if self.particle_count.is_some_and(|count| !(1..=MAX_PARTICLES).contains(&count)) {
+            return Err(InvalidSetting::new(
+                "particle_count",
+                format!("particle count must be between 1 and {}", MAX_PARTICLES),
+            ));
+        }
+
+        if self.initial_particles.is_some_and(|alive| alive > MAX_PARTICLES) {
+            return Err(InvalidSetting::new(
+                "initial_particles",
+                format!("initial particle count must not exceed {}", MAX_PARTICLES),
+            ));
+        }
+
+        for (i, emitter) in self.emitters.iter().flatten().enumerate() {
+            if let Err(e) = emitter.validate() {
+                return Err(InvalidSetting::new(&format!("emitters.{}", i), format!("invalid emitter {}: {:#}", i + 1, e)));
+            }
+        }
+
+        if let Some(ref initial) = self.initial {
+            if let Err(e) = initial.validate() {
+                return Err(InvalidSetting::new(
+                    "initial",
+                    format!("invalid parameters for initial condition `{}`: {:#}", initial, e),
+                ));
+            }
+        }
+
+        if self.width == Some(0) {
+            return Err(InvalidSetting::new("window.width", "window width must be at least 1"));
+        }
+
+        if self.height == Some(0) {
+            return Err(InvalidSetting::new("window.height", "window height must be at least 1"));
+        }
+
+        if self.quad_size.is_some_and(|size| !size.is_finite() || size <= 0.0) {
+            return Err(InvalidSetting::new("quad_size", "quad size must be positive"));
+        }
+
+        if self
+            .clear_color
+            .is_some_and(|color| color.iter().any(|c| !(0.0..=1.0).contains(c)))
+        {
+            return Err(InvalidSetting::new("clear_color", "clear color components must be between 0 and 1"));
+        }
+
+        if self.camera_center.is_some_and(|center| !center.iter().all(|c| c.is_finite())) {
+            return Err(InvalidSetting::new("camera.center", "camera center must be finite"));
+        }
+
+        if self.camera_zoom.is_some_and(|zoom| !(MIN_ZOOM..=MAX_ZOOM).contains(&zoom)) {
+            return Err(InvalidSetting::new(
+                "camera.zoom",
+                format!("camera zoom must be between {} and {}", MIN_ZOOM, MAX_ZOOM),
+            ));
+        }
+
+        if self.fixed_dt.is_some_and(|dt| !dt.is_finite() || dt <= 0.0) {
+            return Err(InvalidSetting::new("fixed_dt", "fixed timestep must be positive"));
+        }
+
+        if self
+            .time_scale
+            .is_some_and(|scale| !(MIN_TIME_SCALE..=MAX_TIME_SCALE).contains(&scale))
+        {
+            return Err(InvalidSetting::new(
+                "time_scale",
+                format!("time scale must be between {} and {}", MIN_TIME_SCALE, MAX_TIME_SCALE),
+            ));
+        }
+
+        if self.max_dt.is_some_and(|dt| !dt.is_finite() || dt <= 0.0) {
+            return Err(InvalidSetting::new("max_dt", "maximum timestep must be positive"));
+        }
+
+        if self.substep_dt.is_some_and(|dt| !dt.is_finite() || dt <= 0.0) {
+            return Err(InvalidSetting::new("substep_dt", "substep timestep must be positive"));
+        }
+
+        if self.max_substeps == Some(0) {
+            return Err(InvalidSetting::new("max_substeps", "maximum substep count must be at least 1"));
+        }
+
+        if self.domain.is_some_and(|(min, max)| !(min.0 < max.0 && min.1 < max.1)) {
+            return Err(InvalidSetting::new(
+                "domain",
+                "domain must have its minimum corner below and left of its maximum corner",
+            ));
+        }
+
+        if self.restitution.is_some_and(|e| !(0.0..=1.0).contains(&e)) {
+            return Err(InvalidSetting::new("restitution", "restitution must be between 0 and 1"));
+        }
+
+        if self.contain_strength.is_some_and(|k| !k.is_finite() || k < 0.0) {
+            return Err(InvalidSetting::new("contain_strength", "containment strength must not be negative"));
+        }
+
+        if self.gravity.is_some_and(|g| !g.is_finite()) {
+            return Err(InvalidSetting::new("gravity", "gravitational constant must be finite"));
+        }
+
+        if self.softening.is_some_and(|eps| !eps.is_finite() || eps <= 0.0) {
+            return Err(InvalidSetting::new("softening", "softening length must be positive"));
+        }
+
+        if self.theta.is_some_and(|theta| !theta.is_finite() || theta < 0.0) {
+            return Err(InvalidSetting::new("theta", "opening angle must not be negative"));
+        }
+
+        if self.color_range.is_some_and(|range| !range.is_finite() || range <= 0.0) {
+            return Err(InvalidSetting::new("color_range", "color range must be positive"));
+        }
+
+        Ok(())
+    }
+
+    /// Checks the settings that depend on each other, which may come from different places.
+    fn validate_combination(&self) -> Result<(), InvalidSetting> {
+        if self
+            .initial_particles
+            .is_some_and(|alive| alive > self.particle_count.unwrap_or(DEFAULT_PARTICLE_COUNT))
+        {
+            return Err(InvalidSetting::new("initial_particles", "initial particle count must not exceed the particle count"));
+        }
+
+        if self.interpolate == Some(true) && self.substep_dt.is_none() {
+            return Err(InvalidSetting::new("interpolate", "--interpolate needs a substep timestep (--substep-dt)"));
+        }
+
+        Ok(())
+    }
+
+    /// Fills in everything that wasn't given on the command line from `config`.
+    pub fn apply_config(&mut self, config: Config) {
+        self.seed = self.seed.or(config.seed);
+        self.fixed_dt = self.fixed_dt.or(config.fixed_dt);
+        self.time_scale = self.time_scale.or(config.time_scale);
+        self.max_dt = self.max_dt.or(config.max_dt);
+        self.step_frames = self.step_frames.or(config.step_frames);
+        self.substep_dt = self.substep_dt.or(config.substep_dt);
+        self.max_substeps = self.max_substeps.or(config.max_substeps);
+        self.interpolate = self.interpolate.or(config.interpolate);
+        self.particle_count = self.particle_count.or(config.particle_count);
+        self.initial_particles = self.initial_particles.or(config.initial_particles);
+        self.emitters = self.emitters.take().or(config.emitters);
+        self.initial = self.initial.take().or(config.initial);
+        let window = config.window.unwrap_or_default();
+        self.width = self.width.or(window.width);
+        self.height = self.height.or(window.height);
+        self.title = self.title.take().or(window.title);
+        self.fullscreen = self.fullscreen.or(window.fullscreen);
+        self.vsync = self.vsync.or(window.vsync);
+        self.quad_size = self.quad_size.or(config.quad_size);
+        self.clear_color = self.clear_color.or(config.clear_color);
+        let camera = config.camera.unwrap_or_default();
+        self.camera_center = self.camera_center.or(camera.center);
+        self.camera_zoom = self.camera_zoom.or(camera.zoom);
+        self.fit_view = self.fit_view.or(camera.fit);
+        self.force_model = self.force_model.or(config.force_model);
+        self.gravity = self.gravity.or(config.gravity);
+        self.softening = self.softening.or(config.softening);
+        self.theta = self.theta.or(config.theta);
+        self.integrator = self.integrator.or(config.integrator);
+        self.boundary = self.boundary.or(config.boundary);
+        self.domain = self.domain.or_else(|| config.domain.map(|[min_x, min_y, max_x, max_y]| ((min_x, min_y), (max_x, max_y))));
+        self.restitution = self.restitution.or(config.restitution);
+        self.contain_strength = self.contain_strength.or(config.contain_strength);
+        self.color_mode = self.color_mode.or(config.color_mode);
+        self.gradient = self.gradient.take().or(config.gradient);
+        self.color_range = self.color_range.or(config.color_range);
+        self.keymap = self.keymap.take().or(config.keymap);
+        self.diagnostics = self.diagnostics.take().or(config.diagnostics);
+        self.diagnostics_every = self.diagnostics_every.or(config.diagnostics_every);
+        self.histogram_bins = self.histogram_bins.or(config.histogram_bins);
+    }
+
+    pub fn sim_params(&self) -> SimParams {
+        let defaults = SimParams::default();
+
+        SimParams {
+            force_model: self.force_model.unwrap_or(defaults.force_model),
+            g: self.gravity.unwrap_or(defaults.g),
+            softening: self.softening.unwrap_or(defaults.softening),
+            theta: self.theta.unwrap_or(defaults.theta),
+            integrator: self.integrator.unwrap_or(defaults.integrator),
+            boundary: Boundary {
+                kind: self.boundary.unwrap_or(defaults.boundary.kind),
+                min: self.domain.map_or(defaults.boundary.min, |(min, _)| min),
+                max: self.domain.map_or(defaults.boundary.max, |(_, max)| max),
+                restitution: self.restitution.unwrap_or(defaults.boundary.restitution),
+                strength: self.contain_strength.unwrap_or(defaults.boundary.strength),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn merged(options: Options, config: Config) -> Options {
+        let mut options = options;
+        options.apply_config(config);
+        options
+    }
+
+    fn invalid_key(options: &Options) -> Option<String> {
+        options.validate().err().map(|invalid| invalid.key)
+    }
+
+    #[test]
+    fn command_line_takes_precedence() {
+        let options = merged(
+            Options {
+                gravity: Some(2.0),
+                ..Options::default()
+            },
+            Config {
+                gravity: Some(1.0),
+                softening: Some(0.1),
+                ..Config::default()
+            },
+        );
+        assert_eq!(options.gravity, Some(2.0), "the command line value wins");
+        assert_eq!(options.softening, Some(0.1), "the file fills in the rest");
+    }
+
+    #[test]
+    fn interpolate_in_file_with_substeps_on_command_line() {
+        let config = Config {
+            interpolate: Some(true),
+            ..Config::default()
+        };
+        let file_only = merged(Options::default(), config.clone());
+        assert!(file_only.validate_ranges().is_ok(), "the file on its own is in range");
+        assert_eq!(invalid_key(&file_only).as_deref(), Some("interpolate"), "nothing gives a substep");
+
+        let options = merged(
+            Options {
+                substep_dt: Some(0.01),
+                ..Options::default()
+            },
+            config,
+        );
+        assert_eq!(invalid_key(&options), None, "the command line gives the substep");
+    }
+
+    #[test]
+    fn initial_particles_against_command_line_count() {
+        let config = Config {
+            initial_particles: Some(500),
+            ..Config::default()
+        };
+        assert_eq!(
+            invalid_key(&merged(Options::default(), config.clone())).as_deref(),
+            Some("initial_particles"),
+            "more than the default count"
+        );
+
+        let options = merged(
+            Options {
+                particle_count: Some(1000),
+                ..Options::default()
+            },
+            config,
+        );
+        assert_eq!(invalid_key(&options), None, "within the command line count");
+    }
+
+    #[test]
+    fn range_errors_name_their_key() {
+        let cases = [
+            (
+                Options {
+                    softening: Some(0.0),
+                    ..Options::default()
+                },
+                "softening",
+            ),
+            (
+                Options {
+                    particle_count: Some(MAX_PARTICLES + 1),
+                    ..Options::default()
+                },
+                "particle_count",
+            ),
+            (
+                Options {
+                    initial_particles: Some(MAX_PARTICLES + 1),
+                    ..Options::default()
+                },
+                "initial_particles",
+            ),
+            (
+                Options {
+                    height: Some(0),
+                    ..Options::default()
+                },
+                "window.height",
+            ),
+        ];
+        for (options, key) in cases {
+            assert_eq!(options.validate_ranges().err().map(|invalid| invalid.key).as_deref(), Some(key), "wrong key");
+        }
+    }
+}
@@ -0,0 +1,232 @@
+use crate::vec2::Vector2;
+
+use super::SimParams;
+
+/// Deeper than this, coincident particles are lumped into one leaf instead of splitting forever.
+const MAX_DEPTH: u32 = 32;
+
+/// One node of a flattened Barnes-Hut quadtree, laid out to match `QuadNode` in `compute.glsl` (std430).
+///
+/// The tree is walked without a stack: a node's non-empty children are stored next to each other
+/// starting at `first_child`, and `next` points at the node to visit once this subtree is done
+/// (its next sibling, or its parent's `next`), `-1` at the end of the walk.
+#[derive(Debug, Clone, Default)]
+#[repr(C)]
+pub struct QuadNode {
+    pub center_of_mass: Vector2,
+    pub mass: f32,
+    /// Side length of the node's square.
+    pub size: f32,
+    /// `-1` for leaves.
+    pub first_child: i32,
+    pub next: i32,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct QuadTree {
+    nodes: Vec<QuadNode>,
+}
+
+impl QuadTree {
+    /// Builds a tree of unit masses at `positions`.
+    pub fn build(positions: &[Vector2]) -> Self {
+        if positions.is_empty() {
+            return Self::default();
+        }
+
+        let (mut min_x, mut min_y) = (f32::INFINITY, f32::INFINITY);
+        let (mut max_x, mut max_y) = (f32::NEG_INFINITY, f32::NEG_INFINITY);
+        for p in positions {
+            min_x = min_x.min(p.x);
+            min_y = min_y.min(p.y);
+            max_x = max_x.max(p.x);
+            max_y = max_y.max(p.y);
+        }
+
+        // Slightly larger than the bounding box so the particles on the max edges fall inside.
+        let mut size = (max_x - min_x).max(max_y - min_y) * 1.0001;
+        if !(size.is_finite() && size > 0.0) {
+            size = 1.0;
+        }
+
+        let mut indices: Vec<u32> = (0..positions.len() as u32).collect();
+        let mut nodes = vec![QuadNode::default()];
+        build_node(&mut nodes, 0, positions, &mut indices, (min_x, min_y), size, 0, -1);
+
+        Self { nodes }
+    }
+
+    pub fn nodes(&self) -> &[QuadNode] {
+        &self.nodes
+    }
+
+    /// Gravitational acceleration at `pos`, opening every node whose `size / distance` is at least
+    /// `params.theta`. The same walk as `barnesHutAcceleration` in `compute.glsl`.
+    pub fn acceleration(&self, pos: &Vector2, params: &SimParams) -> Vector2 {
+        let soft2 = params.softening * params.softening;
+        let theta2 = params.theta * params.theta;
+        let mut acc = Vector2::default();
+
+        let mut node = if self.nodes.is_empty() { -1 } else { 0 };
+        while let Some(n) = usize::try_from(node).ok().and_then(|i| self.nodes.get(i)) {
+            let mut d = pos.clone();
+            d.to(&n.center_of_mass);
+            let dist2 = d.mag_sq();
+            let size2 = n.size * n.size;
+
+            if n.first_child < 0 || size2 < theta2 * dist2 {
+                let r2 = dist2 + soft2;
+                // A leaf holding only `pos` itself, 0 / 0 without softening.
+                if r2 > 0.0 {
+                    d.scale(params.g * n.mass / (r2 * r2.sqrt()));
+                    acc.add_vec(&d);
+                }
+                node = n.next;
+            } else {
+                node = n.first_child;
+            }
+        }
+
+        acc
+    }
+
+    /// Gravitational potential at `pos` from every particle but the one at `pos`, with the same opening
+    /// rule as [`Self::acceleration`].
+    pub fn potential(&self, pos: &Vector2, params: &SimParams) -> f32 {
+        let soft2 = params.softening * params.softening;
+        let theta2 = params.theta * params.theta;
+        let mut phi = 0.0;
+
+        let mut node = if self.nodes.is_empty() { -1 } else { 0 };
+        while let Some(n) = usize::try_from(node).ok().and_then(|i| self.nodes.get(i)) {
+            let mut d = pos.clone();
+            d.to(&n.center_of_mass);
+            let dist2 = d.mag_sq();
+            let size2 = n.size * n.size;
+
+            if n.first_child < 0 || size2 < theta2 * dist2 {
+                // The leaf holding `pos` itself.
+                if dist2 > 0.0 {
+                    phi -= params.g * n.mass / (dist2 + soft2).sqrt();
+                }
+                node = n.next;
+            } else {
+                node = n.first_child;
+            }
+        }
+
+        phi
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn build_node(nodes: &mut Vec<QuadNode>, node: usize, positions: &[Vector2], indices: &mut [u32], min: (f32, f32), size: f32, depth: u32, next: i32) {
+    // Averaged as offsets from the first particle, so particles stacked on one spot get exactly that spot
+    // and not a rounded mean a hair away from it, which would pull on them as if from a tiny distance.
+    let origin = &positions[indices[0] as usize];
+    let mut offset = Vector2::default();
+    for &i in indices.iter() {
+        let mut d = positions[i as usize].clone();
+        d.from(origin);
+        offset.add_vec(&d);
+    }
+    offset.scale(1.0 / indices.len() as f32);
+    let mut com = origin.clone();
+    com.add_vec(&offset);
+
+    nodes[node] = QuadNode {
+        center_of_mass: com,
+        mass: indices.len() as f32,
+        size,
+        first_child: -1,
+        next,
+    };
+
+    if indices.len() == 1 || depth >= MAX_DEPTH {
+        return;
+    }
+
+    let half = size * 0.5;
+    let (cx, cy) = (min.0 + half, min.1 + half);
+    let quadrant = |i: &u32| {
+        let p = &positions[*i as usize];
+        usize::from(p.x >= cx) | (usize::from(p.y >= cy) << 1)
+    };
+    indices.sort_unstable_by_key(quadrant);
+
+    let mut counts = [0; 4];
+    for i in indices.iter() {
+        counts[quadrant(i)] += 1;
+    }
+
+    let children = counts.iter().filter(|&&c| c > 0).count();
+    let first_child = nodes.len();
+    nodes.resize(first_child + children, QuadNode::default());
+    nodes[node].first_child = first_child as i32;
+
+    let mut child = first_child;
+    let mut start = 0;
+    for (q, &count) in counts.iter().enumerate() {
+        if count == 0 {
+            continue;
+        }
+
+        let child_min = (if q & 1 == 0 { min.0 } else { cx }, if q & 2 == 0 { min.1 } else { cy });
+        let child_next = if child + 1 < first_child + children { (child + 1) as i32 } else { next };
+
+        build_node(nodes, child, positions, &mut indices[start..start + count], child_min, half, depth + 1, child_next);
+
+        child += 1;
+        start += count;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::opengl::render::renderstate::seeded_rng;
+    use crate::simulation::cpu::nbody_acceleration;
+
+    /// Scattered particles, a few of them stacked on the same spots.
+    fn positions() -> Vec<Vector2> {
+        let mut rng = seeded_rng(11);
+        let mut positions: Vec<Vector2> = (0..200)
+            .map(|_| Vector2::new(rng.next_f32().mul_add(2.0, -1.0), rng.next_f32().mul_add(2.0, -1.0)))
+            .collect();
+        for i in 0..5 {
+            positions.push(positions[i].clone());
+            positions.push(positions[i].clone());
+        }
+        positions
+    }
+
+    #[test]
+    fn theta_zero_matches_direct_sum() {
+        let positions = positions();
+        let tree = QuadTree::build(&positions);
+
+        for softening in [0.0, 0.05] {
+            let params = SimParams {
+                g: 1.0,
+                theta: 0.0,
+                softening,
+                ..SimParams::default()
+            };
+            for (i, pos) in positions.iter().enumerate() {
+                let tree_acc = tree.acceleration(pos, &params);
+                let direct = nbody_acceleration(pos, &positions, &params);
+                let error = (tree_acc.x - direct.x).hypot(tree_acc.y - direct.y);
+                assert!(
+                    tree_acc.x.is_finite() && tree_acc.y.is_finite() && error <= 1e-4 * direct.mag().max(1.0),
+                    "particle {} with softening {}: tree gives ({}, {}), direct sum ({}, {})",
+                    i,
+                    softening,
+                    tree_acc.x,
+                    tree_acc.y,
+                    direct.x,
+                    direct.y
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,92 @@
+use core::time::Duration;
+use std::time::Instant;
+
+use rayon::prelude::*;
+
+use crate::opengl::render::{particle::RenderData, renderstate::seeded_rng};
+use crate::vec2::Vector2;
+
+use super::barnes_hut::QuadTree;
+use super::cpu::nbody_acceleration;
+use super::SimParams;
+
+/// Particle count for `bench` when `--particles` isn't given.
+pub const DEFAULT_BENCH_PARTICLES: usize = 20_000;
+
+/// Opening angles tried by `bench` when `--theta` isn't given.
+pub const BENCH_THETAS: [f32; 5] = [0.2, 0.35, 0.5, 0.75, 1.0];
+
+/// Times the brute-force and Barnes-Hut force calculations on the same random particles and prints
+/// how far the approximation is from the exact accelerations for each opening angle.
+pub fn run(particle_count: usize, seed: u64, params: &SimParams, thetas: &[f32]) {
+    let mut rng = seeded_rng(seed);
+    let positions: Vec<Vector2> = RenderData::new(&mut rng, particle_count)
+        .data()
+        .iter()
+        .map(|p| p.pos.clone())
+        .collect();
+
+    println!("Benchmarking {} particles (seed {})", particle_count, seed);
+
+    let started = Instant::now();
+    let exact: Vec<Vector2> = positions
+        .par_iter()
+        .map(|pos| nbody_acceleration(pos, &positions, params))
+        .collect();
+    let brute_force = started.elapsed();
+
+    println!("{:>8} {:>12} {:>12} {:>10} {:>12} {:>12}", "theta", "build", "forces", "speedup", "rms error", "max error");
+    println!("{:>8} {:>12} {:>12} {:>10} {:>12} {:>12}", "brute", "-", fmt_ms(brute_force), "1.00x", "-", "-");
+
+    for &theta in thetas {
+        let params = SimParams { theta, ..params.clone() };
+
+        let started = Instant::now();
+        let tree = QuadTree::build(&positions);
+        let build = started.elapsed();
+
+        let started = Instant::now();
+        let approx: Vec<Vector2> = positions.par_iter().map(|pos| tree.acceleration(pos, &params)).collect();
+        let forces = started.elapsed();
+
+        let (rms, max) = relative_error(&exact, &approx);
+        let speedup = brute_force.as_secs_f64() / (build + forces).as_secs_f64();
+
+        println!(
+            "{:>8} {:>12} {:>12} {:>9.2}x {:>12.3e} {:>12.3e}",
+            theta,
+            fmt_ms(build),
+            fmt_ms(forces),
+            speedup,
+            rms,
+            max
+        );
+    }
+}
+
+/// RMS of the error relative to the RMS of the exact accelerations, and the largest per-particle relative error.
+fn relative_error(exact: &[Vector2], approx: &[Vector2]) -> (f64, f64) {
+    let mut err_sq = 0.0;
+    let mut exact_sq = 0.0;
+    let mut max = 0.0f64;
+
+    for (e, a) in exact.iter().zip(approx) {
+        let mut d = e.clone();
+        d.to(a);
+        let d2 = d.mag_sq() as f64;
+        let e2 = e.mag_sq() as f64;
+
+        err_sq += d2;
+        exact_sq += e2;
+        if e2 > 0.0 {
+            max = max.max((d2 / e2).sqrt());
+        }
+    }
+
+    let rms = if exact_sq > 0.0 { (err_sq / exact_sq).sqrt() } else { 0.0 };
+    (rms, max)
+}
+
+fn fmt_ms(d: Duration) -> String {
+    format!("{:.2}ms", d.as_secs_f64() * 1000.0)
+}
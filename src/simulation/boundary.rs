@@ -0,0 +1,300 @@
+use core::fmt::{self, Display};
+use core::str::FromStr;
+
+use anyhow::{bail, Context as AnyhowContextTrait, Error, Result};
+use rayon::prelude::*;
+use serde::Deserialize;
+
+use crate::opengl::render::particle::Particle;
+use crate::vec2::Vector2;
+
+/// Lower-left corner of the domain when `--domain` isn't given.
+pub const DEFAULT_DOMAIN_MIN: (f32, f32) = (-1.0, -1.0);
+/// Upper-right corner of the domain when `--domain` isn't given.
+pub const DEFAULT_DOMAIN_MAX: (f32, f32) = (1.0, 1.0);
+
+/// Fraction of the normal velocity kept when bouncing off a wall, 1 is perfectly elastic.
+pub const DEFAULT_RESTITUTION: f32 = 1.0;
+
+/// Spring constant of the containment force, acceleration per world unit outside the domain.
+pub const DEFAULT_CONTAIN_STRENGTH: f32 = 50.0;
+
+/// What happens at the edges of the domain. Discriminants match the `BOUNDARY_*` constants in `compute.glsl`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BoundaryKind {
+    /// No domain, particles fly off forever.
+    #[default]
+    None = 0,
+    /// Walls that mirror particles back in, keeping `restitution` of their normal velocity.
+    Reflect = 1,
+    /// Leaving through one edge re-enters through the opposite one. Only positions wrap, forces don't
+    /// reach across the edges.
+    Periodic = 2,
+    /// Particles that leave are respawned at rest at a random point inside.
+    Absorb = 3,
+    /// A spring force pulls particles back once they're outside, they can overshoot for a while.
+    Contain = 4,
+}
+
+impl BoundaryKind {
+    /// Whether [`ComputePass::Boundary`](super::ComputePass::Boundary) has anything to do.
+    pub const fn needs_pass(self) -> bool {
+        matches!(self, Self::Reflect | Self::Periodic | Self::Absorb)
+    }
+}
+
+impl FromStr for BoundaryKind {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Self::None),
+            "reflect" => Ok(Self::Reflect),
+            "periodic" => Ok(Self::Periodic),
+            "absorb" => Ok(Self::Absorb),
+            "contain" => Ok(Self::Contain),
+            _ => bail!("unknown boundary `{}`, expected `none`, `reflect`, `periodic`, `absorb` or `contain`", s),
+        }
+    }
+}
+
+impl Display for BoundaryKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::None => write!(f, "none"),
+            Self::Reflect => write!(f, "reflect"),
+            Self::Periodic => write!(f, "periodic"),
+            Self::Absorb => write!(f, "absorb"),
+            Self::Contain => write!(f, "contain"),
+        }
+    }
+}
+
+/// The simulation domain and how its edges behave (`uBoundary`, `uDomainMin`, `uDomainMax`, ...).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Boundary {
+    pub kind: BoundaryKind,
+    pub min: (f32, f32),
+    pub max: (f32, f32),
+    /// Used by [`BoundaryKind::Reflect`].
+    pub restitution: f32,
+    /// Used by [`BoundaryKind::Contain`].
+    pub strength: f32,
+}
+
+impl Default for Boundary {
+    fn default() -> Self {
+        Self {
+            kind: BoundaryKind::default(),
+            min: DEFAULT_DOMAIN_MIN,
+            max: DEFAULT_DOMAIN_MAX,
+            restitution: DEFAULT_RESTITUTION,
+            strength: DEFAULT_CONTAIN_STRENGTH,
+        }
+    }
+}
+
+impl Boundary {
+    /// Parses a domain given as `min_x,min_y,max_x,max_y`.
+    pub fn parse_domain(s: &str) -> Result<((f32, f32), (f32, f32))> {
+        let values = s
+            .split(',')
+            .map(|v| v.trim().parse::<f32>().with_context(|| format!("invalid domain coordinate `{}`", v.trim())))
+            .collect::<Result<Vec<f32>>>()?;
+
+        let [min_x, min_y, max_x, max_y] = values[..] else {
+            bail!("domain `{}` must be four numbers, min_x,min_y,max_x,max_y", s);
+        };
+        if !(min_x < max_x && min_y < max_y) {
+            bail!("domain `{}` must have its minimum corner below and left of its maximum corner", s);
+        }
+
+        Ok(((min_x, min_y), (max_x, max_y)))
+    }
+}
+
+/// Containment spring for a particle at `pos`, zero inside the domain or with any other boundary kind.
+pub fn contain_acceleration(pos: &Vector2, boundary: &Boundary) -> Vector2 {
+    if boundary.kind != BoundaryKind::Contain {
+        return Vector2::default();
+    }
+
+    Vector2::new(
+        contain_axis(pos.x, boundary.min.0, boundary.max.0, boundary.strength),
+        contain_axis(pos.y, boundary.min.1, boundary.max.1, boundary.strength),
+    )
+}
+
+fn contain_axis(pos: f32, min: f32, max: f32, strength: f32) -> f32 {
+    if pos < min {
+        strength * (min - pos)
+    } else if pos > max {
+        strength * (max - pos)
+    } else {
+        0.0
+    }
+}
+
+/// The boundary pass of `compute.glsl`: bounces, wraps or respawns the particles that left the domain.
+///
+/// `step` varies the respawn positions from step to step, the particle index varies them within one.
+///
+/// Returns the indices of the particles that were wrapped or respawned, which jumped instead of moving.
+pub fn apply_boundary(particles: &mut [Particle], boundary: &Boundary, step: u32) -> Vec<u32> {
+    if !boundary.kind.needs_pass() {
+        return Vec::new();
+    }
+
+    particles
+        .par_iter_mut()
+        .enumerate()
+        .filter_map(|(idx, p)| apply_to_particle(p, idx as u32, boundary, step).then_some(idx as u32))
+        .collect()
+}
+
+/// Whether the particle jumped, see [`apply_boundary`].
+fn apply_to_particle(p: &mut Particle, idx: u32, boundary: &Boundary, step: u32) -> bool {
+    if !p.is_alive() {
+        return false;
+    }
+
+    let (min, max) = (boundary.min, boundary.max);
+
+    match boundary.kind {
+        BoundaryKind::Reflect => {
+            (p.pos.x, p.vel.x) = reflect_axis(p.pos.x, p.vel.x, min.0, max.0, boundary.restitution);
+            (p.pos.y, p.vel.y) = reflect_axis(p.pos.y, p.vel.y, min.1, max.1, boundary.restitution);
+            false
+        }
+        BoundaryKind::Periodic => {
+            // Checked first, wrapping a coordinate that's already inside can still round it by an ulp.
+            let outside = !(min.0..max.0).contains(&p.pos.x) || !(min.1..max.1).contains(&p.pos.y);
+            p.pos.x = wrap_axis(p.pos.x, min.0, max.0);
+            p.pos.y = wrap_axis(p.pos.y, min.1, max.1);
+            outside
+        }
+        BoundaryKind::Absorb => {
+            let inside = (min.0..=max.0).contains(&p.pos.x) && (min.1..=max.1).contains(&p.pos.y);
+            if !inside {
+                let h1 = pcg_hash(idx ^ pcg_hash(step));
+                let h2 = pcg_hash(h1);
+                p.pos.set((max.0 - min.0).mul_add(unit_float(h1), min.0), (max.1 - min.1).mul_add(unit_float(h2), min.1));
+                p.vel = Vector2::default();
+                p.acc = Vector2::default();
+            }
+            !inside
+        }
+        BoundaryKind::None | BoundaryKind::Contain => false,
+    }
+}
+
+/// Mirrors a coordinate that overshot a wall back inside, scaled by `restitution` like the velocity.
+fn reflect_axis(pos: f32, vel: f32, min: f32, max: f32, restitution: f32) -> (f32, f32) {
+    let (pos, vel) = if pos < min {
+        ((min - pos).mul_add(restitution, min), vel.abs() * restitution)
+    } else if pos > max {
+        ((pos - max).mul_add(-restitution, max), -vel.abs() * restitution)
+    } else {
+        return (pos, vel);
+    };
+
+    (pos.clamp(min, max), vel)
+}
+
+fn wrap_axis(pos: f32, min: f32, max: f32) -> f32 {
+    let size = max - min;
+    let t = pos - min;
+    min + (-size).mul_add((t / size).floor(), t)
+}
+
+/// PCG hash, the same as `pcgHash` in `compute.glsl`.
+pub const fn pcg_hash(v: u32) -> u32 {
+    let state = v.wrapping_mul(747_796_405).wrapping_add(2_891_336_453);
+    let word = ((state >> ((state >> 28) + 4)) ^ state).wrapping_mul(277_803_737);
+    (word >> 22) ^ word
+}
+
+/// Maps a hash to `[0, 1)` using its top 24 bits, which convert to `f32` exactly. Same as `unitFloat`.
+pub fn unit_float(h: u32) -> f32 {
+    (h >> 8) as f32 * (1.0 / 16_777_216.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn boundary(kind: BoundaryKind) -> Boundary {
+        Boundary {
+            kind,
+            restitution: 0.5,
+            ..Boundary::default()
+        }
+    }
+
+    fn particle(x: f32, y: f32, vx: f32, vy: f32) -> Particle {
+        Particle {
+            pos: Vector2::new(x, y),
+            vel: Vector2::new(vx, vy),
+            ..Particle::default()
+        }
+    }
+
+    #[test]
+    fn wraps_more_than_one_domain_width() {
+        assert_eq!(wrap_axis(3.5, -1.0, 1.0), -0.5, "two widths past the max");
+        assert_eq!(wrap_axis(-5.25, -1.0, 1.0), 0.75, "three widths past the min");
+        assert_eq!(wrap_axis(1.0, -1.0, 1.0), -1.0, "the max edge maps to the min edge");
+        assert_eq!(wrap_axis(0.25, -1.0, 1.0), 0.25, "inside stays put");
+    }
+
+    #[test]
+    fn periodic_reports_wrapped_particles() {
+        let mut particles = vec![particle(0.5, 0.5, 1.0, 0.0), particle(3.5, -5.25, 1.0, 0.0)];
+        let jumped = apply_boundary(&mut particles, &boundary(BoundaryKind::Periodic), 0);
+        assert_eq!(jumped, [1], "only the particle outside wrapped");
+        assert_eq!((particles[1].pos.x, particles[1].pos.y), (-0.5, 0.75), "wrapped position");
+        assert_eq!(particles[1].vel.x, 1.0, "wrapping keeps the velocity");
+    }
+
+    #[test]
+    fn reflect_applies_restitution() {
+        let mut particles = vec![particle(1.2, -1.4, 2.0, -4.0), particle(0.0, 0.0, 2.0, -4.0)];
+        let jumped = apply_boundary(&mut particles, &boundary(BoundaryKind::Reflect), 0);
+        assert!(jumped.is_empty(), "reflecting is continuous");
+
+        let p = &particles[0];
+        assert!((p.pos.x - 0.9).abs() < 1e-6 && (p.pos.y + 0.8).abs() < 1e-6, "overshoot halved, got {:?}", p.pos);
+        assert_eq!((p.vel.x, p.vel.y), (-1.0, 2.0), "velocity reversed and halved");
+        assert_eq!((particles[1].vel.x, particles[1].vel.y), (2.0, -4.0), "particles inside are untouched");
+    }
+
+    #[test]
+    fn reflect_clamps_large_overshoots() {
+        let (pos, vel) = reflect_axis(9.0, 1.0, -1.0, 1.0, 1.0);
+        assert_eq!((pos, vel), (-1.0, -1.0), "overshooting past the far wall is clamped to it");
+    }
+
+    #[test]
+    fn absorb_respawns_inside_at_rest() {
+        let mut particles = vec![particle(2.0, 0.0, 1.0, 1.0), particle(0.0, 0.0, 1.0, 1.0)];
+        let jumped = apply_boundary(&mut particles, &boundary(BoundaryKind::Absorb), 3);
+        assert_eq!(jumped, [0], "only the particle outside respawned");
+
+        let p = &particles[0];
+        assert!((-1.0..=1.0).contains(&p.pos.x) && (-1.0..=1.0).contains(&p.pos.y), "respawned outside, at {:?}", p.pos);
+        assert_eq!((p.vel.x, p.vel.y), (0.0, 0.0), "respawned particles start at rest");
+    }
+
+    #[test]
+    fn parses_domains() {
+        let domain = Boundary::parse_domain("-2, -1, 2, 1").expect("valid domain");
+        assert_eq!(domain, ((-2.0, -1.0), (2.0, 1.0)), "corners in order");
+
+        assert!(Boundary::parse_domain("1,0,1,2").is_err(), "zero width accepted");
+        assert!(Boundary::parse_domain("0,2,1,1").is_err(), "min_y above max_y accepted");
+        assert!(Boundary::parse_domain("0,0,NaN,1").is_err(), "NaN accepted");
+        assert!(Boundary::parse_domain("0,0,1").is_err(), "three numbers accepted");
+        assert!(Boundary::parse_domain("0,0,1,x").is_err(), "non-number accepted");
+    }
+}
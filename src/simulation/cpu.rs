@@ -0,0 +1,268 @@
+use anyhow::Result;
+use rayon::prelude::*;
+
+use crate::opengl::{
+    program::Program,
+    render::{particle::Particle, renderstate::RenderState},
+    uniform::UniformLocations,
+};
+use crate::vec2::Vector2;
+
+use super::barnes_hut::QuadTree;
+use super::boundary::{apply_boundary, contain_acceleration, BoundaryKind};
+use super::emitter::{advance_lifetimes, emit, spawn_total};
+use super::integrator::{Integrator, Rk4State, RK4_OFFSETS, RK4_WEIGHTS};
+use super::tools::{tool_acceleration, ToolForce};
+use super::{BackendKind, ComputePass, ForceModel, SimParams, SimulationBackend};
+
+/// Reference implementation of `compute.glsl` that runs on the CPU with rayon.
+///
+/// The results are uploaded to the SSBO after every step, so the draw pass
+/// stays the same as with [`GpuBackend`](super::gpu::GpuBackend).
+#[derive(Debug, Default)]
+pub struct CpuBackend {
+    stepper: Stepper,
+}
+
+/// What [`step_particles`] keeps from one step to the next.
+#[derive(Debug, Default)]
+pub struct Stepper {
+    /// `acc` holds the accelerations at the current positions, see [`Integrator::reuses_forces`].
+    pub forces_valid: bool,
+    /// Steps taken so far, seeds the respawn positions of [`BoundaryKind::Absorb`].
+    pub step_count: u32,
+    /// Particles spawned, wrapped or respawned during the last step. They jumped, so their previous
+    /// position shouldn't be drawn blended with the new one.
+    pub jumped: Vec<u32>,
+    rk4: Vec<Rk4State>,
+}
+
+impl SimulationBackend for CpuBackend {
+    fn kind(&self) -> BackendKind {
+        BackendKind::Cpu
+    }
+
+    fn step(&mut self, render_state: &mut RenderState, _compute_program: &Program, _compute_uniforms: &UniformLocations, dt: f32) -> Result<()> {
+        let (x, y) = render_state.cursor_position;
+        let mouse_pos = Vector2::new(x, y);
+
+        let mut spawned = Vec::new();
+        let batches = render_state.plan_spawns(dt);
+        if !render_state.emitters.is_empty() || spawn_total(&batches) > 0 {
+            let free = render_state.free_slots.len();
+            advance_lifetimes(render_state.buffer.data_mut(), dt, &mut render_state.free_slots);
+            let died = render_state.free_slots.len() != free;
+            spawned = emit(render_state.buffer.data_mut(), &mut render_state.free_slots, &batches, render_state.step_count);
+
+            // New particles start without `acc`, and dead ones no longer pull on the rest.
+            if died || !spawned.is_empty() {
+                render_state.forces_valid = false;
+            }
+        }
+
+        let tool = render_state.tool_force.as_ref();
+        self.stepper.forces_valid = render_state.forces_valid;
+        self.stepper.step_count = render_state.step_count;
+        step_particles(render_state.buffer.data_mut(), &render_state.params, &mouse_pos, tool, dt, &mut self.stepper);
+        render_state.forces_valid = self.stepper.forces_valid;
+        render_state.step_count = self.stepper.step_count;
+        render_state.update_buffer_data();
+
+        self.stepper.jumped.extend(spawned);
+        render_state.reset_previous_positions(&self.stepper.jumped);
+
+        Ok(())
+    }
+}
+
+/// One step of `compute.glsl`: the passes of `params.integrator`, in the same order as on the GPU.
+pub fn step_particles(
+    particles: &mut [Particle],
+    params: &SimParams,
+    mouse_pos: &Vector2,
+    tool: Option<&ToolForce>,
+    dt: f32,
+    stepper: &mut Stepper,
+) {
+    let integrator = params.integrator;
+    stepper.jumped.clear();
+    if integrator.reuses_forces() && !stepper.forces_valid {
+        compute_forces(particles, params, mouse_pos, tool);
+    }
+
+    for &pass in integrator.passes() {
+        match pass {
+            ComputePass::Forces => compute_forces(particles, params, mouse_pos, tool),
+            ComputePass::Integrate => integrate(particles, dt),
+            ComputePass::Kick => kick(particles, 0.5 * dt),
+            ComputePass::Drift => drift(particles, dt),
+            ComputePass::VerletDrift => verlet_drift(particles, dt),
+            ComputePass::Rk4Stage1 => rk4_stage(particles, &mut stepper.rk4, 0, dt),
+            ComputePass::Rk4Stage2 => rk4_stage(particles, &mut stepper.rk4, 1, dt),
+            ComputePass::Rk4Stage3 => rk4_stage(particles, &mut stepper.rk4, 2, dt),
+            ComputePass::Rk4Stage4 => rk4_stage(particles, &mut stepper.rk4, 3, dt),
+            ComputePass::Boundary => stepper.jumped = apply_boundary(particles, &params.boundary, stepper.step_count),
+            // Run by the backend before the integrator's passes, they never appear in them.
+            ComputePass::Lifetime | ComputePass::Spawn | ComputePass::SpawnCommit => {}
+        }
+    }
+
+    stepper.forces_valid = integrator.reuses_forces();
+    stepper.step_count = stepper.step_count.wrapping_add(1);
+}
+
+/// Writes `acc` for every live particle from the current positions, plus the containment force and the
+/// pull of `tool` if one is held. Dead particles neither feel nor exert any force.
+pub fn compute_forces(particles: &mut [Particle], params: &SimParams, mouse_pos: &Vector2, tool: Option<&ToolForce>) {
+    match params.force_model {
+        ForceModel::Mouse => {
+            particles
+                .par_iter_mut()
+                .filter(|p| p.is_alive())
+                .for_each(|p| p.acc = mouse_acceleration(&p.pos, mouse_pos, params));
+        }
+        ForceModel::NBody => {
+            let positions = live_positions(particles);
+            particles
+                .par_iter_mut()
+                .filter(|p| p.is_alive())
+                .for_each(|p| p.acc = nbody_acceleration(&p.pos, &positions, params));
+        }
+        ForceModel::BarnesHut => {
+            let tree = QuadTree::build(&live_positions(particles));
+            particles
+                .par_iter_mut()
+                .filter(|p| p.is_alive())
+                .for_each(|p| p.acc = tree.acceleration(&p.pos, params));
+        }
+    }
+
+    if params.boundary.kind == BoundaryKind::Contain {
+        particles.par_iter_mut().filter(|p| p.is_alive()).for_each(|p| {
+            let acc = contain_acceleration(&p.pos, &params.boundary);
+            p.acc.add_vec(&acc);
+        });
+    }
+
+    if let Some(tool) = tool {
+        particles.par_iter_mut().filter(|p| p.is_alive()).for_each(|p| {
+            let acc = tool_acceleration(p, tool);
+            p.acc.add_vec(&acc);
+        });
+    }
+}
+
+/// Semi-implicit Euler, the same as the integrate pass of `compute.glsl`.
+pub fn integrate(particles: &mut [Particle], dt: f32) {
+    particles.par_iter_mut().filter(|p| p.is_alive()).for_each(|p| {
+        p.vel.add(p.acc.x * dt, p.acc.y * dt);
+        p.pos.add(p.vel.x * dt, p.vel.y * dt);
+    });
+}
+
+/// Velocity update only, [`ComputePass::Kick`].
+pub fn kick(particles: &mut [Particle], dt: f32) {
+    particles
+        .par_iter_mut()
+        .filter(|p| p.is_alive())
+        .for_each(|p| p.vel.add(p.acc.x * dt, p.acc.y * dt));
+}
+
+/// Position update only, [`ComputePass::Drift`].
+pub fn drift(particles: &mut [Particle], dt: f32) {
+    particles
+        .par_iter_mut()
+        .filter(|p| p.is_alive())
+        .for_each(|p| p.pos.add(p.vel.x * dt, p.vel.y * dt));
+}
+
+/// `pos += vel dt + acc dt² / 2`, then the old acceleration's half of the velocity update, [`ComputePass::VerletDrift`].
+pub fn verlet_drift(particles: &mut [Particle], dt: f32) {
+    let half = 0.5 * dt;
+    particles.par_iter_mut().filter(|p| p.is_alive()).for_each(|p| {
+        p.pos.add(p.acc.x.mul_add(half, p.vel.x) * dt, p.acc.y.mul_add(half, p.vel.y) * dt);
+        p.vel.add(p.acc.x * half, p.acc.y * half);
+    });
+}
+
+/// Stage `stage` (0 to 3) of [`Integrator::Rk4`], the same as the `PASS_RK4_*` passes of `compute.glsl`.
+///
+/// `vel` and `acc` are the derivatives at the current stage. They're added to the running sums, then the
+/// particle is moved to where the next stage is evaluated, or to the end of the step after the last one.
+pub fn rk4_stage(particles: &mut [Particle], states: &mut Vec<Rk4State>, stage: usize, dt: f32) {
+    if stage == 0 {
+        states.resize(particles.len(), Rk4State::default());
+    }
+
+    let weight = RK4_WEIGHTS[stage];
+    particles
+        .par_iter_mut()
+        .zip(states.par_iter_mut())
+        .for_each(|(p, s)| {
+            if !p.is_alive() {
+                return;
+            }
+            if stage == 0 {
+                *s = Rk4State {
+                    pos0: p.pos.clone(),
+                    vel0: p.vel.clone(),
+                    ..Rk4State::default()
+                };
+            }
+            s.sum_pos.add(p.vel.x * weight, p.vel.y * weight);
+            s.sum_vel.add(p.acc.x * weight, p.acc.y * weight);
+
+            if let Some(&offset) = RK4_OFFSETS.get(stage) {
+                let h = offset * dt;
+                let vel = p.vel.clone();
+                p.pos.set_vec(&s.pos0);
+                p.pos.add(vel.x * h, vel.y * h);
+                p.vel.set_vec(&s.vel0);
+                p.vel.add(p.acc.x * h, p.acc.y * h);
+            } else {
+                let h = dt / 6.0;
+                p.pos.set_vec(&s.pos0);
+                p.pos.add(s.sum_pos.x * h, s.sum_pos.y * h);
+                p.vel.set_vec(&s.vel0);
+                p.vel.add(s.sum_vel.x * h, s.sum_vel.y * h);
+            }
+        });
+}
+
+/// Positions of the live particles, the ones that attract the others.
+fn live_positions(particles: &[Particle]) -> Vec<Vector2> {
+    particles.iter().filter(|p| p.is_alive()).map(|p| p.pos.clone()).collect()
+}
+
+pub fn mouse_acceleration(pos: &Vector2, mouse_pos: &Vector2, params: &SimParams) -> Vector2 {
+    let mut dir = pos.clone();
+    dir.to(mouse_pos);
+    let dist = dir.mag() + params.softening;
+
+    let force_magnitude = params.g / (dist * dist);
+    dir.normalize();
+    dir.scale(force_magnitude);
+    dir
+}
+
+/// Pairwise gravity between unit masses with Plummer softening, the same as `nbodyAcceleration` in
+/// `compute.glsl`.
+pub fn nbody_acceleration(pos: &Vector2, positions: &[Vector2], params: &SimParams) -> Vector2 {
+    let soft2 = params.softening * params.softening;
+    let mut acc = Vector2::default();
+
+    for other in positions {
+        let mut d = pos.clone();
+        d.to(other);
+        let r2 = d.mag_sq() + soft2;
+        // The particle itself has d == 0. With softening it would contribute nothing, without it the
+        // term is 0 / 0, so it's skipped, along with any other particle at exactly the same spot.
+        if r2 == 0.0 {
+            continue;
+        }
+        d.scale(params.g / (r2 * r2.sqrt()));
+        acc.add_vec(&d);
+    }
+
+    acc
+}
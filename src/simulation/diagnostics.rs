@@ -0,0 +1,376 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context as AnyhowContextTrait, Result};
+use rayon::prelude::*;
+
+use crate::opengl::render::particle::Particle;
+use crate::vec2::Vector2;
+
+use super::barnes_hut::QuadTree;
+use super::{ForceModel, SimParams};
+
+/// Frames between two `--diagnostics` rows when `--diagnostics-every` isn't given.
+pub const DEFAULT_DIAGNOSTICS_EVERY: u32 = 60;
+
+/// Bins per velocity histogram when `--histogram-bins` isn't given.
+pub const DEFAULT_HISTOGRAM_BINS: usize = 16;
+
+/// Counts of values in equal-width bins between `min` and `max`, both inclusive.
+#[derive(Debug, Clone, Default)]
+pub struct Histogram {
+    pub min: f32,
+    pub max: f32,
+    pub counts: Vec<u32>,
+}
+
+impl Histogram {
+    /// Bins `values` over their own range. All of them land in the first bin if they're all equal.
+    pub fn new<I: Iterator<Item = f32> + Clone>(values: I, bins: usize) -> Self {
+        let (min, max) = values
+            .clone()
+            .fold((f32::INFINITY, f32::NEG_INFINITY), |(lo, hi), v| (lo.min(v), hi.max(v)));
+        let mut counts = vec![0; bins];
+
+        if bins == 0 || min > max {
+            return Self { min: 0.0, max: 0.0, counts };
+        }
+
+        let width = (max - min) / bins as f32;
+        for v in values {
+            let bin = if width > 0.0 { ((v - min) / width) as usize } else { 0 };
+            counts[bin.min(bins - 1)] += 1;
+        }
+
+        Self { min, max, counts }
+    }
+}
+
+/// Aggregate state of the particle set at one point in time. Every particle has unit mass.
+#[derive(Debug, Clone, Default)]
+pub struct Diagnostics {
+    /// Simulated time the particles were measured at.
+    pub time: f64,
+    pub particle_count: usize,
+    pub kinetic_energy: f64,
+    /// Potential energy of the force model, see [`Self::compute`].
+    pub potential_energy: f64,
+    pub momentum: (f64, f64),
+    /// About the origin.
+    pub angular_momentum: f64,
+    pub center_of_mass: (f64, f64),
+    pub bbox_min: Vector2,
+    pub bbox_max: Vector2,
+    pub speed: Histogram,
+    pub vel_x: Histogram,
+    pub vel_y: Histogram,
+}
+
+impl Diagnostics {
+    /// Measures the live `particles` at simulated time `time`. `mouse_pos` is only used by [`ForceModel::Mouse`].
+    ///
+    /// The potential energy is exact and O(N²) for [`ForceModel::NBody`], and a Barnes-Hut estimate in
+    /// O(N log N) for [`ForceModel::BarnesHut`], see [`estimated_potential_energy`]. Everything else is O(N).
+    pub fn compute(particles: &[Particle], params: &SimParams, mouse_pos: &Vector2, time: f64, bins: usize) -> Self {
+        let particles: Vec<Particle> = particles.iter().filter(|p| p.is_alive()).cloned().collect();
+        let mut bbox_min = Vector2::new(f32::INFINITY, f32::INFINITY);
+        let mut bbox_max = Vector2::new(f32::NEG_INFINITY, f32::NEG_INFINITY);
+        let mut center = (0.0, 0.0);
+
+        for p in &particles {
+            bbox_min.set(bbox_min.x.min(p.pos.x), bbox_min.y.min(p.pos.y));
+            bbox_max.set(bbox_max.x.max(p.pos.x), bbox_max.y.max(p.pos.y));
+            center.0 += f64::from(p.pos.x);
+            center.1 += f64::from(p.pos.y);
+        }
+
+        let count = particles.len().max(1) as f64;
+        if particles.is_empty() {
+            bbox_min = Vector2::default();
+            bbox_max = Vector2::default();
+        }
+
+        Self {
+            time,
+            particle_count: particles.len(),
+            kinetic_energy: kinetic_energy(&particles),
+            potential_energy: match params.force_model {
+                ForceModel::BarnesHut => estimated_potential_energy(&particles, params),
+                ForceModel::Mouse | ForceModel::NBody => potential_energy(&particles, params, mouse_pos),
+            },
+            momentum: momentum(&particles),
+            angular_momentum: angular_momentum(&particles),
+            center_of_mass: (center.0 / count, center.1 / count),
+            bbox_min,
+            bbox_max,
+            speed: Histogram::new(particles.iter().map(|p| p.vel.mag()), bins),
+            vel_x: Histogram::new(particles.iter().map(|p| p.vel.x), bins),
+            vel_y: Histogram::new(particles.iter().map(|p| p.vel.y), bins),
+        }
+    }
+
+    pub fn total_energy(&self) -> f64 {
+        self.kinetic_energy + self.potential_energy
+    }
+}
+
+pub fn kinetic_energy(particles: &[Particle]) -> f64 {
+    particles.iter().map(|p| 0.5 * f64::from(p.vel.mag_sq())).sum()
+}
+
+/// Potential energy whose gradient is the force of `params.force_model`.
+///
+/// That's the softened pairwise gravity for [`ForceModel::NBody`] and [`ForceModel::BarnesHut`], or the
+/// pull of the mouse cursor at `mouse_pos`.
+pub fn potential_energy(particles: &[Particle], params: &SimParams, mouse_pos: &Vector2) -> f64 {
+    let g = f64::from(params.g);
+    let softening = f64::from(params.softening);
+
+    match params.force_model {
+        ForceModel::Mouse => particles
+            .iter()
+            .map(|p| {
+                let mut d = p.pos.clone();
+                d.to(mouse_pos);
+                -g / (f64::from(d.mag()) + softening)
+            })
+            .sum(),
+        ForceModel::NBody | ForceModel::BarnesHut => {
+            let soft2 = softening * softening;
+            particles
+                .par_iter()
+                .enumerate()
+                .map(|(i, a)| {
+                    particles[i + 1..]
+                        .iter()
+                        .map(|b| {
+                            let dx = f64::from(b.pos.x - a.pos.x);
+                            let dy = f64::from(b.pos.y - a.pos.y);
+                            -g / dy.mul_add(dy, dx.mul_add(dx, soft2)).sqrt()
+                        })
+                        .sum::<f64>()
+                })
+                .sum()
+        }
+    }
+}
+
+/// Estimate of the gravitational [`potential_energy`] in O(N log N).
+///
+/// Summed over a quadtree the same way [`ForceModel::BarnesHut`] sums the forces. Zero softening counts
+/// particles at exactly the same position as not interacting.
+pub fn estimated_potential_energy(particles: &[Particle], params: &SimParams) -> f64 {
+    let positions: Vec<Vector2> = particles.iter().map(|p| p.pos.clone()).collect();
+    let tree = QuadTree::build(&positions);
+    let total: f64 = positions.par_iter().map(|pos| f64::from(tree.potential(pos, params))).sum();
+    // Every pair was counted from both ends.
+    0.5 * total
+}
+
+pub fn momentum(particles: &[Particle]) -> (f64, f64) {
+    particles
+        .iter()
+        .fold((0.0, 0.0), |(x, y), p| (x + f64::from(p.vel.x), y + f64::from(p.vel.y)))
+}
+
+/// Angular momentum about the origin.
+pub fn angular_momentum(particles: &[Particle]) -> f64 {
+    particles
+        .iter()
+        .map(|p| {
+            let (x, y) = (f64::from(p.pos.x), f64::from(p.pos.y));
+            x.mul_add(f64::from(p.vel.y), -(y * f64::from(p.vel.x)))
+        })
+        .sum()
+}
+
+/// Appends a row of [`Diagnostics`] to a CSV file every `every` simulation frames.
+#[derive(Debug)]
+pub struct DiagnosticsLog {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    every: u32,
+    bins: usize,
+    /// Frames advanced since the last row.
+    frames: u32,
+}
+
+impl DiagnosticsLog {
+    /// Creates (or truncates) `path` and writes the header row.
+    pub fn create(path: &Path, every: u32, bins: usize) -> Result<Self> {
+        if every == 0 {
+            bail!("diagnostics period must be at least 1 frame");
+        }
+
+        let file = File::create(path).with_context(|| format!("Failed to create diagnostics log {}", path.display()))?;
+        let mut log = Self {
+            path: path.to_path_buf(),
+            writer: BufWriter::new(file),
+            every,
+            bins,
+            frames: 0,
+        };
+        log.write_header()?;
+
+        Ok(log)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub const fn bins(&self) -> usize {
+        self.bins
+    }
+
+    /// Called once per frame the simulation advanced. Returns whether a row is due this frame.
+    pub const fn tick(&mut self) -> bool {
+        self.frames += 1;
+        if self.frames < self.every {
+            return false;
+        }
+        self.frames = 0;
+        true
+    }
+
+    fn write_header(&mut self) -> Result<()> {
+        write_header(&mut self.writer, self.bins).with_context(|| self.write_error())
+    }
+
+    /// Appends `diagnostics` as one row and flushes, so the file can be followed while the program runs.
+    pub fn write(&mut self, diagnostics: &Diagnostics) -> Result<()> {
+        write_row(&mut self.writer, diagnostics).with_context(|| self.write_error())
+    }
+
+    fn write_error(&self) -> String {
+        format!("Failed to write diagnostics log {}", self.path.display())
+    }
+}
+
+fn write_header<W: Write>(w: &mut W, bins: usize) -> io::Result<()> {
+    write!(
+        w,
+        "time,particles,kinetic_energy,potential_energy,total_energy,momentum_x,momentum_y,angular_momentum,\
+         com_x,com_y,bbox_min_x,bbox_min_y,bbox_max_x,bbox_max_y"
+    )?;
+    for name in ["speed", "vel_x", "vel_y"] {
+        write!(w, ",{}_min,{}_max", name, name)?;
+        for bin in 0..bins {
+            write!(w, ",{}_{}", name, bin)?;
+        }
+    }
+    writeln!(w)?;
+    w.flush()
+}
+
+fn write_row<W: Write>(w: &mut W, d: &Diagnostics) -> io::Result<()> {
+    write!(
+        w,
+        "{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+        d.time,
+        d.particle_count,
+        d.kinetic_energy,
+        d.potential_energy,
+        d.total_energy(),
+        d.momentum.0,
+        d.momentum.1,
+        d.angular_momentum,
+        d.center_of_mass.0,
+        d.center_of_mass.1,
+        d.bbox_min.x,
+        d.bbox_min.y,
+        d.bbox_max.x,
+        d.bbox_max.y
+    )?;
+    for histogram in [&d.speed, &d.vel_x, &d.vel_y] {
+        write!(w, ",{},{}", histogram.min, histogram.max)?;
+        for count in &histogram.counts {
+            write!(w, ",{}", count)?;
+        }
+    }
+    writeln!(w)?;
+    w.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use core::iter;
+
+    use super::*;
+    use crate::opengl::render::renderstate::seeded_rng;
+    use crate::simulation::drift::rotating_disk;
+
+    #[test]
+    fn histogram_bins_over_the_range() {
+        let histogram = Histogram::new([0.0, 0.1, 0.5, 0.9, 1.0].into_iter(), 4);
+        assert_eq!((histogram.min, histogram.max), (0.0, 1.0), "range of the values");
+        assert_eq!(histogram.counts, [2, 0, 1, 2], "the maximum lands in the last bin");
+    }
+
+    #[test]
+    fn histogram_of_equal_values() {
+        let histogram = Histogram::new([2.0; 3].into_iter(), 4);
+        assert_eq!(histogram.counts, [3, 0, 0, 0], "equal values all land in the first bin");
+    }
+
+    #[test]
+    fn histogram_of_nothing() {
+        let histogram = Histogram::new(iter::empty(), 3);
+        assert_eq!(histogram.counts, [0, 0, 0], "no values, empty bins");
+        assert!(Histogram::new([1.0].into_iter(), 0).counts.is_empty(), "no bins");
+    }
+
+    #[test]
+    fn csv_header_and_row_match() {
+        let particles = vec![
+            Particle {
+                pos: Vector2::new(1.0, 0.0),
+                vel: Vector2::new(0.0, 2.0),
+                ..Particle::default()
+            },
+            Particle {
+                alive: 0,
+                ..Particle::default()
+            },
+        ];
+        let diagnostics = Diagnostics::compute(&particles, &SimParams::default(), &Vector2::default(), 1.5, 2);
+
+        let mut header = Vec::new();
+        write_header(&mut header, 2).expect("writing to memory");
+        let header = String::from_utf8(header).expect("UTF-8 header");
+        let mut row = Vec::new();
+        write_row(&mut row, &diagnostics).expect("writing to memory");
+        let row = String::from_utf8(row).expect("UTF-8 row");
+
+        let names: Vec<&str> = header.trim_end().split(',').collect();
+        let values: Vec<&str> = row.trim_end().split(',').collect();
+        assert_eq!(names.len(), 14 + 3 * 4, "fixed columns plus min, max and 2 bins per histogram");
+        assert_eq!(names.len(), values.len(), "header and row have different column counts");
+
+        let value = |name: &str| values[names.iter().position(|&n| n == name).expect("column exists")];
+        assert_eq!(value("time"), "1.5", "time column");
+        assert_eq!(value("particles"), "1", "only live particles are counted");
+        assert_eq!(value("kinetic_energy"), "2", "kinetic energy column");
+        assert_eq!(value("angular_momentum"), "2", "angular momentum column");
+        assert_eq!(value("speed_0"), "1", "first speed bin");
+        assert_eq!(value("speed_1"), "0", "last speed bin");
+    }
+
+    #[test]
+    fn barnes_hut_estimate_is_close_to_exact() {
+        let particles = rotating_disk(&mut seeded_rng(7), 500, 1e-3);
+        let params = SimParams {
+            force_model: ForceModel::BarnesHut,
+            softening: 0.05,
+            ..SimParams::default()
+        };
+        let exact = potential_energy(&particles, &params, &Vector2::default());
+        let estimate = estimated_potential_energy(&particles, &params);
+        assert!(((estimate - exact) / exact).abs() < 1e-2, "estimate {} too far from {}", estimate, exact);
+
+        let exact_params = SimParams { theta: 0.0, ..params };
+        let opened = estimated_potential_energy(&particles, &exact_params);
+        assert!(((opened - exact) / exact).abs() < 1e-4, "theta 0 gave {}, expected {}", opened, exact);
+    }
+}
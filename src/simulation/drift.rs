@@ -0,0 +1,153 @@
+use core::f32::consts::TAU;
+use std::time::Instant;
+
+use voxell_rng::rng::XorShift128;
+
+use crate::opengl::render::{particle::Particle, renderstate::seeded_rng};
+use crate::vec2::Vector2;
+
+use super::cpu::{step_particles, Stepper};
+use super::diagnostics::{angular_momentum, kinetic_energy, momentum, potential_energy};
+use super::integrator::Integrator;
+use super::{ComputePass, ForceModel, SimParams};
+
+/// Particle count for `drift` when `--particles` isn't given.
+pub const DEFAULT_DRIFT_PARTICLES: usize = 256;
+
+/// Steps per integrator for `drift` when `--steps` isn't given.
+pub const DEFAULT_DRIFT_STEPS: u32 = 1000;
+
+/// Timestep for `drift` when `--fixed-dt` isn't given.
+pub const DEFAULT_DRIFT_DT: f32 = 0.01;
+
+/// Gravitational constant for `drift` when `--gravity` isn't given. [`super::DEFAULT_G`] is far too
+/// weak for anything to move in a few hundred steps.
+pub const DEFAULT_DRIFT_G: f32 = 1e-3;
+
+/// Softening length for `drift` when `--softening` isn't given, large enough that close encounters
+/// don't dominate the error.
+pub const DEFAULT_DRIFT_SOFTENING: f32 = 0.05;
+
+/// How many times over a run the conserved quantities are measured. Each measurement is O(N²).
+const SAMPLES: u32 = 100;
+
+/// Total energy, linear momentum and angular momentum, the quantities a good integrator keeps constant.
+#[derive(Debug, Clone, Copy)]
+struct Conserved {
+    energy: f64,
+    momentum: (f64, f64),
+    angular_momentum: f64,
+}
+
+impl Conserved {
+    fn measure(particles: &[Particle], params: &SimParams, mouse_pos: &Vector2) -> Self {
+        Self {
+            energy: kinetic_energy(particles) + potential_energy(particles, params, mouse_pos),
+            momentum: momentum(particles),
+            angular_momentum: angular_momentum(particles),
+        }
+    }
+}
+
+/// A unit disk of particles on roughly circular orbits around its center, with zero net momentum.
+///
+/// The orbital speed at radius `r` balances the pull of the mass inside it, `G N r²`, as if it were all
+/// at the center.
+pub fn rotating_disk(rng: &mut XorShift128, count: usize, g: f32) -> Vec<Particle> {
+    let mut particles: Vec<Particle> = (0..count)
+        .map(|_| {
+            let r = rng.next_f32().sqrt();
+            let angle = rng.next_f32() * TAU;
+            let (sin, cos) = angle.sin_cos();
+            let speed = (g * count as f32 * r).sqrt();
+
+            Particle {
+                pos: Vector2::new(r * cos, r * sin),
+                vel: Vector2::new(-speed * sin, speed * cos),
+                ..Particle::default()
+            }
+        })
+        .collect();
+
+    let mut mean = Vector2::default();
+    for p in &particles {
+        mean.add_vec(&p.vel);
+    }
+    mean.scale(1.0 / count.max(1) as f32);
+    for p in &mut particles {
+        p.vel.add(-mean.x, -mean.y);
+    }
+
+    particles
+}
+
+/// Runs every integrator in `integrators` for `steps` steps of `dt` from the same [`rotating_disk`] and
+/// prints how far energy, momentum and angular momentum wander from their starting values.
+///
+/// The mouse force model is an outside pull that doesn't conserve momentum, so it's swapped for
+/// [`ForceModel::NBody`].
+pub fn run(particle_count: usize, seed: u64, params: &SimParams, integrators: &[Integrator], dt: f32, steps: u32) {
+    let mut params = params.clone();
+    if params.force_model == ForceModel::Mouse {
+        params.force_model = ForceModel::NBody;
+    }
+
+    let mouse_pos = Vector2::default();
+    let initial = rotating_disk(&mut seeded_rng(seed), particle_count, params.g);
+    let start = Conserved::measure(&initial, &params, &mouse_pos);
+    let momentum_scale: f64 = initial
+        .iter()
+        .map(|p| f64::from(p.vel.mag()))
+        .sum::<f64>()
+        .max(f64::MIN_POSITIVE);
+
+    println!(
+        "Integrating {} particles for {} steps of {}s with {} (G = {}, softening = {}, seed {})",
+        particle_count, steps, dt, params.force_model, params.g, params.softening, seed
+    );
+    println!(
+        "{:>10} {:>8} {:>10} {:>12} {:>12} {:>12} {:>12}",
+        "integrator", "forces", "time", "energy", "max energy", "momentum", "ang. mom."
+    );
+
+    let sample_every = (steps / SAMPLES).max(1);
+
+    for &integrator in integrators {
+        let params = SimParams { integrator, ..params.clone() };
+        let mut particles = initial.clone();
+        let mut stepper = Stepper::default();
+        let mut max_energy_error = 0.0f64;
+
+        let started = Instant::now();
+        for step in 1..=steps {
+            step_particles(&mut particles, &params, &mouse_pos, None, dt, &mut stepper);
+
+            if step % sample_every == 0 {
+                let now = Conserved::measure(&particles, &params, &mouse_pos);
+                max_energy_error = max_energy_error.max(relative(now.energy, start.energy));
+            }
+        }
+        let elapsed = started.elapsed();
+
+        let end = Conserved::measure(&particles, &params, &mouse_pos);
+        let momentum = (end.momentum.0 - start.momentum.0).hypot(end.momentum.1 - start.momentum.1) / momentum_scale;
+        let forces = integrator.passes().iter().filter(|&&pass| pass == ComputePass::Forces).count();
+
+        println!(
+            "{:>10} {:>8} {:>9.2}s {:>12.3e} {:>12.3e} {:>12.3e} {:>12.3e}",
+            integrator.to_string(),
+            forces,
+            elapsed.as_secs_f32(),
+            relative(end.energy, start.energy),
+            max_energy_error,
+            momentum,
+            relative(end.angular_momentum, start.angular_momentum)
+        );
+    }
+}
+
+/// `|now - start|` relative to `|start|`, or absolute if `start` is zero.
+fn relative(now: f64, start: f64) -> f64 {
+    let scale = if start == 0.0 { 1.0 } else { start.abs() };
+    (now - start).abs() / scale
+}
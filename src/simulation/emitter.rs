@@ -0,0 +1,426 @@
+use core::f32::consts::TAU;
+use core::fmt::{self, Display};
+
+use anyhow::{bail, Result};
+use serde::Deserialize;
+
+use crate::opengl::render::particle::Particle;
+use crate::vec2::Vector2;
+
+use super::boundary::{pcg_hash, unit_float};
+
+/// Mixed into the step number so spawns don't draw the same random numbers as absorbing boundaries.
+const SPAWN_SEED: u32 = 0x9E37_79B9;
+
+/// Where an emitter places new particles. Discriminants match the `SHAPE_*` constants in `compute.glsl`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+#[repr(i32)]
+pub enum EmitterShape {
+    /// All at `position`.
+    #[default]
+    Point = 0,
+    /// Uniformly along the segment from `position` to `end`.
+    Line = 1,
+    /// Uniformly over the disk of `radius` around `position`.
+    Disk = 2,
+    /// On the circle of `radius` around `position`.
+    Ring = 3,
+}
+
+impl Display for EmitterShape {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::Point => write!(f, "point"),
+            Self::Line => write!(f, "line"),
+            Self::Disk => write!(f, "disk"),
+            Self::Ring => write!(f, "ring"),
+        }
+    }
+}
+
+/// A source of particles, one `[[emitters]]` table of the config file.
+///
+/// New particles take the slots of dead ones, so an emitter only produces anything once the particle
+/// buffer has free slots, see `initial_particles`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Emitter {
+    pub shape: EmitterShape,
+    /// Center of point, disk and ring emitters, start of line emitters.
+    pub position: [f32; 2],
+    /// Other end of line emitters.
+    pub end: [f32; 2],
+    /// Radius of disk and ring emitters.
+    pub radius: f32,
+    /// Particles per simulated second.
+    pub rate: f32,
+    pub speed: f32,
+    /// Relative spread of the speed, 0.2 draws it uniformly between 80% and 120% of `speed`.
+    pub speed_jitter: f32,
+    /// Launch direction in degrees counterclockwise from +x, or from the outward normal if `radial`.
+    pub direction: f32,
+    /// Width in degrees of the cone of launch directions centered on `direction`.
+    pub spread: f32,
+    /// Measure `direction` from the outward normal of the shape: away from the center for disks and
+    /// rings, to the left of the segment for lines. Point emitters have no normal.
+    pub radial: bool,
+    /// Seconds each particle lives for, 0 for forever.
+    pub lifetime: f32,
+    /// Relative spread of the lifetime, below 1.
+    pub lifetime_jitter: f32,
+}
+
+impl Default for Emitter {
+    fn default() -> Self {
+        Self {
+            shape: EmitterShape::default(),
+            position: [0.0, 0.0],
+            end: [0.0, 0.0],
+            radius: 0.1,
+            rate: 100.0,
+            speed: 0.0,
+            speed_jitter: 0.0,
+            direction: 0.0,
+            spread: 0.0,
+            radial: false,
+            lifetime: 0.0,
+            lifetime_jitter: 0.0,
+        }
+    }
+}
+
+impl Emitter {
+    pub fn validate(&self) -> Result<()> {
+        if !self.rate.is_finite() || self.rate < 0.0 {
+            bail!("emitter rate must not be negative");
+        }
+        if !self.radius.is_finite() || self.radius < 0.0 {
+            bail!("emitter radius must not be negative");
+        }
+        if !(0.0..=1.0).contains(&self.speed_jitter) {
+            bail!("emitter speed jitter must be between 0 and 1");
+        }
+        if !self.lifetime.is_finite() || self.lifetime < 0.0 {
+            bail!("emitter lifetime must not be negative");
+        }
+        if !(0.0..1.0).contains(&self.lifetime_jitter) {
+            bail!("emitter lifetime jitter must be at least 0 and below 1");
+        }
+        Ok(())
+    }
+
+    /// This emitter's part of a step, the spawns up to `spawn_end` that aren't claimed by earlier batches.
+    fn batch(&self, spawn_end: u32) -> SpawnBatch {
+        SpawnBatch {
+            position: Vector2::new(self.position[0], self.position[1]),
+            line_end: Vector2::new(self.end[0], self.end[1]),
+            radius: self.radius,
+            shape: self.shape,
+            speed: self.speed,
+            speed_jitter: self.speed_jitter,
+            direction: self.direction.to_radians(),
+            spread: self.spread.to_radians(),
+            lifetime: self.lifetime,
+            lifetime_jitter: self.lifetime_jitter,
+            radial: u32::from(self.radial),
+            spawn_end,
+        }
+    }
+}
+
+/// One emitter's spawns for a step, the `SpawnBatch` struct of `compute.glsl` (binding 4).
+///
+/// Angles are in radians. The batches of a step are in emitter order and cover consecutive ranges of
+/// spawn indices, each ending at its `spawn_end`.
+#[derive(Debug, Clone, Default)]
+#[repr(C)]
+pub struct SpawnBatch {
+    pub position: Vector2,
+    pub line_end: Vector2,
+    pub radius: f32,
+    pub shape: EmitterShape,
+    pub speed: f32,
+    pub speed_jitter: f32,
+    pub direction: f32,
+    pub spread: f32,
+    pub lifetime: f32,
+    pub lifetime_jitter: f32,
+    pub radial: u32,
+    pub spawn_end: u32,
+}
+
+/// The configured emitters and the fraction of a particle each of them still owes.
+#[derive(Debug, Clone, Default)]
+pub struct Emitters {
+    emitters: Vec<Emitter>,
+    owed: Vec<f32>,
+}
+
+impl Emitters {
+    pub fn new(emitters: Vec<Emitter>) -> Self {
+        let owed = vec![0.0; emitters.len()];
+        Self { emitters, owed }
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.emitters.is_empty()
+    }
+
+    pub fn emitters(&self) -> &[Emitter] {
+        &self.emitters
+    }
+
+    /// Whether any emitter spawns particles that die.
+    pub fn expire(&self) -> bool {
+        self.emitters.iter().any(|e| e.lifetime > 0.0)
+    }
+
+    /// Particles per simulated second, summed over every emitter.
+    pub fn total_rate(&self) -> f32 {
+        self.emitters.iter().map(|e| e.rate).sum()
+    }
+
+    /// The spawns due after another `dt` seconds. Rates that don't divide evenly carry the remainder
+    /// over to the next step.
+    pub fn plan(&mut self, dt: f32) -> Vec<SpawnBatch> {
+        let mut spawn_end = 0u32;
+        self.emitters
+            .iter()
+            .zip(&mut self.owed)
+            .map(|(emitter, owed)| {
+                *owed += emitter.rate * dt;
+                let count = owed.floor();
+                *owed -= count;
+                spawn_end = spawn_end.saturating_add(count as u32);
+                emitter.batch(spawn_end)
+            })
+            .collect()
+    }
+}
+
+/// Total spawns in a step's batches.
+pub fn spawn_total(batches: &[SpawnBatch]) -> u32 {
+    batches.last().map_or(0, |batch| batch.spawn_end)
+}
+
+/// Puts `count` spawns from `emitter` ahead of a step's `batches`, so they get the free slots first.
+pub fn prepend_batch(batches: Vec<SpawnBatch>, emitter: &Emitter, count: u32) -> Vec<SpawnBatch> {
+    let mut prepended = Vec::with_capacity(batches.len() + 1);
+    prepended.push(emitter.batch(count));
+    prepended.extend(batches.into_iter().map(|batch| SpawnBatch {
+        spawn_end: batch.spawn_end.saturating_add(count),
+        ..batch
+    }));
+    prepended
+}
+
+/// Indices of the dead particles, in increasing order. The spawns of a step take them from the end.
+pub fn free_slots(particles: &[Particle]) -> Vec<u32> {
+    particles
+        .iter()
+        .enumerate()
+        .filter(|&(_, p)| !p.is_alive())
+        .map(|(idx, _)| idx as u32)
+        .collect()
+}
+
+/// The lifetime pass of `compute.glsl`: ages every live particle by `dt` and frees the slots of those
+/// that outlived their lifetime.
+///
+/// The GPU pushes onto its free list from many invocations at once, so the slots end up in a different
+/// order there.
+pub fn advance_lifetimes(particles: &mut [Particle], dt: f32, free_slots: &mut Vec<u32>) {
+    for (idx, p) in particles.iter_mut().enumerate() {
+        if !p.is_alive() {
+            continue;
+        }
+        p.age += dt;
+        if p.lifetime > 0.0 && p.age >= p.lifetime {
+            p.alive = 0;
+            free_slots.push(idx as u32);
+        }
+    }
+}
+
+/// The spawn passes of `compute.glsl`: spawn `i` of the step takes the `i`-th free slot from the end.
+/// Spawns beyond the free slots are dropped.
+///
+/// Returns the slots that were filled.
+pub fn emit(particles: &mut [Particle], free_slots: &mut Vec<u32>, batches: &[SpawnBatch], step: u32) -> Vec<u32> {
+    let mut filled = Vec::new();
+    for i in 0..spawn_total(batches) {
+        let Some(slot) = free_slots.pop() else {
+            break;
+        };
+        particles[slot as usize] = spawn_particle(batches, i, step);
+        filled.push(slot);
+    }
+    filled
+}
+
+/// Spawn `i` of step `step`, the same as `spawnParticle` in `compute.glsl`.
+pub fn spawn_particle(batches: &[SpawnBatch], i: u32, step: u32) -> Particle {
+    let b = batches.iter().position(|batch| i < batch.spawn_end).unwrap_or(batches.len() - 1);
+    let batch = &batches[b];
+
+    let h1 = pcg_hash(i ^ pcg_hash(step ^ SPAWN_SEED));
+    let h2 = pcg_hash(h1);
+    let h3 = pcg_hash(h2);
+    let h4 = pcg_hash(h3);
+    let h5 = pcg_hash(h4);
+
+    let mut pos = batch.position.clone();
+    let mut normal = 0.0;
+    match batch.shape {
+        EmitterShape::Line => {
+            let (dx, dy) = (batch.line_end.x - batch.position.x, batch.line_end.y - batch.position.y);
+            let t = unit_float(h1);
+            pos.set(dx.mul_add(t, pos.x), dy.mul_add(t, pos.y));
+            normal = dx.atan2(-dy);
+        }
+        EmitterShape::Disk | EmitterShape::Ring => {
+            let r = if batch.shape == EmitterShape::Disk { batch.radius * unit_float(h1).sqrt() } else { batch.radius };
+            normal = TAU * unit_float(h2);
+            pos.set(r.mul_add(normal.cos(), pos.x), r.mul_add(normal.sin(), pos.y));
+        }
+        EmitterShape::Point => {}
+    }
+
+    let base = if batch.radial != 0 { batch.direction + normal } else { batch.direction };
+    let angle = batch.spread.mul_add(unit_float(h3) - 0.5, base);
+    let speed = batch.speed * batch.speed_jitter.mul_add(unit_float(h4).mul_add(2.0, -1.0), 1.0);
+    let lifetime = batch.lifetime * batch.lifetime_jitter.mul_add(unit_float(h5).mul_add(2.0, -1.0), 1.0);
+
+    Particle {
+        pos,
+        vel: Vector2::new(speed * angle.cos(), speed * angle.sin()),
+        lifetime,
+        ..Particle::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn emitter(rate: f32) -> Emitter {
+        Emitter {
+            rate,
+            ..Emitter::default()
+        }
+    }
+
+    fn dead(count: usize) -> Vec<Particle> {
+        vec![
+            Particle {
+                alive: 0,
+                ..Particle::default()
+            };
+            count
+        ]
+    }
+
+    #[test]
+    fn plan_carries_fractional_spawns() {
+        let mut emitters = Emitters::new(vec![emitter(25.0)]);
+        let totals: Vec<u32> = (0..4).map(|_| spawn_total(&emitters.plan(0.1))).collect();
+        assert_eq!(totals, [2, 3, 2, 3], "2.5 spawns per step alternate between 2 and 3");
+    }
+
+    #[test]
+    fn plan_gives_each_emitter_a_range() {
+        let mut emitters = Emitters::new(vec![emitter(30.0), emitter(0.0), emitter(10.0)]);
+        let ends: Vec<u32> = emitters.plan(0.1).iter().map(|batch| batch.spawn_end).collect();
+        assert_eq!(ends, [3, 3, 4], "batches end where the next one starts");
+        assert_eq!(emitters.total_rate(), 40.0, "rates add up");
+    }
+
+    #[test]
+    fn emit_fills_free_slots_from_the_end() {
+        let mut particles = dead(4);
+        particles[1].alive = 1;
+        let mut slots = free_slots(&particles);
+        assert_eq!(slots, [0, 2, 3], "dead particles in increasing order");
+
+        let batches = Emitters::new(vec![emitter(20.0)]).plan(0.1);
+        let filled = emit(&mut particles, &mut slots, &batches, 0);
+        assert_eq!(filled, [3, 2], "spawns take slots from the end");
+        assert_eq!(slots, [0], "one slot left");
+        assert!(particles.iter().skip(1).all(Particle::is_alive), "spawned particles are alive");
+    }
+
+    #[test]
+    fn emit_drops_spawns_beyond_the_free_slots() {
+        let mut particles = dead(2);
+        let mut slots = free_slots(&particles);
+        let batches = Emitters::new(vec![emitter(50.0)]).plan(0.1);
+        assert_eq!(emit(&mut particles, &mut slots, &batches, 0).len(), 2, "only two slots to fill");
+        assert!(slots.is_empty(), "every slot taken");
+    }
+
+    #[test]
+    fn prepended_spawns_take_the_first_slots() {
+        let tool = Emitter {
+            shape: EmitterShape::Disk,
+            position: [5.0, 5.0],
+            radius: 0.5,
+            ..Emitter::default()
+        };
+        let batches = prepend_batch(Emitters::new(vec![emitter(20.0)]).plan(0.1), &tool, 3);
+        let ends: Vec<u32> = batches.iter().map(|batch| batch.spawn_end).collect();
+        assert_eq!(ends, [3, 5], "the emitters' range moves behind the prepended one");
+
+        let mut particles = dead(3);
+        let mut slots = free_slots(&particles);
+        emit(&mut particles, &mut slots, &batches, 0);
+        for p in &particles {
+            let (dx, dy) = (p.pos.x - 5.0, p.pos.y - 5.0);
+            assert!(dx.hypot(dy) <= 0.5, "the prepended spawns should fill every slot, got one at {:?}", p.pos);
+            assert_eq!((p.vel.x, p.vel.y, p.lifetime), (0.0, 0.0, 0.0), "tool spawns are at rest and live forever");
+        }
+    }
+
+    #[test]
+    fn expired_particles_free_their_slots() {
+        let mut particles = dead(3);
+        let mut slots = free_slots(&particles);
+        let batches = Emitters::new(vec![Emitter {
+            lifetime: 0.25,
+            ..emitter(30.0)
+        }])
+        .plan(0.1);
+        emit(&mut particles, &mut slots, &batches, 0);
+        assert!(slots.is_empty(), "every slot taken");
+
+        advance_lifetimes(&mut particles, 0.2, &mut slots);
+        assert!(slots.is_empty(), "nothing expired yet");
+        advance_lifetimes(&mut particles, 0.1, &mut slots);
+        assert_eq!(slots.len(), 3, "every particle outlived its lifetime");
+
+        let refilled = emit(&mut particles, &mut slots, &batches, 1);
+        assert_eq!(refilled.len(), 3, "freed slots are reused");
+        assert!(particles.iter().all(|p| p.is_alive() && p.age == 0.0), "reused slots start over");
+    }
+
+    #[test]
+    fn spawns_stay_on_their_shape() {
+        let ring = Emitter {
+            shape: EmitterShape::Ring,
+            position: [1.0, -1.0],
+            radius: 0.5,
+            speed: 2.0,
+            radial: true,
+            ..emitter(1000.0)
+        };
+        let batches = Emitters::new(vec![ring]).plan(0.1);
+        for i in 0..spawn_total(&batches) {
+            let p = spawn_particle(&batches, i, 7);
+            let (dx, dy) = (p.pos.x - 1.0, p.pos.y + 1.0);
+            assert!((dx.hypot(dy) - 0.5).abs() < 1e-5, "spawn {} is off the ring", i);
+            // Launched straight outwards.
+            assert!(p.vel.x.mul_add(dy, -(p.vel.y * dx)).abs() < 1e-4, "spawn {} isn't launched radially", i);
+            assert!((p.vel.mag() - 2.0).abs() < 1e-5, "spawn {} has the wrong speed", i);
+        }
+    }
+}
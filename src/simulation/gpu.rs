@@ -0,0 +1,191 @@
+use core::{mem, ptr};
+
+use anyhow::Result;
+
+use crate::opengl::{program::Program, render::renderstate::RenderState, uniform::UniformLocations};
+use crate::vec2::Vector2;
+
+use super::barnes_hut::{QuadNode, QuadTree};
+use super::emitter::{spawn_total, SpawnBatch};
+use super::integrator::{Integrator, Rk4State};
+use super::{BackendKind, ComputePass, ForceModel, SimulationBackend};
+
+/// Runs `compute.glsl` over the particle SSBO.
+///
+/// With [`ForceModel::BarnesHut`] the positions are read back before every forces pass and the quadtree
+/// is built on the CPU, then uploaded to the `TreeBuffer` SSBO (binding 1) for the forces pass to walk.
+/// [`Integrator::Rk4`] keeps its stage sums in the `RkBuffer` SSBO (binding 2), and the emitters' spawns
+/// for each step are uploaded to the `SpawnBuffer` SSBO (binding 4). Particles that wrap, respawn or spawn
+/// also write their new position to `prev_vbo`, bound by `RenderState` as the `PrevBuffer` SSBO (binding 5).
+pub struct GpuBackend {
+    tree_buffer: u32,
+    rk_buffer: u32,
+    spawn_buffer: u32,
+    /// Particles `rk_buffer` has room for.
+    rk_capacity: usize,
+}
+
+impl GpuBackend {
+    pub fn new() -> Self {
+        let mut tree_buffer = 0;
+        let mut rk_buffer = 0;
+        let mut spawn_buffer = 0;
+        unsafe {
+            gl::GenBuffers(1, &raw mut tree_buffer);
+            gl::GenBuffers(1, &raw mut rk_buffer);
+            gl::GenBuffers(1, &raw mut spawn_buffer);
+        }
+
+        Self {
+            tree_buffer,
+            rk_buffer,
+            spawn_buffer,
+            rk_capacity: 0,
+        }
+    }
+
+    /// Grows `rk_buffer` to hold `count` particles and binds it. Its contents are written by the first
+    /// RK4 stage, so they don't need to survive the resize.
+    fn bind_rk_buffer(&mut self, count: usize) {
+        unsafe {
+            gl::BindBuffer(gl::SHADER_STORAGE_BUFFER, self.rk_buffer);
+            if count > self.rk_capacity {
+                gl::BufferData(
+                    gl::SHADER_STORAGE_BUFFER,
+                    (count * mem::size_of::<Rk4State>()) as isize,
+                    ptr::null(),
+                    gl::DYNAMIC_COPY,
+                );
+                self.rk_capacity = count;
+            }
+            gl::BindBufferBase(gl::SHADER_STORAGE_BUFFER, 2, self.rk_buffer);
+        }
+    }
+
+    fn upload_tree(&self, render_state: &mut RenderState) {
+        render_state.read_back_buffer_data();
+        let positions: Vec<Vector2> = render_state
+            .buffer
+            .data()
+            .iter()
+            .filter(|p| p.is_alive())
+            .map(|p| p.pos.clone())
+            .collect();
+        let tree = QuadTree::build(&positions);
+        let nodes = tree.nodes();
+
+        unsafe {
+            gl::BindBuffer(gl::SHADER_STORAGE_BUFFER, self.tree_buffer);
+            gl::BufferData(
+                gl::SHADER_STORAGE_BUFFER,
+                mem::size_of_val(nodes) as isize,
+                nodes.as_ptr().cast(),
+                gl::STREAM_DRAW,
+            );
+            gl::BindBufferBase(gl::SHADER_STORAGE_BUFFER, 1, self.tree_buffer);
+        }
+    }
+
+    /// Runs one pass over every particle, rebuilding the quadtree first if it's a Barnes-Hut forces pass.
+    fn dispatch(&self, pass: ComputePass, render_state: &mut RenderState, compute_program: &Program, compute_uniforms: &UniformLocations) -> Result<()> {
+        match pass {
+            ComputePass::Forces if render_state.params.force_model == ForceModel::BarnesHut => self.upload_tree(render_state),
+            ComputePass::Boundary if !render_state.params.boundary.kind.needs_pass() => return Ok(()),
+            _ => {}
+        }
+
+        compute_program.use_program();
+        compute_uniforms.set("uPass", pass as i32)?;
+        render_state.dispatch_compute_call();
+
+        Ok(())
+    }
+
+    /// Uploads `batches` and runs the spawn pass with one invocation per spawn, then the commit pass that
+    /// pops the slots it used off the free list.
+    fn spawn(&self, batches: &[SpawnBatch], render_state: &RenderState, compute_program: &Program, compute_uniforms: &UniformLocations) -> Result<()> {
+        let total = spawn_total(batches);
+        if total == 0 {
+            return Ok(());
+        }
+
+        unsafe {
+            gl::BindBuffer(gl::SHADER_STORAGE_BUFFER, self.spawn_buffer);
+            gl::BufferData(
+                gl::SHADER_STORAGE_BUFFER,
+                mem::size_of_val(batches) as isize,
+                batches.as_ptr().cast(),
+                gl::STREAM_DRAW,
+            );
+            gl::BindBufferBase(gl::SHADER_STORAGE_BUFFER, 4, self.spawn_buffer);
+        }
+
+        compute_program.use_program();
+        compute_uniforms.set("uSpawnCount", total)?;
+        compute_uniforms.set("uPass", ComputePass::Spawn as i32)?;
+        render_state.dispatch_compute(total as usize);
+        compute_uniforms.set("uPass", ComputePass::SpawnCommit as i32)?;
+        render_state.dispatch_compute(1);
+
+        Ok(())
+    }
+}
+
+impl Default for GpuBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SimulationBackend for GpuBackend {
+    fn kind(&self) -> BackendKind {
+        BackendKind::Gpu
+    }
+
+    fn step(&mut self, render_state: &mut RenderState, compute_program: &Program, compute_uniforms: &UniformLocations, dt: f32) -> Result<()> {
+        let integrator = render_state.params.integrator;
+        compute_uniforms.set("uStep", render_state.step_count)?;
+
+        let batches = render_state.plan_spawns(dt);
+        let spawns = spawn_total(&batches) > 0;
+        if !render_state.emitters.is_empty() || spawns {
+            self.dispatch(ComputePass::Lifetime, render_state, compute_program, compute_uniforms)?;
+            self.spawn(&batches, render_state, compute_program, compute_uniforms)?;
+
+            // New particles start without `acc`, and dead ones no longer pull on the rest. How many of either
+            // there were would take a readback, so assume the worst.
+            if spawns || render_state.emitters.expire() {
+                render_state.forces_valid = false;
+            }
+        }
+
+        if integrator == Integrator::Rk4 {
+            self.bind_rk_buffer(render_state.count());
+        }
+
+        if integrator.reuses_forces() && !render_state.forces_valid {
+            self.dispatch(ComputePass::Forces, render_state, compute_program, compute_uniforms)?;
+        }
+        for &pass in integrator.passes() {
+            self.dispatch(pass, render_state, compute_program, compute_uniforms)?;
+        }
+        render_state.forces_valid = integrator.reuses_forces();
+        render_state.step_count = render_state.step_count.wrapping_add(1);
+
+        Ok(())
+    }
+}
+
+impl Drop for GpuBackend {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteBuffers(1, &raw const self.tree_buffer);
+            gl::DeleteBuffers(1, &raw const self.rk_buffer);
+            gl::DeleteBuffers(1, &raw const self.spawn_buffer);
+        }
+    }
+}
+
+const _: () = assert!(mem::size_of::<QuadNode>() == 24, "QuadNode must match the std430 layout in compute.glsl");
+const _: () = assert!(mem::size_of::<Rk4State>() == 32, "Rk4State must match the std430 layout in compute.glsl");
+const _: () = assert!(mem::size_of::<SpawnBatch>() == 56, "SpawnBatch must match the std430 layout in compute.glsl");
@@ -0,0 +1,634 @@
+use core::f32::consts::TAU;
+use core::fmt::{self, Display};
+use core::str::FromStr;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context as AnyhowContextTrait, Error, Result};
+use serde::Deserialize;
+use toml::{Table, Value};
+use voxell_rng::rng::XorShift128;
+
+use crate::opengl::render::particle::Particle;
+use crate::vec2::Vector2;
+
+use super::SimParams;
+
+/// How the particles are laid out when they're created or reset.
+///
+/// Selected with `--init` or the `[initial]` table of the config file. Each generator has its own
+/// parameters, all of them optional except the image path.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum InitialCondition {
+    /// Every component of `pos`, `vel` and `acc` uniform in `[0, 1)`, so everything starts in one quadrant.
+    #[default]
+    Noise,
+    Uniform(UniformParams),
+    Gaussian(GaussianParams),
+    Galaxy(GalaxyParams),
+    Collision(CollisionParams),
+    Ring(RingParams),
+    Grid(GridParams),
+    Image(ImageParams),
+}
+
+/// Positions uniform in a box, velocities uniform in a disk.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UniformParams {
+    pub min: [f32; 2],
+    pub max: [f32; 2],
+    /// Largest initial speed.
+    pub speed: f32,
+}
+
+impl Default for UniformParams {
+    fn default() -> Self {
+        Self {
+            min: [-1.0, -1.0],
+            max: [1.0, 1.0],
+            speed: 0.0,
+        }
+    }
+}
+
+/// A normally distributed blob.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GaussianParams {
+    pub center: [f32; 2],
+    /// Standard deviation of the positions along each axis.
+    pub sigma: f32,
+    /// Standard deviation of the velocities along each axis.
+    pub dispersion: f32,
+}
+
+impl Default for GaussianParams {
+    fn default() -> Self {
+        Self {
+            center: [0.0, 0.0],
+            sigma: 0.25,
+            dispersion: 0.0,
+        }
+    }
+}
+
+/// An exponential disk on circular orbits.
+///
+/// Each particle orbits at the speed that balances the softened pull of every particle closer to the
+/// center, as if they were all at the center. The speeds come from the gravity parameters, so they're
+/// only circular with [`ForceModel::NBody`](super::ForceModel::NBody) or
+/// [`ForceModel::BarnesHut`](super::ForceModel::BarnesHut).
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GalaxyParams {
+    pub center: [f32; 2],
+    /// Distance over which the surface density drops by a factor of e.
+    pub scale_length: f32,
+    /// Nothing is placed beyond this radius.
+    pub radius: f32,
+    /// Multiplies the orbital speeds, below 1 the disk collapses and above 1 it flies apart.
+    pub velocity_scale: f32,
+    pub clockwise: bool,
+}
+
+impl Default for GalaxyParams {
+    fn default() -> Self {
+        Self {
+            center: [0.0, 0.0],
+            scale_length: 0.25,
+            radius: 1.0,
+            velocity_scale: 1.0,
+            clockwise: false,
+        }
+    }
+}
+
+/// Two Gaussian clusters side by side on the x axis, heading towards each other.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CollisionParams {
+    /// Distance between the cluster centers along x.
+    pub separation: f32,
+    /// Offset between the cluster centers along y, 0 for a head-on collision.
+    pub impact: f32,
+    /// Standard deviation of the positions in each cluster.
+    pub sigma: f32,
+    /// Speed of each cluster towards the other.
+    pub speed: f32,
+    /// Fraction of the particles in the left cluster.
+    pub ratio: f32,
+}
+
+impl Default for CollisionParams {
+    fn default() -> Self {
+        Self {
+            separation: 1.0,
+            impact: 0.2,
+            sigma: 0.15,
+            speed: 0.1,
+            ratio: 0.5,
+        }
+    }
+}
+
+/// A ring, optionally spinning.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RingParams {
+    pub center: [f32; 2],
+    pub radius: f32,
+    /// Standard deviation of the distance from the center.
+    pub width: f32,
+    /// Tangential speed, counterclockwise when positive.
+    pub speed: f32,
+}
+
+impl Default for RingParams {
+    fn default() -> Self {
+        Self {
+            center: [0.0, 0.0],
+            radius: 0.5,
+            width: 0.02,
+            speed: 0.0,
+        }
+    }
+}
+
+/// A rectangular lattice filling a box row by row, at rest.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GridParams {
+    pub min: [f32; 2],
+    pub max: [f32; 2],
+    /// Random offset of each particle from its lattice point, as a fraction of the spacing.
+    pub jitter: f32,
+}
+
+impl Default for GridParams {
+    fn default() -> Self {
+        Self {
+            min: [-1.0, -1.0],
+            max: [1.0, 1.0],
+            jitter: 0.0,
+        }
+    }
+}
+
+/// Positions sampled from the brightness of a PNG image stretched over a box, at rest. Transparent pixels
+/// count as black.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ImageParams {
+    /// Relative to the scene file's directory when given there.
+    pub path: PathBuf,
+    #[serde(default = "default_image_min")]
+    pub min: [f32; 2],
+    #[serde(default = "default_image_max")]
+    pub max: [f32; 2],
+    /// Sample from the darkness instead.
+    #[serde(default)]
+    pub invert: bool,
+}
+
+const fn default_image_min() -> [f32; 2] {
+    [-1.0, -1.0]
+}
+
+const fn default_image_max() -> [f32; 2] {
+    [1.0, 1.0]
+}
+
+impl InitialCondition {
+    pub fn validate(&self) -> Result<()> {
+        match *self {
+            Self::Noise => {}
+            Self::Uniform(ref p) => validate_box(p.min, p.max)?,
+            Self::Gaussian(ref p) => {
+                if !p.sigma.is_finite() || p.sigma <= 0.0 {
+                    bail!("sigma must be positive");
+                }
+            }
+            Self::Galaxy(ref p) => {
+                if !(p.scale_length.is_finite() && p.radius.is_finite() && p.scale_length > 0.0 && p.radius > 0.0) {
+                    bail!("scale length and radius must be positive");
+                }
+            }
+            Self::Collision(ref p) => {
+                if !p.sigma.is_finite() || p.sigma <= 0.0 {
+                    bail!("sigma must be positive");
+                }
+                if !(0.0..=1.0).contains(&p.ratio) {
+                    bail!("ratio must be between 0 and 1");
+                }
+            }
+            Self::Ring(ref p) => {
+                if !(p.radius >= 0.0 && p.width >= 0.0) {
+                    bail!("radius and width must not be negative");
+                }
+            }
+            Self::Grid(ref p) => validate_box(p.min, p.max)?,
+            Self::Image(ref p) => validate_box(p.min, p.max)?,
+        }
+        Ok(())
+    }
+}
+
+fn validate_box(min: [f32; 2], max: [f32; 2]) -> Result<()> {
+    if !(min[0] < max[0] && min[1] < max[1]) {
+        bail!("min must be below and left of max");
+    }
+    Ok(())
+}
+
+/// Parses `name` or `name:key=value,key=value,...`, with the same names and keys as the `[initial]` table.
+///
+/// Values are TOML, so vectors are written `center=[0.5,0.5]`. Anything that doesn't parse as TOML is
+/// taken as a string, so paths don't need quotes.
+impl FromStr for InitialCondition {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, params) = s.split_once(':').unwrap_or((s, ""));
+
+        let mut table = Table::new();
+        table.insert("kind".to_owned(), Value::String(name.trim().to_owned()));
+        for param in split_params(params) {
+            let (key, value) = param
+                .split_once('=')
+                .with_context(|| format!("initial condition parameter `{}` must be key=value", param))?;
+            table.insert(key.trim().to_owned(), parse_value(value.trim()));
+        }
+
+        Value::Table(table)
+            .try_into()
+            .with_context(|| format!("invalid initial condition `{}`", s))
+    }
+}
+
+impl Display for InitialCondition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::Noise => write!(f, "noise"),
+            Self::Uniform(_) => write!(f, "uniform"),
+            Self::Gaussian(_) => write!(f, "gaussian"),
+            Self::Galaxy(_) => write!(f, "galaxy"),
+            Self::Collision(_) => write!(f, "collision"),
+            Self::Ring(_) => write!(f, "ring"),
+            Self::Grid(_) => write!(f, "grid"),
+            Self::Image(ref p) => write!(f, "image ({})", p.path.display()),
+        }
+    }
+}
+
+/// Splits on the commas that aren't inside brackets.
+fn split_params(params: &str) -> Vec<&str> {
+    let mut depth = 0u32;
+    params
+        .split(|c| {
+            match c {
+                '[' => depth += 1,
+                ']' => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+            c == ',' && depth == 0
+        })
+        .filter(|part| !part.trim().is_empty())
+        .collect()
+}
+
+fn parse_value(value: &str) -> Value {
+    toml::from_str::<Table>(&format!("v = {}", value))
+        .ok()
+        .and_then(|mut table| table.remove("v"))
+        .unwrap_or_else(|| Value::String(value.to_owned()))
+}
+
+/// An [`InitialCondition`] that passed validation, with its image loaded if it samples one.
+#[derive(Debug, Clone, Default)]
+pub struct Generator {
+    condition: InitialCondition,
+    image: Option<BrightnessMap>,
+}
+
+impl Generator {
+    pub fn new(condition: InitialCondition) -> Result<Self> {
+        condition
+            .validate()
+            .with_context(|| format!("invalid parameters for initial condition `{}`", condition))?;
+
+        let image = match condition {
+            InitialCondition::Image(ref p) => Some(BrightnessMap::load(&p.path, p.invert)?),
+            _ => None,
+        };
+
+        Ok(Self { condition, image })
+    }
+
+    pub const fn condition(&self) -> &InitialCondition {
+        &self.condition
+    }
+
+    /// Lays out `count` particles. `params` sets the orbital speeds of [`InitialCondition::Galaxy`].
+    pub fn generate(&self, rng: &mut XorShift128, count: usize, params: &SimParams) -> Vec<Particle> {
+        match self.condition {
+            InitialCondition::Noise => (0..count).map(|_| Particle::random(rng)).collect(),
+            InitialCondition::Uniform(ref p) => (0..count)
+                .map(|_| {
+                    let pos = in_box(p.min, p.max, rng.next_f32(), rng.next_f32());
+                    let speed = p.speed * rng.next_f32().sqrt();
+                    let angle = TAU * rng.next_f32();
+                    at(pos, Vector2::new(speed * angle.cos(), speed * angle.sin()))
+                })
+                .collect(),
+            InitialCondition::Gaussian(ref p) => (0..count).map(|_| blob(rng, p.center, p.sigma, p.dispersion)).collect(),
+            InitialCondition::Galaxy(ref p) => galaxy(rng, count, p, params),
+            InitialCondition::Collision(ref p) => {
+                let left = (count as f32 * p.ratio).round() as usize;
+                let (dx, dy) = (0.5 * p.separation, 0.5 * p.impact);
+                (0..count)
+                    .map(|i| {
+                        let (center, vx) = if i < left { ([-dx, -dy], p.speed) } else { ([dx, dy], -p.speed) };
+                        let mut particle = blob(rng, center, p.sigma, 0.0);
+                        particle.vel.x = vx;
+                        particle
+                    })
+                    .collect()
+            }
+            InitialCondition::Ring(ref p) => (0..count)
+                .map(|_| {
+                    let r = normal(rng).0.mul_add(p.width, p.radius);
+                    let (sin, cos) = (TAU * rng.next_f32()).sin_cos();
+                    at(
+                        Vector2::new(r.mul_add(cos, p.center[0]), r.mul_add(sin, p.center[1])),
+                        Vector2::new(-p.speed * sin, p.speed * cos),
+                    )
+                })
+                .collect(),
+            InitialCondition::Grid(ref p) => {
+                let (w, h) = (p.max[0] - p.min[0], p.max[1] - p.min[1]);
+                let cols = ((count as f32 * w / h).sqrt().ceil() as usize).max(1);
+                let rows = count.div_ceil(cols).max(1);
+                (0..count)
+                    .map(|i| {
+                        let jitter_x = p.jitter.mul_add(rng.next_f32() - 0.5, 0.5);
+                        let jitter_y = p.jitter.mul_add(rng.next_f32() - 0.5, 0.5);
+                        let u = ((i % cols) as f32 + jitter_x) / cols as f32;
+                        let v = ((i / cols) as f32 + jitter_y) / rows as f32;
+                        at(in_box(p.min, p.max, u, v), Vector2::default())
+                    })
+                    .collect()
+            }
+            InitialCondition::Image(ref p) => {
+                let Some(ref image) = self.image else {
+                    return Vec::new();
+                };
+                (0..count)
+                    .map(|_| {
+                        let (u, v) = image.sample(rng);
+                        at(in_box(p.min, p.max, u, v), Vector2::default())
+                    })
+                    .collect()
+            }
+        }
+    }
+}
+
+/// A live particle at `pos` moving at `vel`.
+fn at(pos: Vector2, vel: Vector2) -> Particle {
+    Particle {
+        pos,
+        vel,
+        ..Particle::default()
+    }
+}
+
+/// The point at fractions `u` and `v` of the way across the box.
+fn in_box(min: [f32; 2], max: [f32; 2], u: f32, v: f32) -> Vector2 {
+    Vector2::new((max[0] - min[0]).mul_add(u, min[0]), (max[1] - min[1]).mul_add(v, min[1]))
+}
+
+/// Two independent standard normal samples, with the Box-Muller transform.
+fn normal(rng: &mut XorShift128) -> (f32, f32) {
+    // `1 - x` is in (0, 1], so the logarithm stays finite.
+    let r = (-2.0 * (1.0 - rng.next_f32()).ln()).sqrt();
+    let (sin, cos) = (TAU * rng.next_f32()).sin_cos();
+    (r * cos, r * sin)
+}
+
+fn blob(rng: &mut XorShift128, center: [f32; 2], sigma: f32, dispersion: f32) -> Particle {
+    let (x, y) = normal(rng);
+    let (vx, vy) = normal(rng);
+    at(
+        Vector2::new(x.mul_add(sigma, center[0]), y.mul_add(sigma, center[1])),
+        Vector2::new(vx * dispersion, vy * dispersion),
+    )
+}
+
+/// Halvings of the search interval in [`disk_radius`], enough to pin the radius down to well below `f32` precision.
+const BISECTION_STEPS: u32 = 48;
+
+/// The radius at fraction `u` of the exponential disk's radial density `r e^(-r / h)`, cut off at `radius`.
+///
+/// That density's CDF is `1 - (1 + x) e^(-x)` with `x = r / h`, which has no closed-form inverse, so it's
+/// inverted by bisection. Unlike rejecting samples past the cutoff, this takes the same time however
+/// small `radius` is next to `h`.
+fn disk_radius(u: f32, scale_length: f32, radius: f32) -> f32 {
+    let cdf = |x: f64| x.mul_add(-(-x).exp(), -(-x).exp_m1());
+    let (mut lo, mut hi) = (0.0, f64::from(radius) / f64::from(scale_length));
+    let target = f64::from(u) * cdf(hi);
+
+    for _ in 0..BISECTION_STEPS {
+        let mid = 0.5 * (lo + hi);
+        if cdf(mid) < target {
+            lo = mid;
+        } else {
+            hi = mid;
+        }
+    }
+
+    ((0.5 * (lo + hi)) as f32 * scale_length).min(radius)
+}
+
+fn galaxy(rng: &mut XorShift128, count: usize, p: &GalaxyParams, params: &SimParams) -> Vec<Particle> {
+    let mut radii: Vec<f32> = (0..count).map(|_| disk_radius(rng.next_f32(), p.scale_length, p.radius)).collect();
+    radii.sort_by(f32::total_cmp);
+
+    let soft2 = params.softening * params.softening;
+    let spin = if p.clockwise { -1.0 } else { 1.0 };
+
+    radii
+        .iter()
+        .enumerate()
+        .map(|(inside, &r)| {
+            let (sin, cos) = (TAU * rng.next_f32()).sin_cos();
+            // Softened point mass of the `inside` particles closer in: a = G M r / (r² + ε²)^(3/2), v² = a r.
+            let r2 = r * r;
+            let speed = p.velocity_scale * (params.g * inside as f32 * r2 / (r2 + soft2).powf(1.5)).sqrt();
+            at(
+                Vector2::new(r.mul_add(cos, p.center[0]), r.mul_add(sin, p.center[1])),
+                Vector2::new(-spin * speed * sin, spin * speed * cos),
+            )
+        })
+        .collect()
+}
+
+/// Cumulative brightness of an image's pixels in row-major order, top row first.
+#[derive(Debug, Clone)]
+struct BrightnessMap {
+    width: usize,
+    height: usize,
+    cumulative: Vec<f64>,
+}
+
+impl BrightnessMap {
+    fn load(path: &Path, invert: bool) -> Result<Self> {
+        let file = File::open(path).with_context(|| format!("Failed to open image {}", path.display()))?;
+        let mut decoder = png::Decoder::new(file);
+        decoder.set_transformations(png::Transformations::normalize_to_color8());
+        let mut reader = decoder
+            .read_info()
+            .with_context(|| format!("Failed to decode image {}", path.display()))?;
+        let mut pixels = vec![0; reader.output_buffer_size()];
+        let info = reader
+            .next_frame(&mut pixels)
+            .with_context(|| format!("Failed to decode image {}", path.display()))?;
+
+        let channels = info.color_type.samples();
+        let (width, height) = (info.width as usize, info.height as usize);
+
+        let mut total = 0.0;
+        let mut cumulative = Vec::with_capacity(width * height);
+        for row in pixels.chunks_exact(info.line_size).take(height) {
+            for pixel in row.chunks_exact(channels).take(width) {
+                let (luma, alpha) = match *pixel {
+                    [l] => (f64::from(l), 255.0),
+                    [l, a] => (f64::from(l), f64::from(a)),
+                    [r, g, b] => (luma(r, g, b), 255.0),
+                    [r, g, b, a] => (luma(r, g, b), f64::from(a)),
+                    _ => (0.0, 0.0),
+                };
+                let brightness = if invert { 255.0 - luma } else { luma };
+                total += brightness * alpha;
+                cumulative.push(total);
+            }
+        }
+
+        if total <= 0.0 {
+            bail!("image {} has no pixels to sample particles from", path.display());
+        }
+
+        Ok(Self { width, height, cumulative })
+    }
+
+    /// A point in the unit square, y up, with density proportional to the brightness.
+    fn sample(&self, rng: &mut XorShift128) -> (f32, f32) {
+        let total = self.cumulative.last().copied().unwrap_or_default();
+        let target = f64::from(rng.next_f32()) * total;
+        let pixel = self
+            .cumulative
+            .partition_point(|&c| c <= target)
+            .min(self.cumulative.len() - 1);
+
+        let (col, row) = (pixel % self.width, pixel / self.width);
+        let u = (col as f32 + rng.next_f32()) / self.width as f32;
+        let v = 1.0 - (row as f32 + rng.next_f32()) / self.height as f32;
+        (u, v)
+    }
+}
+
+/// Rec. 709 luma of an 8-bit color.
+fn luma(r: u8, g: u8, b: u8) -> f64 {
+    0.0722f64.mul_add(f64::from(b), 0.2126f64.mul_add(f64::from(r), 0.7152 * f64::from(g)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::opengl::render::renderstate::seeded_rng;
+
+    #[test]
+    fn splits_on_top_level_commas() {
+        assert_eq!(split_params("a=1,b=[2,3],c=x"), ["a=1", "b=[2,3]", "c=x"], "commas in brackets kept");
+        assert_eq!(split_params("a=[[1,2],[3]] , b=2"), ["a=[[1,2],[3]] ", " b=2"], "nested brackets");
+        assert_eq!(split_params(",a=1,,"), ["a=1"], "empty parts dropped");
+        assert!(split_params("").is_empty(), "nothing to split");
+    }
+
+    #[test]
+    fn parses_bare_names() {
+        assert_eq!("noise".parse::<InitialCondition>().expect("valid"), InitialCondition::Noise, "noise");
+        assert_eq!(
+            " galaxy ".parse::<InitialCondition>().expect("valid"),
+            InitialCondition::Galaxy(GalaxyParams::default()),
+            "defaults without parameters"
+        );
+    }
+
+    #[test]
+    fn parses_parameters() {
+        let condition: InitialCondition = "galaxy:center=[0.5, -0.5],radius=2,clockwise=true".parse().expect("valid");
+        assert_eq!(
+            condition,
+            InitialCondition::Galaxy(GalaxyParams {
+                center: [0.5, -0.5],
+                radius: 2.0,
+                clockwise: true,
+                ..GalaxyParams::default()
+            }),
+            "parameters override the defaults"
+        );
+
+        let condition: InitialCondition = "image:path=pics/a b.png,invert=true".parse().expect("valid");
+        let InitialCondition::Image(ref p) = condition else {
+            panic!("expected an image condition, got {:?}", condition);
+        };
+        assert_eq!(p.path, PathBuf::from("pics/a b.png"), "unquoted paths are strings");
+        assert!(p.invert, "invert parsed");
+    }
+
+    #[test]
+    fn rejects_bad_conditions() {
+        assert!("spiral".parse::<InitialCondition>().is_err(), "unknown name accepted");
+        assert!("galaxy:radius".parse::<InitialCondition>().is_err(), "missing `=` accepted");
+        assert!("galaxy:arms=2".parse::<InitialCondition>().is_err(), "unknown key accepted");
+        assert!("galaxy:radius=big".parse::<InitialCondition>().is_err(), "string for a number accepted");
+        assert!("image".parse::<InitialCondition>().is_err(), "image without a path accepted");
+    }
+
+    #[test]
+    fn disk_radius_stays_within_the_cutoff() {
+        for (scale_length, radius) in [(0.25, 1.0), (1.0, 1e-4), (1e3, 1.0), (1e-3, 1e3)] {
+            let mut last = 0.0;
+            for i in 0..=100 {
+                let u = i as f32 / 100.0;
+                let r = disk_radius(u, scale_length, radius);
+                assert!((0.0..=radius).contains(&r), "radius {} outside 0..={} for u = {}", r, radius, u);
+                assert!(r >= last, "radius shrank from {} to {} at u = {}", last, r, u);
+                last = r;
+            }
+            assert!(disk_radius(0.0, scale_length, radius) < radius * 1e-6, "u = 0 gives the center");
+        }
+        assert!(disk_radius(1.0, 1e3, 1.0) > 0.999_999, "u = 1 gives the cutoff when it's inside the disk");
+    }
+
+    #[test]
+    fn disk_radius_follows_the_density() {
+        // Without a meaningful cutoff the median of r e^(-r) is about 1.678.
+        let median = disk_radius(0.5, 1.0, 1e3);
+        assert!((median - 1.678).abs() < 1e-3, "median {}", median);
+    }
+
+    #[test]
+    fn galaxy_with_tiny_radius_finishes() {
+        let condition = InitialCondition::Galaxy(GalaxyParams {
+            scale_length: 1e6,
+            radius: 1e-3,
+            ..GalaxyParams::default()
+        });
+        let particles = Generator::new(condition).expect("valid").generate(&mut seeded_rng(1), 1000, &SimParams::default());
+        assert_eq!(particles.len(), 1000, "every particle placed");
+        assert!(particles.iter().all(|p| p.pos.mag() <= 1e-3), "particles placed beyond the radius");
+    }
+}
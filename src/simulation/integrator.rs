@@ -0,0 +1,209 @@
+use core::fmt::{self, Display};
+use core::str::FromStr;
+
+use anyhow::{bail, Error};
+use serde::Deserialize;
+
+use crate::vec2::Vector2;
+
+use super::ComputePass;
+
+/// How positions and velocities are advanced from the accelerations. Selected with `--integrator`,
+/// the same on both backends.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+pub enum Integrator {
+    /// Velocity first, then position from the new velocity. One force evaluation, first order.
+    #[default]
+    #[serde(rename = "euler")]
+    SymplecticEuler,
+    /// Position from the old acceleration, then velocity from the average of the old and new ones.
+    #[serde(rename = "verlet")]
+    VelocityVerlet,
+    /// Kick-drift-kick: half a velocity step, a full position step and another half velocity step.
+    #[serde(rename = "leapfrog")]
+    Leapfrog,
+    /// Classic fourth-order Runge-Kutta. Four force evaluations per step and not symplectic, so energy
+    /// slowly leaks over long runs even though each step is very accurate.
+    #[serde(rename = "rk4")]
+    Rk4,
+}
+
+impl Integrator {
+    pub const ALL: [Self; 4] = [Self::SymplecticEuler, Self::VelocityVerlet, Self::Leapfrog, Self::Rk4];
+
+    /// The `compute.glsl` dispatches that make up one step, in order.
+    ///
+    /// Integrators that [reuse forces](Self::reuses_forces) start from the `acc` left by the previous
+    /// step, the backend runs an extra [`ComputePass::Forces`] first when that's stale.
+    ///
+    /// [`ComputePass::Boundary`] comes right after the last position update, before the forces that the
+    /// next step reuses are evaluated.
+    pub const fn passes(self) -> &'static [ComputePass] {
+        use ComputePass::*;
+
+        match self {
+            Self::SymplecticEuler => &[Forces, Integrate, Boundary],
+            Self::VelocityVerlet => &[VerletDrift, Boundary, Forces, Kick],
+            Self::Leapfrog => &[Kick, Drift, Boundary, Forces, Kick],
+            Self::Rk4 => &[Forces, Rk4Stage1, Forces, Rk4Stage2, Forces, Rk4Stage3, Forces, Rk4Stage4, Boundary],
+        }
+    }
+
+    /// Whether a step ends with `acc` evaluated at the new positions, so the next step can start from it.
+    pub const fn reuses_forces(self) -> bool {
+        matches!(self, Self::VelocityVerlet | Self::Leapfrog)
+    }
+}
+
+impl FromStr for Integrator {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "euler" => Ok(Self::SymplecticEuler),
+            "verlet" => Ok(Self::VelocityVerlet),
+            "leapfrog" => Ok(Self::Leapfrog),
+            "rk4" => Ok(Self::Rk4),
+            _ => bail!("unknown integrator `{}`, expected `euler`, `verlet`, `leapfrog` or `rk4`", s),
+        }
+    }
+}
+
+impl Display for Integrator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::SymplecticEuler => write!(f, "euler"),
+            Self::VelocityVerlet => write!(f, "verlet"),
+            Self::Leapfrog => write!(f, "leapfrog"),
+            Self::Rk4 => write!(f, "rk4"),
+        }
+    }
+}
+
+/// Per-particle scratch space for [`Integrator::Rk4`], the `RkState` struct of `compute.glsl` (binding 2).
+#[derive(Debug, Clone, Default)]
+#[repr(C)]
+pub struct Rk4State {
+    /// Position at the start of the step.
+    pub pos0: Vector2,
+    /// Velocity at the start of the step.
+    pub vel0: Vector2,
+    /// Weighted sum of the stage velocities so far.
+    pub sum_pos: Vector2,
+    /// Weighted sum of the stage accelerations so far.
+    pub sum_vel: Vector2,
+}
+
+/// Weight of each RK4 stage in the final sum, the `1 2 2 1` of the classic tableau.
+pub const RK4_WEIGHTS: [f32; 4] = [1.0, 2.0, 2.0, 1.0];
+
+/// Fraction of the step at which the next stage is evaluated, after stages 1, 2 and 3.
+pub const RK4_OFFSETS: [f32; 3] = [0.5, 0.5, 1.0];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::opengl::render::particle::Particle;
+    use crate::simulation::cpu::{compute_forces, step_particles, Stepper};
+    use crate::simulation::diagnostics::{kinetic_energy, potential_energy};
+    use crate::simulation::{ForceModel, SimParams};
+
+    fn params(integrator: Integrator) -> SimParams {
+        SimParams {
+            force_model: ForceModel::NBody,
+            g: 1.0,
+            softening: 0.0,
+            integrator,
+            ..SimParams::default()
+        }
+    }
+
+    /// Two unit masses a distance 1 apart on a circular orbit around their common center.
+    fn two_body() -> Vec<Particle> {
+        let speed = 0.5f32.sqrt();
+        [1.0f32, -1.0]
+            .into_iter()
+            .map(|side| Particle {
+                pos: Vector2::new(0.5 * side, 0.0),
+                vel: Vector2::new(0.0, speed * side),
+                ..Particle::default()
+            })
+            .collect()
+    }
+
+    fn energy(particles: &[Particle], params: &SimParams) -> f64 {
+        kinetic_energy(particles) + potential_energy(particles, params, &Vector2::default())
+    }
+
+    #[test]
+    fn two_body_energy_drift_is_bounded() {
+        // About four orbits.
+        let (dt, steps) = (0.01, 2000);
+
+        for integrator in Integrator::ALL {
+            let tolerance = match integrator {
+                Integrator::SymplecticEuler => 1e-2,
+                Integrator::VelocityVerlet | Integrator::Leapfrog => 1e-3,
+                Integrator::Rk4 => 1e-5,
+            };
+            let params = params(integrator);
+            let mut particles = two_body();
+            let mut stepper = Stepper::default();
+            let start = energy(&particles, &params);
+            assert!((start + 0.5).abs() < 1e-6, "the orbit should have energy -1/2, got {}", start);
+
+            let mut max_drift = 0.0f64;
+            for _ in 0..steps {
+                step_particles(&mut particles, &params, &Vector2::default(), None, dt, &mut stepper);
+                max_drift = max_drift.max(((energy(&particles, &params) - start) / start).abs());
+            }
+
+            assert!(max_drift < tolerance, "{} drifted by {:e}, more than {:e}", integrator, max_drift, tolerance);
+        }
+    }
+
+    #[test]
+    fn reused_forces_match_the_new_positions() {
+        let mouse_pos = Vector2::default();
+        for integrator in Integrator::ALL {
+            let params = params(integrator);
+            let mut particles = two_body();
+            let mut stepper = Stepper::default();
+            step_particles(&mut particles, &params, &mouse_pos, None, 0.01, &mut stepper);
+            assert_eq!(stepper.forces_valid, integrator.reuses_forces(), "{} left forces_valid wrong", integrator);
+
+            if integrator.reuses_forces() {
+                let mut fresh = particles.clone();
+                compute_forces(&mut fresh, &params, &mouse_pos, None);
+                for (p, q) in particles.iter().zip(&fresh) {
+                    assert_eq!((p.acc.x, p.acc.y), (q.acc.x, q.acc.y), "{} left stale forces", integrator);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn passes_evaluate_forces_where_needed() {
+        for integrator in Integrator::ALL {
+            let passes = integrator.passes();
+            let last_forces = passes.iter().rposition(|&pass| pass == ComputePass::Forces);
+            let last_move = passes.iter().rposition(|&pass| {
+                matches!(
+                    pass,
+                    ComputePass::Integrate | ComputePass::Drift | ComputePass::VerletDrift | ComputePass::Rk4Stage4 | ComputePass::Boundary
+                )
+            });
+
+            // Reusing forces means the last evaluation comes after every position update, otherwise the
+            // step has to start with a fresh one.
+            let forces_after_move = last_forces > last_move;
+            assert_eq!(forces_after_move, integrator.reuses_forces(), "{} evaluates forces too early", integrator);
+            assert_eq!(
+                passes.first() == Some(&ComputePass::Forces),
+                !integrator.reuses_forces(),
+                "{} should start from fresh forces exactly when it doesn't reuse them",
+                integrator
+            );
+        }
+    }
+}
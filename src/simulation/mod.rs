@@ -0,0 +1,173 @@
+pub mod barnes_hut;
+pub mod bench;
+pub mod boundary;
+pub mod cpu;
+pub mod diagnostics;
+pub mod drift;
+pub mod emitter;
+pub mod gpu;
+pub mod initial;
+pub mod integrator;
+pub mod time;
+pub mod tools;
+
+use core::fmt::{self, Display};
+use core::str::FromStr;
+
+use anyhow::{bail, Error, Result};
+use serde::Deserialize;
+
+use crate::opengl::{program::Program, render::renderstate::RenderState, uniform::UniformLocations};
+
+use self::boundary::Boundary;
+use self::integrator::Integrator;
+
+pub const DEFAULT_G: f32 = 6.67430e-11;
+pub const DEFAULT_SOFTENING: f32 = 0.001;
+pub const DEFAULT_THETA: f32 = 0.5;
+
+/// Physical parameters shared by every backend. The GPU gets them as uniforms each frame.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SimParams {
+    pub force_model: ForceModel,
+    /// Gravitational constant (`uG`).
+    pub g: f32,
+    /// Softening length (`uSoftening`), keeps close encounters from blowing up.
+    pub softening: f32,
+    /// Barnes-Hut opening angle (`uTheta`). 0 opens every node and matches [`ForceModel::NBody`].
+    pub theta: f32,
+    pub integrator: Integrator,
+    /// Domain edges (`uBoundary`, `uDomainMin`, ...).
+    pub boundary: Boundary,
+}
+
+impl Default for SimParams {
+    fn default() -> Self {
+        Self {
+            force_model: ForceModel::default(),
+            g: DEFAULT_G,
+            softening: DEFAULT_SOFTENING,
+            theta: DEFAULT_THETA,
+            integrator: Integrator::default(),
+            boundary: Boundary::default(),
+        }
+    }
+}
+
+/// What pulls on the particles. Discriminants match the `FORCE_*` constants in `compute.glsl`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ForceModel {
+    /// Every particle is attracted towards the mouse cursor.
+    #[default]
+    Mouse = 0,
+    /// Every particle attracts every other one, O(N²).
+    NBody = 1,
+    /// Gravity approximated with a quadtree built on the CPU every step, O(N log N).
+    #[serde(rename = "barnes-hut")]
+    BarnesHut = 2,
+}
+
+impl FromStr for ForceModel {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "mouse" => Ok(Self::Mouse),
+            "nbody" => Ok(Self::NBody),
+            "barnes-hut" => Ok(Self::BarnesHut),
+            _ => bail!("unknown force model `{}`, expected `mouse`, `nbody` or `barnes-hut`", s),
+        }
+    }
+}
+
+impl Display for ForceModel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::Mouse => write!(f, "mouse"),
+            Self::NBody => write!(f, "nbody"),
+            Self::BarnesHut => write!(f, "barnes-hut"),
+        }
+    }
+}
+
+/// The dispatches that make up one step of `compute.glsl`, selected through `uPass`.
+/// Discriminants match the `PASS_*` constants in the shader.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ComputePass {
+    /// Writes `acc` from the current positions.
+    Forces = 0,
+    /// Advances `vel` and then `pos` from `acc`, the whole [`Integrator::SymplecticEuler`] step.
+    Integrate = 1,
+    /// Advances `vel` by half a step of `acc`.
+    Kick = 2,
+    /// Advances `pos` by a full step of `vel`.
+    Drift = 3,
+    /// The first half of an [`Integrator::VelocityVerlet`] step: `pos` from `vel` and `acc`, and the
+    /// old `acc`'s half of the velocity update.
+    VerletDrift = 4,
+    /// [`Integrator::Rk4`] stages, each after a forces pass evaluated at the previous stage's state.
+    Rk4Stage1 = 5,
+    Rk4Stage2 = 6,
+    Rk4Stage3 = 7,
+    Rk4Stage4 = 8,
+    /// Bounces, wraps or respawns particles that left the domain. Skipped unless the boundary kind needs it.
+    Boundary = 9,
+    /// Ages the live particles and pushes the ones that outlived their lifetime onto the free list. Runs
+    /// before the integrator's passes, only while there are emitters.
+    Lifetime = 10,
+    /// Fills free slots with this step's [`SpawnBatch`](emitter::SpawnBatch)es, one invocation per spawn.
+    Spawn = 11,
+    /// Pops the slots the spawn pass took off the free list, a single invocation.
+    SpawnCommit = 12,
+}
+
+/// A way of advancing the particle set by one integration step.
+///
+/// Every backend must leave the SSBO owned by `render_state` holding the
+/// post-step particles, so the draw pass doesn't care which one ran.
+pub trait SimulationBackend {
+    fn kind(&self) -> BackendKind;
+
+    /// Advances the simulation by `dt` seconds.
+    ///
+    /// Per-frame uniforms (`uDt`, `uMousePos`, `uG`, ...) must already be set on `compute_program`.
+    fn step(&mut self, render_state: &mut RenderState, compute_program: &Program, compute_uniforms: &UniformLocations, dt: f32) -> Result<()>;
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BackendKind {
+    Cpu,
+    #[default]
+    Gpu,
+}
+
+impl BackendKind {
+    pub fn create(self) -> Box<dyn SimulationBackend> {
+        match self {
+            Self::Cpu => Box::new(cpu::CpuBackend::default()),
+            Self::Gpu => Box::new(gpu::GpuBackend::new()),
+        }
+    }
+}
+
+impl FromStr for BackendKind {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "cpu" => Ok(Self::Cpu),
+            "gpu" => Ok(Self::Gpu),
+            _ => bail!("unknown simulation backend `{}`, expected `cpu` or `gpu`", s),
+        }
+    }
+}
+
+impl Display for BackendKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::Cpu => write!(f, "cpu"),
+            Self::Gpu => write!(f, "gpu"),
+        }
+    }
+}
@@ -0,0 +1,255 @@
+/// Longest wall-clock step taken in one frame, so that a stall (dragging the window, sitting on a
+/// breakpoint) doesn't turn into one huge integration step.
+pub const DEFAULT_MAX_DT: f32 = 0.05;
+
+pub const MIN_TIME_SCALE: f32 = 0.1;
+pub const MAX_TIME_SCALE: f32 = 10.0;
+
+/// Factor the speed keys multiply or divide the time scale by.
+pub const TIME_SCALE_STEP: f32 = 1.25;
+
+/// Frames advanced by the "step many" key.
+pub const DEFAULT_STEP_FRAMES: u32 = 10;
+
+/// Most substeps run in one frame with `substep_dt`. Time beyond that is dropped rather than carried
+/// over, so a slow frame can't snowball into ever more substeps.
+pub const DEFAULT_MAX_SUBSTEPS: u32 = 8;
+
+/// Decides how far the simulation advances each frame: pausing, stepping, time scale and the dt clamp.
+#[derive(Debug, Clone)]
+pub struct TimeControl {
+    /// Constant step used instead of wall-clock deltas, for reproducible runs.
+    pub fixed_dt: Option<f32>,
+    /// Upper bound for wall-clock steps, see [`DEFAULT_MAX_DT`]. Doesn't apply to `fixed_dt`.
+    pub max_dt: f32,
+    /// Simulated seconds per real second (or per `fixed_dt`).
+    scale: f32,
+    pub paused: bool,
+    /// Frames left to advance while paused.
+    pub pending_steps: u32,
+    /// Frames advanced by [`Self::step`] when stepping many at once.
+    pub step_frames: u32,
+    /// Integrate in substeps of exactly this many simulated seconds, carrying leftover time to the next frame.
+    pub substep_dt: Option<f32>,
+    /// Cap on substeps per frame, see [`DEFAULT_MAX_SUBSTEPS`].
+    pub max_substeps: u32,
+    /// Draw particles between the last two substeps by the leftover fraction, see [`Self::alpha`].
+    pub interpolate: bool,
+    /// Simulated time not yet covered by a substep.
+    accumulator: f32,
+}
+
+impl Default for TimeControl {
+    fn default() -> Self {
+        Self {
+            fixed_dt: None,
+            max_dt: DEFAULT_MAX_DT,
+            scale: 1.0,
+            paused: false,
+            pending_steps: 0,
+            step_frames: DEFAULT_STEP_FRAMES,
+            substep_dt: None,
+            max_substeps: DEFAULT_MAX_SUBSTEPS,
+            interpolate: false,
+            accumulator: 0.0,
+        }
+    }
+}
+
+impl TimeControl {
+    pub const fn scale(&self) -> f32 {
+        self.scale
+    }
+
+    /// Sets the time scale, clamped to [`MIN_TIME_SCALE`]..=[`MAX_TIME_SCALE`].
+    pub const fn set_scale(&mut self, scale: f32) {
+        self.scale = scale.clamp(MIN_TIME_SCALE, MAX_TIME_SCALE);
+    }
+
+    pub const fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+        self.pending_steps = 0;
+    }
+
+    /// Pauses and queues `frames` steps.
+    pub const fn step(&mut self, frames: u32) {
+        self.paused = true;
+        self.pending_steps = self.pending_steps.saturating_add(frames);
+    }
+
+    /// How far to advance this frame given the wall-clock time since the last one, or `None` while
+    /// paused with no steps queued.
+    pub fn frame_dt(&mut self, wall_dt: f32) -> Option<f32> {
+        if self.paused {
+            if self.pending_steps == 0 {
+                return None;
+            }
+            self.pending_steps -= 1;
+        }
+
+        let dt = self.fixed_dt.unwrap_or_else(|| wall_dt.min(self.max_dt));
+        Some(dt * self.scale)
+    }
+
+    /// Splits a frame's worth of simulated time into substeps, returning their count and length.
+    ///
+    /// Without `substep_dt` that's always one step of `frame_dt`. Otherwise it's however many whole
+    /// substeps fit in the accumulated time, possibly zero, at most `max_substeps`. A frame stepped while
+    /// paused always runs at least one substep, so that stepping visibly advances.
+    pub fn substeps(&mut self, frame_dt: f32) -> (u32, f32) {
+        let Some(h) = self.substep_dt else {
+            return (1, frame_dt);
+        };
+
+        self.accumulator += frame_dt;
+        // The epsilon keeps `frame_dt == h` from landing a hair short because of rounding.
+        let whole = (self.accumulator / h + 1e-4).floor() as u32;
+        let count = whole.min(self.max_substeps).max(u32::from(self.paused));
+
+        if whole > count {
+            self.accumulator = 0.0;
+        } else {
+            self.accumulator = (count as f32).mul_add(-h, self.accumulator).max(0.0);
+        }
+
+        (count, h)
+    }
+
+    /// How far between the previous and the current substep the accumulator is, 0 to 1.
+    ///
+    /// Always 1 (draw the current state) unless `interpolate` is on.
+    pub fn alpha(&self) -> f32 {
+        match self.substep_dt {
+            Some(h) if self.interpolate => (self.accumulator / h).clamp(0.0, 1.0),
+            _ => 1.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn runs_at_wall_clock_by_default() {
+        let mut time = TimeControl::default();
+        assert_eq!(time.frame_dt(0.01), Some(0.01), "unscaled wall-clock step");
+        assert_eq!(time.frame_dt(1.0), Some(DEFAULT_MAX_DT), "long frames are clamped to max_dt");
+    }
+
+    #[test]
+    fn fixed_dt_ignores_max_dt() {
+        let mut time = TimeControl {
+            fixed_dt: Some(0.1),
+            ..TimeControl::default()
+        };
+        assert_eq!(time.frame_dt(0.001), Some(0.1), "fixed_dt replaces the wall clock");
+        assert_eq!(time.frame_dt(5.0), Some(0.1), "fixed_dt isn't clamped");
+    }
+
+    #[test]
+    fn pause_stops_time() {
+        let mut time = TimeControl::default();
+        time.toggle_pause();
+        assert_eq!(time.frame_dt(0.01), None, "paused frames don't advance");
+        time.toggle_pause();
+        assert_eq!(time.frame_dt(0.01), Some(0.01), "unpausing resumes");
+    }
+
+    #[test]
+    fn step_advances_queued_frames() {
+        let mut time = TimeControl::default();
+        time.step(2);
+        assert!(time.paused, "stepping pauses");
+        assert_eq!(time.frame_dt(0.01), Some(0.01), "first queued step");
+        assert_eq!(time.frame_dt(0.01), Some(0.01), "second queued step");
+        assert_eq!(time.frame_dt(0.01), None, "no steps left");
+
+        time.step(3);
+        time.toggle_pause();
+        time.toggle_pause();
+        assert_eq!(time.frame_dt(0.01), None, "toggling pause drops queued steps");
+    }
+
+    #[test]
+    fn scale_is_clamped() {
+        let mut time = TimeControl::default();
+        time.set_scale(2.0);
+        assert_eq!(time.frame_dt(0.01), Some(0.02), "scale multiplies the step");
+
+        time.set_scale(1000.0);
+        assert_eq!(time.scale(), MAX_TIME_SCALE, "scale clamped from above");
+        time.set_scale(0.0);
+        assert_eq!(time.scale(), MIN_TIME_SCALE, "scale clamped from below");
+    }
+
+    fn substepped(h: f32) -> TimeControl {
+        TimeControl {
+            substep_dt: Some(h),
+            ..TimeControl::default()
+        }
+    }
+
+    #[test]
+    fn one_substep_without_substep_dt() {
+        let mut time = TimeControl::default();
+        assert_eq!(time.substeps(0.3), (1, 0.3), "the whole frame in one step");
+        assert_eq!(time.alpha(), 1.0, "nothing to interpolate");
+    }
+
+    #[test]
+    fn accumulator_carries_leftover_time() {
+        let mut time = substepped(0.01);
+        assert_eq!(time.substeps(0.025), (2, 0.01), "two whole substeps fit");
+        assert_eq!(time.substeps(0.004), (0, 0.01), "0.009 left, not a whole substep");
+        assert_eq!(time.substeps(0.001), (1, 0.01), "the leftover adds up to one");
+    }
+
+    #[test]
+    fn exact_multiples_are_not_lost_to_rounding() {
+        let mut time = substepped(0.1);
+        for _ in 0..100 {
+            assert_eq!(time.substeps(0.1).0, 1, "a frame of exactly one substep");
+        }
+    }
+
+    #[test]
+    fn substeps_are_capped() {
+        let mut time = TimeControl {
+            max_substeps: 4,
+            ..substepped(0.01)
+        };
+        assert_eq!(time.substeps(0.1).0, 4, "capped at max_substeps");
+        assert_eq!(time.substeps(0.005).0, 0, "the time beyond the cap was dropped");
+    }
+
+    #[test]
+    fn paused_step_runs_a_substep() {
+        let mut time = substepped(0.05);
+        time.step(1);
+        let dt = time.frame_dt(0.01).expect("a queued step");
+        assert_eq!(time.substeps(dt).0, 1, "stepping always advances");
+        assert_eq!(time.alpha(), 1.0, "interpolation is off");
+
+        time.interpolate = true;
+        time.step(1);
+        let dt = time.frame_dt(0.01).expect("a queued step");
+        time.substeps(dt);
+        assert!(time.alpha() < 0.25, "the forced substep consumed the accumulated time");
+    }
+
+    #[test]
+    fn alpha_is_the_leftover_fraction() {
+        let mut time = TimeControl {
+            interpolate: true,
+            ..substepped(0.01)
+        };
+        time.substeps(0.0125);
+        assert!((time.alpha() - 0.25).abs() < 1e-3, "a quarter substep left over, got {}", time.alpha());
+        time.substeps(0.005);
+        assert!((time.alpha() - 0.75).abs() < 1e-3, "three quarters left over, got {}", time.alpha());
+
+        time.interpolate = false;
+        assert_eq!(time.alpha(), 1.0, "without interpolation the current state is drawn");
+    }
+}
@@ -0,0 +1,160 @@
+use core::fmt::{self, Display};
+
+use crate::opengl::render::particle::Particle;
+use crate::vec2::Vector2;
+
+/// Multiplier per scroll wheel notch when adjusting a tool's strength or radius.
+pub const TOOL_SCROLL_STEP: f32 = 1.1;
+
+/// What holding the left mouse button does. Discriminants match the `TOOL_*` constants in `compute.glsl`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ToolKind {
+    /// Pulls particles towards the cursor.
+    #[default]
+    Attract = 0,
+    /// Pushes particles away from the cursor.
+    Repel = 1,
+    /// Swirls particles counter-clockwise around the cursor.
+    Vortex = 2,
+    /// Pulls particle velocities towards the cursor's velocity: slows them down when the cursor is
+    /// still and sweeps them along when it moves.
+    Drag = 3,
+    /// Adds particles around the cursor, `strength` per second.
+    Spawn = 4,
+}
+
+impl ToolKind {
+    pub const ALL: [Self; 5] = [Self::Attract, Self::Repel, Self::Vortex, Self::Drag, Self::Spawn];
+}
+
+impl Display for ToolKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::Attract => write!(f, "attract"),
+            Self::Repel => write!(f, "repel"),
+            Self::Vortex => write!(f, "vortex"),
+            Self::Drag => write!(f, "drag"),
+            Self::Spawn => write!(f, "spawn"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ToolSettings {
+    /// Peak acceleration at the cursor, or particles per second for [`ToolKind::Spawn`].
+    pub strength: f32,
+    /// World-space radius the tool reaches, its effect fades out linearly towards the edge.
+    pub radius: f32,
+}
+
+/// The selected tool and the settings of every tool, so switching back and forth keeps them.
+#[derive(Debug, Clone)]
+pub struct Tools {
+    pub selected: ToolKind,
+    /// Indexed by `ToolKind as usize`.
+    pub settings: [ToolSettings; ToolKind::ALL.len()],
+    /// Whether the mouse button is held.
+    pub engaged: bool,
+    /// Fractional particles owed by the spawn tool, carried over between frames.
+    pub spawn_carry: f32,
+}
+
+impl Default for Tools {
+    fn default() -> Self {
+        Self {
+            selected: ToolKind::default(),
+            settings: [
+                ToolSettings { strength: 2.0, radius: 0.5 },
+                ToolSettings { strength: 2.0, radius: 0.5 },
+                ToolSettings { strength: 2.0, radius: 0.5 },
+                ToolSettings { strength: 5.0, radius: 0.3 },
+                ToolSettings { strength: 200.0, radius: 0.1 },
+            ],
+            engaged: false,
+            spawn_carry: 0.0,
+        }
+    }
+}
+
+impl Tools {
+    pub const fn current(&self) -> ToolSettings {
+        self.settings[self.selected as usize]
+    }
+
+    pub const fn current_mut(&mut self) -> &mut ToolSettings {
+        &mut self.settings[self.selected as usize]
+    }
+
+    /// The force to apply this frame, `None` unless a force tool is held down.
+    pub fn force(&self, pos: (f32, f32), velocity: (f32, f32)) -> Option<ToolForce> {
+        if !self.engaged || self.selected == ToolKind::Spawn {
+            return None;
+        }
+
+        let settings = self.current();
+        Some(ToolForce {
+            kind: self.selected,
+            pos: Vector2::new(pos.0, pos.1),
+            velocity: Vector2::new(velocity.0, velocity.1),
+            strength: settings.strength,
+            radius: settings.radius,
+        })
+    }
+
+    /// How many particles the spawn tool adds over `dt` seconds, 0 unless it is held down.
+    pub fn spawn_count(&mut self, dt: f32) -> u32 {
+        if !self.engaged || self.selected != ToolKind::Spawn {
+            self.spawn_carry = 0.0;
+            return 0;
+        }
+
+        self.spawn_carry += self.current().strength * dt;
+        let count = self.spawn_carry.floor();
+        self.spawn_carry -= count;
+        count as u32
+    }
+}
+
+/// A force tool as applied to the particles for one step. Sent to `compute.glsl` as the `uTool*` uniforms.
+#[derive(Debug, Clone)]
+pub struct ToolForce {
+    pub kind: ToolKind,
+    /// Cursor position in world space.
+    pub pos: Vector2,
+    /// Cursor velocity in world units per second.
+    pub velocity: Vector2,
+    pub strength: f32,
+    pub radius: f32,
+}
+
+/// Acceleration `tool` adds to `p`, the same as `toolAcceleration` in `compute.glsl`.
+pub fn tool_acceleration(p: &Particle, tool: &ToolForce) -> Vector2 {
+    let mut dir = p.pos.clone();
+    dir.to(&tool.pos);
+    let dist = dir.mag();
+    if dist >= tool.radius {
+        return Vector2::default();
+    }
+
+    let falloff = tool.strength * (1.0 - dist / tool.radius);
+    if dist > 0.0 {
+        dir.scale(1.0 / dist);
+    }
+
+    match tool.kind {
+        ToolKind::Attract => dir.scale(falloff),
+        ToolKind::Repel => dir.scale(-falloff),
+        ToolKind::Vortex => {
+            dir.set(-dir.y, dir.x);
+            dir.scale(falloff);
+        }
+        ToolKind::Drag => {
+            dir.set_vec(&p.vel);
+            dir.to(&tool.velocity);
+            dir.scale(falloff);
+        }
+        ToolKind::Spawn => return Vector2::default(),
+    }
+
+    dir
+}